@@ -0,0 +1,284 @@
+use crate::database::{self, RomDatabase, RomInfo};
+use crate::font::{self, CELL_HEIGHT, CELL_WIDTH};
+use crate::palette::Palette;
+use crate::renderer::Frame;
+use crate::rom::{self, RomFormat};
+use minifb::Key;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Startup screen listing the roms in a directory with what the rom database
+// knows about them. It draws into its own frame, twice the resolution of the
+// CHIP-8 display so a useful amount of text fits.
+
+pub const DEFAULT_DIRECTORY: &str = "data";
+
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+const COLUMNS: usize = WIDTH / CELL_WIDTH;
+// a title line, the list and three lines of details
+const LIST_ROWS: usize = HEIGHT / CELL_HEIGHT - 4;
+
+#[derive(Debug, Clone)]
+pub struct RomEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub info: Option<RomInfo>,
+}
+
+// the files in `directory` that look like roms, sorted by name
+pub fn scan<P: AsRef<Path>>(directory: P, database: Option<&RomDatabase>) -> io::Result<Vec<RomEntry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        if name.starts_with('.') || !path.is_file() {
+            continue;
+        }
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        if RomFormat::detect(&data) == RomFormat::Raw && rom::validate(&data, crate::cpu::PROGRAM_START).is_err() {
+            continue;
+        }
+        let info = database.and_then(|database| database.get(&database::sha1_hex(&data))).cloned();
+        entries.push(RomEntry { path, name, info });
+    }
+    entries.sort_by_key(|entry| entry.name.to_lowercase());
+    Ok(entries)
+}
+
+pub struct RomBrowser {
+    directory: String,
+    entries: Vec<RomEntry>,
+    selected: usize,
+    // the first entry shown in the list
+    scroll: usize,
+    pixels: Vec<u32>,
+}
+
+impl RomBrowser {
+    pub fn new(directory: &str, entries: Vec<RomEntry>) -> RomBrowser {
+        RomBrowser {
+            directory: String::from(directory),
+            entries,
+            selected: 0,
+            scroll: 0,
+            pixels: vec![0; WIDTH * HEIGHT],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn selected(&self) -> Option<&RomEntry> {
+        self.entries.get(self.selected)
+    }
+
+    // selects the entry of `path`, if it is listed
+    pub fn select_path(&mut self, path: &Path) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.path == path) {
+            self.select(index);
+        }
+    }
+
+    // moves the selection, returns the entry to launch when Enter is pressed
+    pub fn handle_key(&mut self, key: Key) -> Option<&RomEntry> {
+        let last = self.entries.len().saturating_sub(1);
+        match key {
+            Key::Up => self.select(self.selected.saturating_sub(1)),
+            Key::Down => self.select((self.selected + 1).min(last)),
+            Key::PageUp | Key::Left => self.select(self.selected.saturating_sub(LIST_ROWS)),
+            Key::PageDown | Key::Right => self.select((self.selected + LIST_ROWS).min(last)),
+            Key::Home => self.select(0),
+            Key::End => self.select(last),
+            Key::Enter | Key::Space => return self.selected(),
+            _ => {},
+        }
+        None
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + LIST_ROWS {
+            self.scroll = self.selected + 1 - LIST_ROWS;
+        }
+    }
+
+    // the browser in the palette's background and fill colors
+    pub fn frame(&mut self, palette: &Palette) -> Frame<'_> {
+        let (background, foreground) = (palette.color(0), palette.color(1));
+        self.pixels.fill(background);
+
+        let title = format!("roms in {}/", self.directory.trim_end_matches('/'));
+        self.draw_line(0, &title, foreground, background);
+        if self.entries.is_empty() {
+            self.draw_line(2, "no roms found", background, foreground);
+        }
+
+        let visible = self.entries.iter().enumerate().skip(self.scroll).take(LIST_ROWS);
+        let lines: Vec<(String, bool)> = visible
+            .map(|(index, entry)| {
+                let name = entry.info.as_ref().map(|info| info.title.as_str()).unwrap_or(&entry.name);
+                (String::from(name), index == self.selected)
+            })
+            .collect();
+        for (row, (name, selected)) in lines.iter().enumerate() {
+            let line = format!("{}{}", if *selected { '>' } else { ' ' }, name);
+            if *selected {
+                self.draw_line(row + 1, &line, foreground, background);
+            } else {
+                self.draw_line(row + 1, &line, background, foreground);
+            }
+        }
+
+        for (row, line) in self.details().iter().enumerate() {
+            self.draw_line(LIST_ROWS + 1 + row, line, background, foreground);
+        }
+
+        Frame { width: WIDTH, height: HEIGHT, pixels: &self.pixels }
+    }
+
+    // what is known about the selected rom, three lines
+    fn details(&self) -> Vec<String> {
+        let entry = match self.selected() {
+            Some(entry) => entry,
+            None => return Vec::new(),
+        };
+        let info = match entry.info.as_ref() {
+            Some(info) => info,
+            None => return vec![entry.name.clone(), String::from("not in the rom database")],
+        };
+
+        let mut credits = info.authors.join(", ");
+        if let Some(release) = info.release.as_deref() {
+            credits = if credits.is_empty() { String::from(release) } else { format!("{} {}", credits, release) };
+        }
+        let mut platform = String::from(info.platform.as_deref().unwrap_or("unknown platform"));
+        if let Some(tickrate) = info.tickrate {
+            platform.push_str(&format!(" {} IPF", tickrate));
+        }
+        vec![entry.name.clone(), credits, platform]
+    }
+
+    // text on a `background` colored line, `row` counts lines from the top
+    fn draw_line(&mut self, row: usize, text: &str, background: u32, foreground: u32) {
+        let top = row * CELL_HEIGHT;
+        if top + CELL_HEIGHT > HEIGHT {
+            return;
+        }
+        self.pixels[top * WIDTH..(top + CELL_HEIGHT) * WIDTH].fill(background);
+        let text: String = text.chars().take(COLUMNS).collect();
+        font::draw_text(&mut self.pixels, WIDTH, 1, top + 1, &text, foreground);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn browser(count: usize) -> RomBrowser {
+        let entries = (0..count)
+            .map(|index| {
+                let path = PathBuf::from(format!("{}.ch8", index));
+                RomEntry { path, name: index.to_string(), info: None }
+            })
+            .collect();
+        RomBrowser::new("roms", entries)
+    }
+
+    fn selected(browser: &RomBrowser) -> usize {
+        let index = browser.selected().unwrap().name.parse().unwrap();
+        assert!(browser.scroll <= index && index < browser.scroll + LIST_ROWS);
+        index
+    }
+
+    #[test]
+    fn scans_list_valid_roms_by_name_in_any_case() {
+        let directory = std::env::temp_dir().join(format!("chip8-browser-test-{}", std::process::id()));
+        fs::create_dir_all(directory.join("subdirectory")).unwrap();
+        for (name, data) in [
+            ("b.ch8", vec![0x12, 0x00]),
+            ("A.ch8", vec![0x12, 0x02]),
+            ("c.ch8", vec![0x12, 0x04]),
+            (".hidden.ch8", vec![0x12, 0x00]),
+            ("empty.ch8", Vec::new()),
+            ("huge.ch8", vec![0; 4000]),
+        ] {
+            fs::write(directory.join(name), data).unwrap();
+        }
+        let mut database = RomDatabase::new();
+        let json = format!(r#"[{{"title": "Bee", "roms": {{"{}": {{}}}}}}]"#, database::sha1_hex(&[0x12, 0x00]));
+        database.merge_json(&json).unwrap();
+
+        let entries = scan(&directory, Some(&database));
+        let without_database = scan(&directory, None);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let entries = entries.unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["A.ch8", "b.ch8", "c.ch8"]);
+        assert_eq!(entries[1].path, directory.join("b.ch8"));
+        assert_eq!(entries[1].info.as_ref().map(|info| info.title.as_str()), Some("Bee"));
+        assert!(entries[0].info.is_none());
+        assert!(without_database.unwrap().iter().all(|entry| entry.info.is_none()));
+        assert!(scan(&directory, None).is_err());
+    }
+
+    #[test]
+    fn keys_move_the_selection_and_scroll_the_list() {
+        let count = LIST_ROWS * 2 + 3;
+        let mut browser = browser(count);
+        browser.handle_key(Key::Up);
+        assert_eq!(selected(&browser), 0);
+        browser.handle_key(Key::Down);
+        assert_eq!(selected(&browser), 1);
+
+        browser.handle_key(Key::PageDown);
+        assert_eq!(selected(&browser), LIST_ROWS + 1);
+        assert_eq!(browser.scroll, 2);
+        browser.handle_key(Key::PageDown);
+        browser.handle_key(Key::PageDown);
+        assert_eq!(selected(&browser), count - 1);
+        browser.handle_key(Key::Down);
+        assert_eq!(selected(&browser), count - 1);
+        assert_eq!(browser.scroll, count - LIST_ROWS);
+
+        browser.handle_key(Key::PageUp);
+        assert_eq!(selected(&browser), count - 1 - LIST_ROWS);
+        browser.handle_key(Key::Home);
+        assert_eq!((selected(&browser), browser.scroll), (0, 0));
+        browser.handle_key(Key::End);
+        assert_eq!(selected(&browser), count - 1);
+
+        browser.select_path(Path::new("3.ch8"));
+        assert_eq!(selected(&browser), 3);
+        browser.select_path(Path::new("missing.ch8"));
+        assert_eq!(selected(&browser), 3);
+        assert_eq!(browser.handle_key(Key::Enter).map(|entry| entry.name.as_str()), Some("3"));
+        assert!(browser.handle_key(Key::A).is_none());
+    }
+
+    #[test]
+    fn empty_lists_have_nothing_to_select() {
+        let mut browser = browser(0);
+        assert!(browser.is_empty());
+        for key in [Key::Down, Key::PageDown, Key::End, Key::Up, Key::PageUp, Key::Home] {
+            browser.handle_key(key);
+        }
+        assert!(browser.handle_key(Key::Enter).is_none());
+        assert!(browser.selected().is_none());
+        let palette = Palette::default();
+        let frame = browser.frame(&palette);
+        assert_eq!(frame.pixels.len(), WIDTH * HEIGHT);
+        assert!(frame.pixels.contains(&palette.color(1)));
+    }
+}
@@ -1,15 +1,14 @@
 use crate::keyboard::Keyboard;
-use crate::display::Display;
+use crate::display::{Display, DirtyRegion};
 use crate::ram::Ram;
 use std::fmt;
-use std::time;
 
 pub struct Bus {
     ram: Ram,
     keyboard: Keyboard,
     display: Display,
     delay_timer: u8,
-    delay_timer_set_time: time::Instant,
+    sound_timer: u8,
 }
 
 impl Bus {
@@ -20,7 +19,7 @@ impl Bus {
             keyboard: Keyboard::new(),
             display: Display::new(),
             delay_timer: 0,
-            delay_timer_set_time: time::Instant::now(),
+            sound_timer: 0,
         }
     }
 
@@ -32,16 +31,20 @@ impl Bus {
         self.ram.write_byte(address, value)
     }
 
-    pub fn debug_draw_byte(&mut self, byte: u8, x: u8, y: u8) -> bool {
-        self.display.debug_draw_byte(byte, x, y)
+    pub fn draw_sprite(&mut self, sprite: &[u8], x: u8, y: u8, clip: bool) -> bool {
+        self.display.draw_sprite(sprite, x, y, clip)
     }
 
     pub fn clear_screen(&mut self) {
         self.display.clear();
     }
 
-    pub fn set_key_pressed(&mut self, key: Option<u8>) {
-        self.keyboard.set_key_pressed(key);
+    pub fn set_keys_down(&mut self, keys_down: u16) {
+        self.keyboard.set_keys_down(keys_down);
+    }
+
+    pub fn get_keys_down(&self) -> u16 {
+        self.keyboard.get_keys_down()
     }
 
     pub fn is_key_pressed(&self, key_code: u8) -> bool {
@@ -53,34 +56,52 @@ impl Bus {
     }
 
     pub fn set_delay_timer(&mut self, value: u8) {
-        self.delay_timer_set_time = time::Instant::now();
+        crate::log!(Timers, Debug, "delay timer set to {}", value);
         self.delay_timer = value;
     }
 
     pub fn get_delay_timer(&self) -> u8 {
-        let diff = time::Instant::now() - self.delay_timer_set_time;
-        let ms = diff.as_millis() as u64;
-        let ticks = ms / 16;
-
-        if ticks >= self.delay_timer as u64 {
-            0
-        } else {
-            self.delay_timer - ticks as u8
-        }
+        self.delay_timer
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        crate::log!(Timers, Debug, "sound timer set to {}", value);
+        self.sound_timer = value;
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // called once per 60Hz frame
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // the pixels drawn or cleared since the last call
+    pub fn take_display_dirty(&mut self) -> DirtyRegion {
+        self.display.take_dirty()
     }
 
     pub fn get_display_buffer(&self) -> &[u8] {
         self.display.get_display_buffer()
     }
 
-    pub fn print_ram(&self) {
-        self.ram.print_ram();
+    pub fn ram_dump(&self) -> String {
+        self.ram.dump()
     }
 
 }
 
+impl Default for Bus {
+    fn default() -> Self {
+        Bus::new()
+    }
+}
+
 impl fmt::Debug for Bus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, " Delay timer: {:?}", self.delay_timer)
+        write!(f, " Delay timer: {:?} Sound timer: {:?}", self.delay_timer, self.sound_timer)
     }
 }
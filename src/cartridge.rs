@@ -0,0 +1,201 @@
+use crate::octo;
+use crate::palette::{self, Palette};
+use crate::quirks::Quirks;
+use crate::rom::RomLoadError;
+use crate::symbols::Symbols;
+use serde_json::Value;
+use std::path::Path;
+
+// Octo cartridges are GIF images whose palette indices carry a payload in their
+// two low bits, four pixels per byte, most significant bits first, continuing
+// across frames. The payload is a 32 bit big endian length followed by that many
+// bytes of UTF-8 JSON: {"program": "<Octo source>", "options": {...}}.
+
+// the source is inside the image, the lines of its symbols name it like this
+pub const SOURCE_NAME: &str = "cartridge.8o";
+
+pub struct CartridgeOptions {
+    pub tickrate: Option<u32>,
+    pub quirks: Quirks,
+    pub palette: Palette,
+}
+
+pub struct Cartridge {
+    // Octo source, see octo::assemble
+    pub source: String,
+    pub options: CartridgeOptions,
+}
+
+impl Cartridge {
+    pub fn decode(data: &[u8]) -> Result<Cartridge, RomLoadError> {
+        let payload = decode_payload(data)?;
+        let json: Value = serde_json::from_slice(&payload)
+            .map_err(|err| invalid(format!("payload is not valid JSON: {}", err)))?;
+
+        let source = match json.get("program") {
+            Some(Value::String(source)) => source.clone(),
+            _ => return Err(invalid(String::from("payload has no program"))),
+        };
+
+        let options = match json.get("options") {
+            Some(options) => parse_options(options),
+            None => parse_options(&Value::Null),
+        };
+
+        Ok(Cartridge { source, options })
+    }
+}
+
+fn invalid(message: String) -> RomLoadError {
+    RomLoadError::InvalidCartridge(message)
+}
+
+fn decode_payload(data: &[u8]) -> Result<Vec<u8>, RomLoadError> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options
+        .read_info(data)
+        .map_err(|err| invalid(format!("failed to decode GIF: {}", err)))?;
+
+    let mut bytes = Vec::<u8>::new();
+    let mut current: u8 = 0;
+    let mut bits = 0;
+
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|err| invalid(format!("failed to decode GIF frame: {}", err)))?
+    {
+        for index in frame.buffer.iter() {
+            current = (current << 2) | (index & 0b11);
+            bits += 2;
+            if bits == 8 {
+                bytes.push(current);
+                current = 0;
+                bits = 0;
+            }
+        }
+    }
+
+    if bytes.len() < 4 {
+        return Err(invalid(String::from("image is too small to hold a payload")));
+    }
+    let size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    if bytes.len() - 4 < size {
+        return Err(invalid(format!("payload claims {} bytes but only {} are present", size, bytes.len() - 4)));
+    }
+
+    Ok(bytes[4..4 + size].to_vec())
+}
+
+impl Cartridge {
+    // the program bytes, to load at 0x200, and the symbols of the source
+    pub fn assemble(&self) -> Result<(Vec<u8>, Symbols), RomLoadError> {
+        octo::assemble_with_symbols(&self.source, Path::new(SOURCE_NAME)).map_err(RomLoadError::Assemble)
+    }
+}
+
+fn parse_options(options: &Value) -> CartridgeOptions {
+    let flag = |name: &str| options.get(name).and_then(Value::as_bool).unwrap_or(false);
+    let color = |name: &str, default: u32| {
+        options
+            .get(name)
+            .and_then(Value::as_str)
+            .and_then(palette::parse_hex_color)
+            .unwrap_or(default)
+    };
+
+    let tickrate = options
+        .get("tickrate")
+        .and_then(|rate| rate.as_u64().or_else(|| rate.as_str()?.parse().ok()))
+        .map(|rate| rate as u32);
+
+    let quirks = Quirks {
+        shift: flag("shiftQuirks"),
+        load_store: flag("loadStoreQuirks"),
+        vf_reset: flag("logicQuirks"),
+        vf_order: flag("vfOrderQuirks"),
+        clip: flag("clipQuirks"),
+        jump: flag("jumpQuirks"),
+        vblank: flag("vBlankQuirks"),
+        // Octo has no CHIP-48 increment option
+        increment_by_x: false,
+    };
+
+    let default = Palette::default();
+    let palette = Palette {
+        colors: [
+            color("backgroundColor", default.colors[0]),
+            color("fillColor", default.colors[1]),
+            color("fillColor2", default.colors[2]),
+            color("blendColor", default.colors[3]),
+        ],
+        buzzer: color("buzzColor", default.buzzer),
+        quiet: color("quietColor", default.quiet),
+    };
+
+    CartridgeOptions { tickrate, quirks, palette }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+    use serde_json::json;
+    use std::borrow::Cow;
+
+    // a cartridge image holding `json` the way Octo writes it
+    fn encode(json: &Value) -> Vec<u8> {
+        let text = json.to_string();
+        let mut payload = (text.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(text.as_bytes());
+        let mut pixels: Vec<u8> =
+            payload.iter().flat_map(|byte| [6, 4, 2, 0].map(|shift| (byte >> shift) & 0b11)).collect();
+        let width = 128;
+        pixels.resize(pixels.len().div_ceil(width) * width, 0);
+
+        let mut image = Vec::new();
+        let palette = [0, 0, 0, 0x55, 0x55, 0x55, 0xAA, 0xAA, 0xAA, 0xFF, 0xFF, 0xFF];
+        let height = (pixels.len() / width) as u16;
+        let mut encoder = gif::Encoder::new(&mut image, width as u16, height, &palette).unwrap();
+        let frame = gif::Frame { width: width as u16, height, buffer: Cow::Owned(pixels), ..gif::Frame::default() };
+        encoder.write_frame(&frame).unwrap();
+        drop(encoder);
+        image
+    }
+
+    #[test]
+    fn cartridges_round_trip_through_gif() {
+        let source = ": main\n  v0 := 7\n  loop again\n";
+        let options = json!({ "tickrate": 200, "shiftQuirks": true, "fillColor": "#FF0000" });
+        let image = encode(&json!({ "program": source, "options": options }));
+
+        let cartridge = Cartridge::decode(&image).unwrap();
+        assert_eq!(cartridge.source, source);
+        assert_eq!(cartridge.options.tickrate, Some(200));
+        assert!(cartridge.options.quirks.shift && !cartridge.options.quirks.jump);
+        assert_eq!(cartridge.options.palette.colors[1], 0xFF0000);
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&image).unwrap();
+        assert_eq!(chip8.read_instruction(0x200), 0x6007);
+        assert_eq!(chip8.read_instruction(0x202), 0x1202);
+        assert_eq!(chip8.tickrate(), 200);
+        assert!(chip8.quirks().shift);
+
+        // the labels of the source name the program, symbols given for the rom replace them
+        let symbols = chip8.symbols().unwrap();
+        assert_eq!(symbols.label(0x200), Some("main"));
+        assert_eq!(symbols.source_line(0x202).unwrap().path, Path::new(SOURCE_NAME));
+        chip8.set_symbols(Some(Symbols::default()));
+        assert_eq!(chip8.symbols().unwrap().label(0x200), None);
+        chip8.set_symbols(None);
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(chip8.symbols().is_none());
+    }
+
+    #[test]
+    fn cartridges_that_do_not_assemble_are_rejected() {
+        let image = encode(&json!({ "program": ": main\n  v0 := 999\n" }));
+        assert!(matches!(Chip8::new().load_rom(&image), Err(RomLoadError::Assemble(err)) if err.line == 2));
+    }
+}
@@ -1,42 +1,612 @@
 use crate::bus::Bus;
+use crate::cartridge::Cartridge;
 use crate::cpu::Cpu;
 use crate::cpu;
+use crate::database::{self, RomDatabase, RomInfo};
+use crate::disasm;
+use crate::display::{self, DirtyRegion};
+use crate::palette::Palette;
+use crate::quirks::Quirks;
+use crate::renderer::Frame;
+use crate::rom::{self, RomFormat, RomLoadError};
+use crate::scaler::FilterPipeline;
+use crate::screenshot;
+use crate::symbols::Symbols;
+use crate::timing::{self, TimingMode};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+// instructions per 60Hz frame, Octo's default
+pub const DEFAULT_TICKRATE: u32 = 20;
+
+// Snapshot of the registers for debuggers and frontends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuState {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
 
 pub struct Chip8 {
     bus: Bus,
     cpu: Cpu,
+    load_address: u16,
+    quirks: Quirks,
+    palette: Palette,
+    tickrate: u32,
+    database: Option<RomDatabase>,
+    rom_info: Option<RomInfo>,
+    timing: TimingMode,
+    // machine cycles spent in the current frame in CosmacVip timing, starting
+    // with the cycles the last instruction of the previous frame ran over
+    frame_cycles: u32,
+    // instructions run in the current frame, a frame stopped by a debugger
+    // resumes where it stopped
+    frame_instructions: u32,
+    // the pixels drawn or cleared during the last frame
+    dirty: DirtyRegion,
+    // seed of the random numbers, so a session can be replayed
+    seed: u64,
+    // SHA-1 of the loaded rom file
+    rom_sha1: Option<String>,
+    // the last rom file loaded and the program it put in memory, for resets
+    rom_data: Vec<u8>,
+    program: Vec<u8>,
+    // instructions executed since power on, for the IPS counter
+    instructions: u64,
+    // names for the addresses of the loaded program in traces and debuggers
+    symbols: Option<Symbols>,
+    // the labels and lines of the loaded cartridge's source
+    cartridge_symbols: Option<Symbols>,
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
-        Chip8 {
+        let mut chip8 = Chip8 {
             bus: Bus::new(),
             cpu: Cpu::new(),
+            load_address: cpu::PROGRAM_START,
+            quirks: Quirks::default(),
+            palette: Palette::default(),
+            tickrate: DEFAULT_TICKRATE,
+            database: None,
+            rom_info: None,
+            timing: TimingMode::default(),
+            frame_cycles: 0,
+            frame_instructions: 0,
+            dirty: DirtyRegion::full(),
+            seed: 0,
+            rom_sha1: None,
+            rom_data: Vec::new(),
+            program: Vec::new(),
+            instructions: 0,
+            symbols: None,
+            cartridge_symbols: None,
+        };
+        chip8.set_seed(rand::random());
+        chip8
+    }
+
+    // 0x200 for most programs, rom::ETI_660_PROGRAM_START for ETI 660 programs
+    pub fn set_load_address(&mut self, address: u16) -> Result<(), RomLoadError> {
+        rom::max_rom_size(address)?;
+        self.load_address = address;
+        self.cpu.set_pc(address);
+        Ok(())
+    }
+
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.cpu.set_quirks(quirks);
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn tickrate(&self) -> u32 {
+        self.tickrate
+    }
+
+    pub fn set_tickrate(&mut self, tickrate: u32) {
+        self.tickrate = tickrate.max(1);
+    }
+
+    pub fn timing(&self) -> TimingMode {
+        self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: TimingMode) {
+        self.timing = timing;
+        self.frame_cycles = 0;
+        self.frame_instructions = 0;
+    }
+
+    // restarts the random numbers from `seed`, the same seed and inputs from
+    // power on give the same session
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.cpu.set_seed(seed);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn rom_sha1(&self) -> Option<&str> {
+        self.rom_sha1.as_deref()
+    }
+
+    // raw roms loaded afterwards get their settings from this database
+    pub fn set_database(&mut self, database: RomDatabase) {
+        self.database = Some(database);
+    }
+
+    pub fn database(&self) -> Option<&RomDatabase> {
+        self.database.as_ref()
+    }
+
+    // database entry of the loaded rom, if it was found
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+
+    // kept across rom loads, the frontend replaces them with the rom's.
+    // Without them a cartridge has the symbols of its source
+    pub fn set_symbols(&mut self, symbols: Option<Symbols>) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> Option<&Symbols> {
+        self.symbols.as_ref().or(self.cartridge_symbols.as_ref())
+    }
+
+    // loads raw programs and Octo cartridges, see load_octo_cartridge
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), RomLoadError> {
+        self.rom_info = None;
+        self.rom_sha1 = None;
+        self.cartridge_symbols = None;
+        match RomFormat::detect(data) {
+            RomFormat::Raw => {
+                self.load_program(data)?;
+                self.apply_database(data);
+            },
+            RomFormat::OctoCartridge => self.load_octo_cartridge(data)?,
+        }
+        self.rom_sha1 = Some(database::sha1_hex(data));
+        self.rom_data = data.to_vec();
+        Ok(())
+    }
+
+    // restarts the machine with the same program: registers, memory, display,
+    // timers and keys start over, the settings and the seed are kept
+    pub fn soft_reset(&mut self) {
+        self.reset_machine();
+        let program = std::mem::take(&mut self.program);
+        // the program fit when it was first loaded
+        let _ = self.load_program(&program);
+    }
+
+    // power cycles the machine: the rom is loaded again with the settings of the
+    // database or cartridge and a new seed
+    pub fn hard_reset(&mut self) -> Result<(), RomLoadError> {
+        let data = self.rom_data.clone();
+        self.reload_rom(&data)
+    }
+
+    // hard resets with `data` as the rom. If it does not load, the machine keeps
+    // running the old one
+    pub fn reload_rom(&mut self, data: &[u8]) -> Result<(), RomLoadError> {
+        match RomFormat::detect(data) {
+            RomFormat::Raw => rom::validate(data, self.load_address)?,
+            RomFormat::OctoCartridge => {
+                Cartridge::decode(data)?.assemble()?;
+            },
+        }
+
+        self.quirks = Quirks::default();
+        self.tickrate = DEFAULT_TICKRATE;
+        self.palette = Palette::default();
+        self.seed = rand::random();
+        self.reset_machine();
+        self.load_rom(data)
+    }
+
+    pub fn reload_rom_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), RomLoadError> {
+        let data = fs::read(path)?;
+        self.reload_rom(&data)
+    }
+
+    fn reset_machine(&mut self) {
+        self.bus = Bus::new();
+        self.cpu = Cpu::new();
+        self.cpu.set_quirks(self.quirks);
+        self.cpu.set_seed(self.seed);
+        self.cpu.set_pc(self.load_address);
+        self.frame_cycles = 0;
+        self.frame_instructions = 0;
+        self.dirty = DirtyRegion::full();
+    }
+
+    fn apply_database(&mut self, data: &[u8]) {
+        let info = match self.database.as_ref().and_then(|database| database.lookup(data)) {
+            Some(info) => info.clone(),
+            None => return,
+        };
+
+        if let Some(tickrate) = info.tickrate {
+            self.set_tickrate(tickrate);
         }
+        self.set_quirks(info.quirks);
+        if let Some(palette) = info.palette {
+            self.set_palette(palette);
+        }
+        self.rom_info = Some(info);
     }
 
-    pub fn load_rom(&mut self, data: &Vec<u8>) {
-        for i in 0..data.len() {
-            self.bus.ram_write_byte(cpu::PROGRAM_START + (i as u16), data[i]);
+    // decodes the cartridge, assembles and loads its program and applies the
+    // embedded tickrate, quirks and colors
+    pub fn load_octo_cartridge(&mut self, data: &[u8]) -> Result<(), RomLoadError> {
+        let cartridge = Cartridge::decode(data)?;
+        let (program, symbols) = cartridge.assemble()?;
+        self.load_program(&program)?;
+        self.cartridge_symbols = Some(symbols);
+
+        let options = cartridge.options;
+        if let Some(tickrate) = options.tickrate {
+            self.set_tickrate(tickrate);
+        }
+        self.set_quirks(options.quirks);
+        self.set_palette(options.palette);
+        Ok(())
+    }
+
+    fn load_program(&mut self, data: &[u8]) -> Result<(), RomLoadError> {
+        rom::validate(data, self.load_address)?;
+
+        for (i, byte) in data.iter().enumerate() {
+            self.bus.ram_write_byte(self.load_address + (i as u16), *byte);
         }
+        self.cpu.set_pc(self.load_address);
+        self.program = data.to_vec();
+        Ok(())
+    }
+
+    pub fn load_rom_from_reader<R: Read>(&mut self, reader: &mut R) -> Result<(), RomLoadError> {
+        let mut data = Vec::<u8>::new();
+        reader.read_to_end(&mut data)?;
+        self.load_rom(&data)
+    }
+
+    pub fn load_rom_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), RomLoadError> {
+        let mut file = File::open(path)?;
+        self.load_rom_from_reader(&mut file)
     }
 
     pub fn run_instruction(&mut self) {
+        crate::log!(Cpu, Trace, "{}", self.trace_line());
         self.cpu.run_instruction(&mut self.bus);
-        //println!("Cpu state: {:?}", self.cpu);
-        //println!("Bus state: {:?}", self.bus);
+        self.instructions += 1;
+        crate::log!(Cpu, Trace, "cpu state: {:?}", self.cpu);
+        crate::log!(Bus, Trace, "bus state: {:?}", self.bus);
+    }
+
+    // the instruction at pc like 0x2A8 <draw_paddle+0x4>: 2300 CALL draw_score
+    fn trace_line(&self) -> String {
+        let pc = self.cpu.pc();
+        let instruction = self.cpu.peek_instruction(&self.bus);
+        let text = disasm::disassemble_with(instruction, self.symbols());
+        match self.symbols().and_then(|symbols| symbols.describe(pc)) {
+            Some(location) => format!("{:#05X} <{}>: {:04X} {}", pc, location, instruction, text),
+            None => format!("{:#05X}: {:04X} {}", pc, instruction, text),
+        }
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        CpuState {
+            v: self.cpu.registers(),
+            i: self.cpu.i(),
+            pc: self.cpu.pc(),
+            stack: self.cpu.stack().to_vec(),
+            delay_timer: self.bus.get_delay_timer(),
+            sound_timer: self.bus.get_sound_timer(),
+        }
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.cpu.pc()
+    }
+
+    // restores registers, stack and timers, for debuggers
+    pub fn set_cpu_state(&mut self, state: &CpuState) {
+        for (index, value) in state.v.iter().enumerate() {
+            self.cpu.write_reg_vx(index as u8, *value);
+        }
+        self.cpu.set_i(state.i);
+        self.cpu.set_pc(state.pc);
+        self.cpu.set_stack(&state.stack);
+        self.bus.set_delay_timer(state.delay_timer);
+        self.bus.set_sound_timer(state.sound_timer);
+    }
+
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.bus.ram_read_byte(address % rom::RAM_SIZE as u16)
+    }
+
+    pub fn write_memory(&mut self, address: u16, value: u8) {
+        self.bus.ram_write_byte(address % rom::RAM_SIZE as u16, value);
+    }
+
+    // big endian instruction word at `address`
+    pub fn read_instruction(&self, address: u16) -> u16 {
+        (self.read_memory(address) as u16) << 8 | self.read_memory(address.wrapping_add(1)) as u16
     }
 
     pub fn get_display_buffer(&self) -> &[u8] {
         self.bus.get_display_buffer()
     }
 
-    pub fn set_key_pressed(&mut self, key: Option<u8>) {
-        self.bus.set_key_pressed(key);
+    // (width, height) of the display in pixels
+    pub fn display_size(&self) -> (usize, usize) {
+        (display::WIDTH, display::HEIGHT)
+    }
+
+    // fills `framebuffer` with the display in 0RGB colors from the active palette
+    pub fn write_framebuffer(&self, framebuffer: &mut Vec<u32>) {
+        framebuffer.clear();
+        framebuffer.extend(self.get_display_buffer().iter().map(|pixel| self.palette.color(*pixel)));
+    }
+
+    // saves the display as a PNG in the active palette, `scale` times the native
+    // resolution with nearest neighbour scaling
+    pub fn save_screenshot<P: AsRef<Path>>(&self, path: P, scale: usize) -> io::Result<()> {
+        let (width, height) = self.display_size();
+        let mut framebuffer = Vec::with_capacity(width * height);
+        self.write_framebuffer(&mut framebuffer);
+        let frame = Frame { width, height, pixels: &framebuffer };
+
+        let scale = scale.max(1);
+        if scale == 1 {
+            return screenshot::save_png(path, &frame);
+        }
+        let mut scaled = Vec::new();
+        FilterPipeline::default().apply(&frame, &mut scaled, width * scale, height * scale);
+        screenshot::save_png(path, &Frame { width: width * scale, height: height * scale, pixels: &scaled })
+    }
+
+    // runs one 60Hz frame followed by a timer tick. The frame is `tickrate`
+    // instructions, or a VIP frame's worth of machine cycles in CosmacVip timing.
+    // Returns true if the display changed during the frame, dirty_region tells
+    // which part
+    pub fn run_frame(&mut self) -> bool {
+        self.run_until(|_| false);
+        !self.dirty.is_empty()
+    }
+
+    // runs the rest of the current frame like run_frame, but stops before an
+    // instruction when `stop` returns true. Returns true if the frame finished,
+    // a stopped frame continues where it stopped on the next call
+    pub fn run_until<F: FnMut(&Chip8) -> bool>(&mut self, mut stop: F) -> bool {
+        while self.frame_continues() {
+            if stop(self) {
+                return false;
+            }
+            self.run_frame_instruction();
+        }
+        self.finish_frame();
+        true
+    }
+
+    // runs exactly one instruction, finishing frames before and after it like
+    // run_frame would. Returns true if a frame finished
+    pub fn step(&mut self) -> bool {
+        let mut finished = false;
+        // a frame can end before its first instruction on a debt of cycles
+        while !self.frame_continues() {
+            self.finish_frame();
+            finished = true;
+        }
+        self.run_frame_instruction();
+        if !self.frame_continues() {
+            self.finish_frame();
+            finished = true;
+        }
+        finished
+    }
+
+    pub fn dirty_region(&self) -> &DirtyRegion {
+        &self.dirty
+    }
+
+    // keeps counting across resets
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions
+    }
+
+    fn frame_continues(&self) -> bool {
+        let has_time = match self.timing {
+            TimingMode::Fixed => self.frame_instructions < self.tickrate,
+            TimingMode::CosmacVip => self.frame_cycles < timing::vip_cycles_per_frame(),
+        };
+        has_time && !self.waits_for_vblank(self.frame_instructions > 0)
+    }
+
+    fn run_frame_instruction(&mut self) {
+        if self.timing == TimingMode::CosmacVip {
+            let instruction = self.cpu.peek_instruction(&self.bus);
+            let vx = self.cpu.read_reg_vx(((instruction & 0x0F00) >> 8) as u8);
+            self.frame_cycles += timing::vip_instruction_cycles(instruction, vx);
+        }
+        self.run_instruction();
+        self.frame_instructions += 1;
+    }
+
+    fn finish_frame(&mut self) {
+        // cycles the frame ran over are taken from the next one. A frame that
+        // stopped early to wait for the interrupt is under budget and owes nothing
+        self.frame_cycles = self.frame_cycles.saturating_sub(timing::vip_cycles_per_frame());
+        self.frame_instructions = 0;
+        self.bus.tick_timers();
+        self.dirty = self.bus.take_display_dirty();
+    }
+
+    // with the vblank quirk DXYN only draws at the start of a frame. The VIP
+    // interpreter always waits for the display interrupt before drawing
+    fn waits_for_vblank(&self, ran_this_frame: bool) -> bool {
+        ran_this_frame
+            && (self.quirks.vblank || self.timing == TimingMode::CosmacVip)
+            && self.cpu.peek_instruction(&self.bus) & 0xF000 == 0xD000
+    }
+
+    // bit N set means CHIP-8 key N is held down
+    pub fn set_keys_down(&mut self, keys_down: u16) {
+        self.bus.set_keys_down(keys_down);
+    }
+
+    pub fn is_sound_playing(&self) -> bool {
+        self.bus.get_sound_timer() > 0
+    }
+
+    pub fn ram_dump(&self) -> String {
+        self.bus.ram_dump()
+    }
+
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vip_machine(program: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(program).unwrap();
+        chip8.set_timing(TimingMode::CosmacVip);
+        chip8
+    }
+
+    #[test]
+    fn vip_frames_run_until_the_cycles_are_spent() {
+        // a jump to itself costs 52 cycles, 50 of them go over the 2598 cycle budget by 2
+        let mut chip8 = vip_machine(&[0x12, 0x00]);
+        chip8.run_frame();
+        assert_eq!(chip8.frame_cycles, 2);
+        // the cycles a frame runs over are taken from the next one
+        for _ in 0..24 {
+            chip8.run_frame();
+        }
+        assert_eq!(chip8.frame_cycles, 50);
+        chip8.run_frame();
+        assert_eq!(chip8.frame_cycles, 0);
+    }
+
+    #[test]
+    fn vip_timing_waits_for_the_display_interrupt_to_draw() {
+        // Reg V0 counts the passes through the loop
+        let program = [0x70, 0x01, 0xD0, 0x01, 0x12, 0x00];
+        let mut chip8 = vip_machine(&program);
+        assert!(!chip8.quirks().vblank);
+        chip8.run_frame();
+        assert_eq!(chip8.cpu.read_reg_vx(0), 1);
+        chip8.run_frame();
+        assert_eq!(chip8.cpu.read_reg_vx(0), 2);
+
+        // without the quirk, fixed timing draws whenever it gets to the sprite
+        let mut chip8 = vip_machine(&program);
+        chip8.set_timing(TimingMode::Fixed);
+        chip8.set_tickrate(10);
+        chip8.run_frame();
+        assert_eq!(chip8.cpu.read_reg_vx(0), 4);
+    }
+
+    #[test]
+    fn vip_frames_keep_their_debt_when_a_draw_comes_next() {
+        // 50 jumps to the next instruction go over the budget by 2, then a sprite is drawn
+        let mut program = Vec::new();
+        for index in 0..50u16 {
+            let next = 0x202 + index * 2;
+            program.extend_from_slice(&[0x10 | (next >> 8) as u8, next as u8]);
+        }
+        program.extend_from_slice(&[0xD0, 0x01, 0x12, 0x00]);
+        let mut chip8 = vip_machine(&program);
+        chip8.run_frame();
+        assert_eq!(chip8.instructions_executed(), 50);
+        assert_eq!(chip8.pc(), 0x264);
+        assert_eq!(chip8.frame_cycles, 2);
+    }
+
+    // a machine drawing and jumping around under `timing`, with the vblank quirk
+    fn busy_machine(timing: TimingMode) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        // 200: V0 += 3, 202: draw at V0,V0, 204: I = V0 * 5 digit, 206: jump to 200
+        chip8.load_rom(&[0x70, 0x03, 0xD0, 0x05, 0xF0, 0x29, 0x12, 0x00]).unwrap();
+        chip8.set_quirks(Quirks { vblank: true, ..chip8.quirks() });
+        chip8.set_timing(timing);
+        chip8
+    }
+
+    #[test]
+    fn run_until_without_a_stop_runs_frames_like_run_frame() {
+        for timing in [TimingMode::Fixed, TimingMode::CosmacVip] {
+            let mut framed = busy_machine(timing);
+            let mut stopped = busy_machine(timing);
+            for _ in 0..20 {
+                let dirty = framed.run_frame();
+                assert!(stopped.run_until(|_| false));
+                assert_eq!(dirty, !stopped.dirty_region().is_empty());
+                assert_eq!(framed.cpu_state(), stopped.cpu_state());
+                assert_eq!(framed.instructions_executed(), stopped.instructions_executed());
+            }
+        }
+    }
+
+    #[test]
+    fn stepping_finishes_the_same_frames_as_run_frame() {
+        for timing in [TimingMode::Fixed, TimingMode::CosmacVip] {
+            let mut framed = busy_machine(timing);
+            let mut stepped = busy_machine(timing);
+            for _ in 0..20 {
+                framed.run_frame();
+                let before = stepped.instructions_executed();
+                while !stepped.step() {}
+                assert!(stepped.instructions_executed() > before);
+                assert_eq!(framed.cpu_state(), stepped.cpu_state());
+                assert_eq!(framed.instructions_executed(), stepped.instructions_executed());
+            }
+        }
     }
 
-    pub fn print_ram(&self) {
-        self.bus.print_ram();
+    #[test]
+    fn stopped_frames_continue_where_they_stopped() {
+        let mut framed = busy_machine(TimingMode::Fixed);
+        let mut stopped = busy_machine(TimingMode::Fixed);
+        framed.run_frame();
+        stopped.run_frame();
+        framed.run_frame();
+        assert!(!stopped.run_until(|chip8| chip8.pc() == 0x206));
+        assert_eq!(stopped.pc(), 0x206);
+        assert!(stopped.run_until(|_| false));
+        assert_eq!(framed.cpu_state(), stopped.cpu_state());
     }
-    
 }
@@ -0,0 +1,335 @@
+use crate::keymap::{self, KeyBindings, Keymap, PRESET_NAMES};
+use crate::palette::{self, Palette, PALETTE_NAMES};
+use crate::persistence::FlickerFilter;
+use crate::scaler::{FilterPipeline, Scaler};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use toml::{Table, Value};
+
+// User settings from a TOML file, read from DEFAULT_PATH unless another one is
+// given on the command line:
+//
+//     [display]
+//     palette = "amber"                 # classic, green, amber, lcd, octo, inverted
+//     colors = ["#000000", "#FFB000"]   # background, fill[, fill 2, blend]
+//     buzzer = "#332200"
+//     quiet = "#000000"
+//     flicker = "fade:0.6"              # off, blend, fade or fade:<decay>
+//     scaler = "scale2x"                # nearest, bilinear, scale2x
+//     scanlines = true
+//     grid = false
+//
+//     [keys]
+//     preset = "azerty"                 # qwerty, azerty, qwertz, dvorak, numpad
+//     5 = ["W", "Up"]                   # CHIP-8 key = host key or keys
+//     A = "Space"
+//
+//     [keys.roms.TETRIS]                # rom file name or database title
+//     4 = "Left"
+//     6 = "Right"
+
+pub const DEFAULT_PATH: &str = "chip8.toml";
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    // None leaves the colors of the rom database or cartridge in place
+    pub palette: Option<Palette>,
+    pub flicker: Option<FlickerFilter>,
+    pub filters: FilterPipeline,
+    pub keys: KeyBindings,
+    // per rom overrides of `keys`, by lowercase rom name
+    pub rom_keys: HashMap<String, KeyBindings>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "failed to read config: {}", err),
+            ConfigError::Parse(message) => write!(f, "invalid config: {}", message),
+            ConfigError::Invalid(message) => write!(f, "invalid config: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl Config {
+    // DEFAULT_PATH if it exists, the defaults otherwise
+    pub fn load_default() -> Result<Config, ConfigError> {
+        if Path::new(DEFAULT_PATH).exists() {
+            Config::load(DEFAULT_PATH)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path)?;
+        Config::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let table: Table = text.parse().map_err(|err: toml::de::Error| ConfigError::Parse(err.to_string()))?;
+        let mut config = Config::default();
+
+        if let Some(display) = table.get("display") {
+            let display = display
+                .as_table()
+                .ok_or_else(|| ConfigError::Invalid(String::from("[display] must be a table")))?;
+            config.palette = parse_palette(display)?;
+            if let Some(flicker) = display.get("flicker") {
+                let flicker = flicker.as_str().and_then(FlickerFilter::parse).ok_or_else(|| {
+                    ConfigError::Invalid(String::from("flicker must be off, blend, fade or fade:<decay>"))
+                })?;
+                config.flicker = Some(flicker);
+            }
+            config.filters = parse_filters(display)?;
+        }
+
+        if let Some(keys) = table.get("keys") {
+            let keys = keys
+                .as_table()
+                .ok_or_else(|| ConfigError::Invalid(String::from("[keys] must be a table")))?;
+            config.keys = parse_key_bindings(keys, "[keys]")?;
+            if let Some(roms) = keys.get("roms") {
+                let roms = roms
+                    .as_table()
+                    .ok_or_else(|| ConfigError::Invalid(String::from("[keys.roms] must be a table")))?;
+                for (name, bindings) in roms {
+                    let context = format!("[keys.roms.{}]", name);
+                    let bindings = bindings
+                        .as_table()
+                        .ok_or_else(|| ConfigError::Invalid(format!("{} must be a table", context)))?;
+                    config.rom_keys.insert(name.to_lowercase(), parse_key_bindings(bindings, &context)?);
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    // the global key bindings, then the overrides of the first of `rom_names`
+    // that has any
+    pub fn keymap(&self, rom_names: &[&str]) -> Keymap {
+        let mut keymap = Keymap::default();
+        self.keys.apply(&mut keymap);
+        if let Some(bindings) = rom_names.iter().find_map(|name| self.rom_keys.get(&name.to_lowercase())) {
+            bindings.apply(&mut keymap);
+        }
+        keymap
+    }
+}
+
+fn parse_key_bindings(table: &Table, context: &str) -> Result<KeyBindings, ConfigError> {
+    let mut bindings = KeyBindings::default();
+    for (name, value) in table {
+        match name.as_str() {
+            "roms" if context == "[keys]" => {},
+            "preset" => {
+                let preset = value.as_str().filter(|preset| Keymap::preset(preset).is_some()).ok_or_else(|| {
+                    ConfigError::Invalid(format!("{} preset must be one of {}", context, PRESET_NAMES.join(", ")))
+                })?;
+                bindings.preset = Some(String::from(preset));
+            },
+            _ => {
+                let chip8_key = keymap::parse_chip8_key(name)
+                    .ok_or_else(|| ConfigError::Invalid(format!("{} has unknown CHIP-8 key '{}'", context, name)))?;
+                let names: Vec<&Value> = match value {
+                    Value::Array(names) => names.iter().collect(),
+                    name => vec![name],
+                };
+                let keys = names
+                    .iter()
+                    .map(|name| name.as_str().and_then(keymap::parse_key).ok_or_else(|| {
+                        ConfigError::Invalid(format!("{} key {} has unknown host key {}", context, chip8_key, name))
+                    }))
+                    .collect::<Result<Vec<_>, _>>()?;
+                bindings.keys.push((chip8_key, keys));
+            },
+        }
+    }
+    Ok(bindings)
+}
+
+fn parse_palette(display: &Table) -> Result<Option<Palette>, ConfigError> {
+    let mut palette = match display.get("palette") {
+        Some(name) => {
+            let name = name.as_str().unwrap_or_default();
+            let palette = Palette::named(name).ok_or_else(|| {
+                ConfigError::Invalid(format!("unknown palette '{}', expected one of {}", name, PALETTE_NAMES.join(", ")))
+            })?;
+            Some(palette)
+        },
+        None => None,
+    };
+
+    if let Some(colors) = display.get("colors") {
+        let colors = colors
+            .as_array()
+            .ok_or_else(|| ConfigError::Invalid(String::from("colors must be an array of hex strings")))?;
+        let list: Vec<&str> = colors.iter().filter_map(Value::as_str).collect();
+        let with_colors = palette
+            .unwrap_or_default()
+            .with_colors(&list.join(","))
+            .filter(|_| list.len() == colors.len())
+            .ok_or_else(|| ConfigError::Invalid(String::from("colors must be 1 to 4 hex colors")))?;
+        palette = Some(with_colors);
+    }
+
+    for (name, is_buzzer) in [("buzzer", true), ("quiet", false)] {
+        if let Some(color) = display.get(name) {
+            let color = color
+                .as_str()
+                .and_then(palette::parse_hex_color)
+                .ok_or_else(|| ConfigError::Invalid(format!("{} must be a hex color", name)))?;
+            let mut with_color = palette.unwrap_or_default();
+            if is_buzzer {
+                with_color.buzzer = color;
+            } else {
+                with_color.quiet = color;
+            }
+            palette = Some(with_color);
+        }
+    }
+
+    Ok(palette)
+}
+
+fn parse_filters(display: &Table) -> Result<FilterPipeline, ConfigError> {
+    let mut filters = FilterPipeline::default();
+    if let Some(scaler) = display.get("scaler") {
+        filters.scaler = scaler
+            .as_str()
+            .and_then(Scaler::parse)
+            .ok_or_else(|| ConfigError::Invalid(String::from("scaler must be nearest, bilinear or scale2x")))?;
+    }
+    for (name, flag) in [("scanlines", &mut filters.scanlines), ("grid", &mut filters.grid)] {
+        if let Some(value) = display.get(name) {
+            *flag = value
+                .as_bool()
+                .ok_or_else(|| ConfigError::Invalid(format!("{} must be true or false", name)))?;
+        }
+    }
+    Ok(filters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minifb::Key;
+
+    #[test]
+    fn an_empty_config_keeps_the_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.palette, None);
+        assert_eq!(config.flicker, None);
+        assert!(config.rom_keys.is_empty());
+    }
+
+    #[test]
+    fn colors_apply_on_top_of_the_named_palette() {
+        let config = Config::parse(
+            r##"
+            [display]
+            palette = "amber"
+            colors = ["#000000", "#FF0000"]
+            quiet = "#010101"
+            "##,
+        )
+        .unwrap();
+        let palette = config.palette.unwrap();
+        assert_eq!(palette.colors, [0x000000, 0xFF0000, 0xFF0000, 0xFF0000]);
+        assert_eq!(palette.buzzer, Palette::named("amber").unwrap().buzzer);
+        assert_eq!(palette.quiet, 0x010101);
+
+        // a color alone starts from the default palette
+        let config = Config::parse("[display]\nbuzzer = \"#332200\"").unwrap();
+        assert_eq!(config.palette, Some(Palette { buzzer: 0x332200, ..Palette::default() }));
+    }
+
+    #[test]
+    fn flicker_filters_are_read_from_the_display_table() {
+        let config = Config::parse("[display]\nflicker = \"fade:0.5\"").unwrap();
+        assert_eq!(config.flicker, Some(FlickerFilter::Fade { decay: 0.5 }));
+        assert!(matches!(Config::parse("[display]\nflicker = \"glow\""), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn filters_are_read_from_the_display_table() {
+        let config = Config::parse("[display]\nscaler = \"bilinear\"\nscanlines = true").unwrap();
+        assert_eq!(config.filters, FilterPipeline { scaler: Scaler::Bilinear, scanlines: true, grid: false });
+        assert!(matches!(Config::parse("[display]\ngrid = \"yes\""), Err(ConfigError::Invalid(_))));
+        assert!(matches!(Config::parse("[display]\nscaler = \"hq2x\""), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn rom_key_overrides_apply_over_the_global_keys() {
+        let config = Config::parse(
+            r#"
+            [keys]
+            preset = "qwertz"
+            5 = ["W", "Up"]
+
+            [keys.roms.Tetris]
+            4 = "Left"
+            "#,
+        )
+        .unwrap();
+
+        let keymap = config.keymap(&["pong.ch8"]);
+        assert_eq!(keymap.chip8_key(Key::Y), Some(0xA));
+        assert_eq!(keymap.chip8_key(Key::Up), Some(0x5));
+        assert_eq!(keymap.chip8_key(Key::Left), None);
+
+        // rom names match in any case, the first name with overrides wins
+        let keymap = config.keymap(&["tetris.ch8", "TETRIS"]);
+        assert_eq!(keymap.host_keys(0x4), vec![Key::Left]);
+        assert_eq!(keymap.chip8_key(Key::Up), Some(0x5));
+    }
+
+    #[test]
+    fn invalid_key_bindings_name_their_table() {
+        let invalid = |text: &str| match Config::parse(text) {
+            Err(ConfigError::Invalid(message)) => message,
+            other => panic!("{:?}", other.map(|_| ())),
+        };
+        assert_eq!(invalid("[keys]\nG = \"W\""), "[keys] has unknown CHIP-8 key 'G'");
+        assert_eq!(
+            invalid("[keys.roms.pong]\n5 = \"Escape\""),
+            "[keys.roms.pong] key 5 has unknown host key \"Escape\""
+        );
+        assert!(invalid("[keys]\npreset = \"colemak\"").starts_with("[keys] preset must be one of"));
+        // only [keys] has roms
+        assert_eq!(invalid("[keys.roms.pong.roms]\n5 = \"W\""), "[keys.roms.pong] has unknown CHIP-8 key 'roms'");
+    }
+
+    #[test]
+    fn invalid_display_settings_are_errors() {
+        for text in [
+            "[display]\npalette = \"sepia\"",
+            "[display]\ncolors = [\"#000000\", 1]",
+            "[display]\ncolors = \"#000000\"",
+            "[display]\nbuzzer = \"red\"",
+            "display = 1",
+        ] {
+            assert!(matches!(Config::parse(text), Err(ConfigError::Invalid(_))), "{}", text);
+        }
+        assert!(matches!(Config::parse("[display"), Err(ConfigError::Parse(_))));
+    }
+}
@@ -1,6 +1,8 @@
 use core::{panic, fmt};
 use crate::bus::Bus;
-use rand::Rng;
+use crate::quirks::Quirks;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 pub const PROGRAM_START: u16 = 0x200;
 
 pub struct Cpu {
@@ -8,6 +10,11 @@ pub struct Cpu {
     pc: u16,
     i: u16,
     ret_stack: Vec<u16>,
+    quirks: Quirks,
+    // key seen by FX0A, which completes when it is released
+    waiting_key: Option<u8>,
+    // ChaCha8 gives the same numbers for a seed on every platform and rand version
+    rng: ChaCha8Rng,
 }
 
 impl Cpu {
@@ -17,16 +24,22 @@ impl Cpu {
             pc: PROGRAM_START,
             i: 0,
             ret_stack: Vec::<u16>::new(),
+            quirks: Quirks::default(),
+            waiting_key: None,
+            rng: ChaCha8Rng::from_entropy(),
         }
     }
 
-    pub fn run_instruction(&mut self, bus: &mut Bus) {
+    // the instruction at pc, without executing it
+    pub fn peek_instruction(&self, bus: &Bus) -> u16 {
         let hi = bus.ram_read_byte(self.pc) as u16;
-        let lo = bus.ram_read_byte(self.pc + 1) as u16;
-        let instruction:u16 = (hi << 8) | lo;
-
-        //println!("Instruction Read instruction {:#X}: self.pc {:#X}, hi: {:#X}, lo: {:#X}", instruction, self.pc, hi, lo);
+        // an instruction at the last byte of RAM wraps around to the first
+        let lo = bus.ram_read_byte((self.pc + 1) & 0x0FFF) as u16;
+        (hi << 8) | lo
+    }
 
+    pub fn run_instruction(&mut self, bus: &mut Bus) {
+        let instruction:u16 = self.peek_instruction(bus);
 
         let nnn = instruction & 0x0FFF;
         let nn = (instruction & 0x0FF) as u8;
@@ -34,8 +47,6 @@ impl Cpu {
         let x = ((instruction & 0x0F00) >> 8) as u8;
         let y = ((instruction & 0x00F0) >> 4) as u8;
 
-        //println!("nnn={:?}, nn={:?}, n={:?}, x={}, y={}", nnn, nn, n, x, y);
-
         match (instruction & 0xF000) >> 12{
             0x0 => {
                 match nn {
@@ -109,35 +120,45 @@ impl Cpu {
                         // sets Reg VX to value of Reg VY
                         self.write_reg_vx(x, vy);
                     },
+                    1 => {
+                        // sets Reg VX to Reg VX OR Reg VY
+                        self.write_reg_vx(x, vx | vy);
+                        self.logic_vf_reset();
+                    },
                     2 => {
                         // sets Reg VX to Reg VX AND Reg VY
                         self.write_reg_vx(x, vx & vy);
+                        self.logic_vf_reset();
                     },
                     3 => {
                         // sets Reg VX to Reg VX XOR Reg VY
                         self.write_reg_vx(x, vx ^ vy);
+                        self.logic_vf_reset();
                     },
                     4 => {
                         // adds Reg VY to Reg VX. Reg VF is set to 1 when there's a carry, and to 0 when there isn't
-                        let sum:u16 = vx as u16 + vy as u16;
-                        self.write_reg_vx(x, sum as u8);
-                        if sum > 0xFF {
-                            self.write_reg_vx(0xF, 1);
-                        }
+                        let (sum, carry) = vx.overflowing_add(vy);
+                        self.write_result_and_flag(x, sum, carry as u8);
                     },
                     5 => {
                         // Reg VY is subtracted from Reg VX. Reg VF is set to 0 when there's a borrow, and 1 when there isn't
-                        let diff:i8 = vx as i8 - vy as i8;
-                        self.write_reg_vx(x, diff as u8);
-                        if diff < 0 {
-                            self.write_reg_vx(0xF, 1);
-                        }
+                        let (diff, borrow) = vx.overflowing_sub(vy);
+                        self.write_result_and_flag(x, diff, !borrow as u8);
                     },
                     6 => {
-                        // Vx=Vy>>1
-                        self.write_reg_vx(0xF, vy & 0x1);
-                        self.write_reg_vx(y, vy >> 1);
-                        self.write_reg_vx(x, vy >> 1);
+                        // Vx=Vy>>1, or Vx=Vx>>1 with the shift quirk
+                        let value = if self.quirks.shift { vx } else { vy };
+                        self.write_result_and_flag(x, value >> 1, value & 0x1);
+                    },
+                    7 => {
+                        // sets Reg VX to Reg VY minus Reg VX. Reg VF is set to 0 when there's a borrow, and 1 when there isn't
+                        let (diff, borrow) = vy.overflowing_sub(vx);
+                        self.write_result_and_flag(x, diff, !borrow as u8);
+                    },
+                    0xE => {
+                        // Vx=Vy<<1, or Vx=Vx<<1 with the shift quirk
+                        let value = if self.quirks.shift { vx } else { vy };
+                        self.write_result_and_flag(x, value << 1, value >> 7);
                     },
                     _=> panic!("Unknown 0x8** instruction {:#X}:{:#X}", self.pc, instruction),
                 }
@@ -153,10 +174,15 @@ impl Cpu {
                     self.pc += 2;
                 }
             },
+            0xB => {
+                // jumps to address NNN plus Reg V0, or XNN plus Reg VX with the jump quirk,
+                // wrapping around the 4K address space
+                let offset = if self.quirks.jump { self.read_reg_vx(x) } else { self.read_reg_vx(0) };
+                self.pc = (nnn + offset as u16) & 0x0FFF;
+            },
             0xC => {
                 // sets Reg VX to result of bitwise AND on random number and NN
-                let mut rng = rand::thread_rng();
-                let random_number:u8 = rng.gen();
+                let random_number:u8 = self.rng.gen();
                 self.write_reg_vx(x, random_number & nn);
                 self.pc += 2;
             },
@@ -205,13 +231,15 @@ impl Cpu {
                     },
                     0x0A => {
                         // waits for a key press, stores the value of the key in Reg VX
-                        let key = bus.get_key_pressed();
-                        match key {
-                            Some(val) => {
-                                self.write_reg_vx(x, val);
+                        // once it is released again, like the COSMAC VIP
+                        match self.waiting_key {
+                            Some(key) if !bus.is_key_pressed(key) => {
+                                self.waiting_key = None;
+                                self.write_reg_vx(x, key);
                                 self.pc += 2;
                             },
-                            None => (),
+                            Some(_) => (),
+                            None => self.waiting_key = bus.get_key_pressed(),
                         }
                     },
                     0x15 => {
@@ -220,7 +248,8 @@ impl Cpu {
                         self.pc += 2;
                     },
                     0x18 => {
-                        // TODO sets sound timer to Reg VX
+                        // sets sound timer to Reg VX
+                        bus.set_sound_timer(self.read_reg_vx(x));
                         self.pc += 2;
                     },
                     0x29 => {
@@ -247,7 +276,7 @@ impl Cpu {
                             let value = self.read_reg_vx(index);
                             bus.ram_write_byte(self.i + index as u16, value);
                         }
-                        self.i += x as u16 + 1;
+                        self.increment_i_after_load_store(x);
                         self.pc += 2;
                     },
                     0x65 => {
@@ -256,6 +285,7 @@ impl Cpu {
                             let value = bus.ram_read_byte(self.i + index as u16);
                             self.write_reg_vx(index, value);
                         }
+                        self.increment_i_after_load_store(x);
                         self.pc += 2;
                     },
                     0x1E => {
@@ -269,18 +299,17 @@ impl Cpu {
             },
             _=> panic!("Unknown instruction {:#X}:{:#X}", self.pc, instruction),
         }
+        // stepping past the last byte of RAM wraps to the start
+        self.pc &= 0x0FFF;
     }
 
     fn debug_draw_sprite(&mut self, bus: &mut Bus, x:u8, y:u8, height: u8) {
-        println!("Drawing sprite at ({}, {})", x, y);
-        let mut should_set_vf = false;
-        
-        for sprite_y in 0..height {
-            let byte = bus.ram_read_byte(self.i + sprite_y as u16);
-            if bus.debug_draw_byte(byte, x, y + sprite_y) {
-                should_set_vf = true;
-            }
-        }
+        crate::log!(Display, Debug, "drawing sprite at ({}, {}) height {}", x, y, height);
+
+        let sprite: Vec<u8> = (0..height)
+            .map(|sprite_y| bus.ram_read_byte(self.i + sprite_y as u16))
+            .collect();
+        let should_set_vf = bus.draw_sprite(&sprite, x, y, self.quirks.clip);
 
         if should_set_vf {
             self.write_reg_vx(0xF, 1);
@@ -289,24 +318,202 @@ impl Cpu {
         }
     }
 
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // CXNN draws from a generator restarted from `seed`
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    // Reg VF may itself be the destination, the vf_order quirk decides which write wins
+    fn write_result_and_flag(&mut self, x: u8, result: u8, flag: u8) {
+        if self.quirks.vf_order {
+            self.write_reg_vx(0xF, flag);
+            self.write_reg_vx(x, result);
+        } else {
+            self.write_reg_vx(x, result);
+            self.write_reg_vx(0xF, flag);
+        }
+    }
+
+    fn logic_vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.write_reg_vx(0xF, 0);
+        }
+    }
+
+    fn increment_i_after_load_store(&mut self, x: u8) {
+        if self.quirks.load_store {
+            return;
+        }
+        let increment = if self.quirks.increment_by_x { x } else { x + 1 };
+        self.i += increment as u16;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn registers(&self) -> [u8; 16] {
+        self.vx
+    }
+
+    // return addresses, innermost call last
+    pub fn stack(&self) -> &[u16] {
+        &self.ret_stack
+    }
+
+    pub fn set_pc(&mut self, address: u16) {
+        self.pc = address;
+    }
+
+    pub fn set_i(&mut self, address: u16) {
+        self.i = address;
+    }
+
+    pub fn set_stack(&mut self, stack: &[u16]) {
+        self.ret_stack = stack.to_vec();
+    }
+
     pub fn write_reg_vx(&mut self, index: u8, value: u8) {
         self.vx[index as usize] = value;
     }
 
-    pub fn read_reg_vx(&mut self, index: u8) -> u8{
+    pub fn read_reg_vx(&self, index: u8) -> u8{
         self.vx[index as usize]
     }
 
 }
 
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new()
+    }
+}
+
 impl fmt::Debug for Cpu {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-       write!(f, "\npc: {:#X}\n", self.pc);
-       write!(f, "vx: ");
+       writeln!(f, "\npc: {:#X}", self.pc)?;
+       write!(f, "vx: ")?;
        for item in self.vx.iter() {
-           write!(f, "{:#X} ", *item);
+           write!(f, "{:#X} ", *item)?;
        }
-       write!(f, "\n");
-       write!(f, "i: {:#X}\n", self.i)
+       writeln!(f)?;
+       writeln!(f, "i: {:#X}", self.i)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // runs `instruction` with `registers` set, the rest of the state at power on
+    fn run(quirks: Quirks, registers: &[(u8, u8)], instruction: u16) -> Cpu {
+        let mut bus = Bus::new();
+        bus.ram_write_byte(PROGRAM_START, (instruction >> 8) as u8);
+        bus.ram_write_byte(PROGRAM_START + 1, instruction as u8);
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(quirks);
+        for (index, value) in registers {
+            cpu.write_reg_vx(*index, *value);
+        }
+        cpu.run_instruction(&mut bus);
+        cpu
+    }
+
+    #[test]
+    fn logic_ops_reset_vf_with_the_quirk() {
+        let registers = [(0, 0b1100), (1, 0b1010), (0xF, 7)];
+        let or = run(Quirks::default(), &registers, 0x8011);
+        assert_eq!((or.read_reg_vx(0), or.read_reg_vx(0xF)), (0b1110, 7));
+        let and = run(Quirks { vf_reset: true, ..Quirks::default() }, &registers, 0x8012);
+        assert_eq!((and.read_reg_vx(0), and.read_reg_vx(0xF)), (0b1000, 0));
+        let xor = run(Quirks::default(), &registers, 0x8013);
+        assert_eq!(xor.read_reg_vx(0), 0b0110);
+    }
+
+    #[test]
+    fn arithmetic_sets_and_clears_the_flag() {
+        let add = run(Quirks::default(), &[(0, 0xF0), (1, 0x20), (0xF, 9)], 0x8014);
+        assert_eq!((add.read_reg_vx(0), add.read_reg_vx(0xF)), (0x10, 1));
+        let add = run(Quirks::default(), &[(0, 0x10), (1, 0x20), (0xF, 9)], 0x8014);
+        assert_eq!((add.read_reg_vx(0), add.read_reg_vx(0xF)), (0x30, 0));
+
+        let sub = run(Quirks::default(), &[(0, 0x30), (1, 0x10)], 0x8015);
+        assert_eq!((sub.read_reg_vx(0), sub.read_reg_vx(0xF)), (0x20, 1));
+        let sub = run(Quirks::default(), &[(0, 0x10), (1, 0x30)], 0x8015);
+        assert_eq!((sub.read_reg_vx(0), sub.read_reg_vx(0xF)), (0xE0, 0));
+
+        let subn = run(Quirks::default(), &[(0, 0x10), (1, 0x30)], 0x8017);
+        assert_eq!((subn.read_reg_vx(0), subn.read_reg_vx(0xF)), (0x20, 1));
+    }
+
+    #[test]
+    fn the_flag_wins_over_vf_as_destination_unless_vf_order() {
+        let registers = [(0xF, 0xF0), (1, 0x20)];
+        assert_eq!(run(Quirks::default(), &registers, 0x8F14).read_reg_vx(0xF), 1);
+        let quirks = Quirks { vf_order: true, ..Quirks::default() };
+        assert_eq!(run(quirks, &registers, 0x8F14).read_reg_vx(0xF), 0x10);
+    }
+
+    #[test]
+    fn shifts_use_vy_unless_the_shift_quirk() {
+        let registers = [(0, 0b0000_0011), (1, 0b1000_0100)];
+        let right = run(Quirks::default(), &registers, 0x8016);
+        assert_eq!((right.read_reg_vx(0), right.read_reg_vx(0xF)), (0b0100_0010, 0));
+        let left = run(Quirks::default(), &registers, 0x801E);
+        assert_eq!((left.read_reg_vx(0), left.read_reg_vx(0xF)), (0b0000_1000, 1));
+
+        let quirks = Quirks { shift: true, ..Quirks::default() };
+        let right = run(quirks, &registers, 0x8016);
+        assert_eq!((right.read_reg_vx(0), right.read_reg_vx(0xF)), (0b0000_0001, 1));
+    }
+
+    #[test]
+    fn jump_with_offset() {
+        let registers = [(0, 0x10), (3, 0x20)];
+        assert_eq!(run(Quirks::default(), &registers, 0xB300).pc, 0x310);
+        assert_eq!(run(Quirks { jump: true, ..Quirks::default() }, &registers, 0xB300).pc, 0x320);
+    }
+
+    #[test]
+    fn jump_with_offset_wraps_around_ram() {
+        assert_eq!(run(Quirks::default(), &[(0, 0xFF)], 0xBFFF).pc, 0x0FE);
+
+        // the last byte of RAM fetches the first one as the rest of its instruction
+        let mut cpu = run(Quirks::default(), &[(0, 0xFF)], 0xBF00);
+        assert_eq!(cpu.pc, 0xFFF);
+        let mut bus = Bus::new();
+        bus.ram_write_byte(0xFFF, 0x60);
+        bus.ram_write_byte(0x000, 0x42);
+        assert_eq!(cpu.peek_instruction(&bus), 0x6042);
+        cpu.run_instruction(&mut bus);
+        assert_eq!(cpu.read_reg_vx(0), 0x42);
+        assert_eq!(cpu.pc, 0x001);
+    }
+
+    #[test]
+    fn load_and_store_increment_i_unless_the_quirk() {
+        let mut bus = Bus::new();
+        for (offset, byte) in [0xF2u8, 0x65, 0xF2, 0x55].iter().enumerate() {
+            bus.ram_write_byte(PROGRAM_START + offset as u16, *byte);
+        }
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        cpu.run_instruction(&mut bus);
+        assert_eq!(cpu.i, 0x303);
+        cpu.run_instruction(&mut bus);
+        assert_eq!(cpu.i, 0x306);
+
+        let cpu = run(Quirks { load_store: true, ..Quirks::default() }, &[], 0xF265);
+        assert_eq!(cpu.i, 0);
+        let cpu = run(Quirks { increment_by_x: true, ..Quirks::default() }, &[], 0xF255);
+        assert_eq!(cpu.i, 2);
+    }
+}
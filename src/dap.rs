@@ -0,0 +1,814 @@
+use crate::chip8::{Chip8, CpuState};
+use crate::debugger::{DebugEvent, DebugServer, Debugger, Run, StopReason};
+use crate::disasm;
+use crate::rom;
+use crate::symbols::{self, Symbols};
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+// Debug Adapter Protocol server on a local TCP port, for editors that connect
+// to a running debug adapter. A client launches a rom, or attaches to the one
+// running, and gets:
+//
+//     breakpoints by address (instruction breakpoints), or by source line and
+//     label (function breakpoints) through the rom's symbol file
+//     continue, pause, step in, step over calls and step out
+//     registers, the return stack as the call stack named after labels, and
+//     reads, writes and disassembly of memory
+//
+// There is one thread, the CPU.
+
+const THREAD_ID: i64 = 1;
+const REGISTERS_REFERENCE: i64 = 1;
+const STACK_REFERENCE: i64 = 2;
+const REGISTER_NAMES: [&str; 21] = [
+    "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF", "I", "PC", "SP",
+    "DT", "ST",
+];
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+struct Client {
+    stream: TcpStream,
+    // bytes received that do not form a whole message yet
+    input: Vec<u8>,
+    seq: i64,
+}
+
+pub struct DapServer {
+    listener: TcpListener,
+    client: Option<Client>,
+    debugger: Debugger,
+    // the debugger breaks on the union of the breakpoints set on each source
+    // file, the function and the instruction breakpoints
+    source_breakpoints: HashMap<PathBuf, Vec<u16>>,
+    function_breakpoints: Vec<u16>,
+    instruction_breakpoints: Vec<u16>,
+    stop_on_entry: bool,
+}
+
+impl DapServer {
+    // listens on `port` of the loopback interface
+    pub fn bind(port: u16) -> io::Result<DapServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        Ok(DapServer {
+            listener,
+            client: None,
+            debugger: Debugger::new(),
+            source_breakpoints: HashMap::new(),
+            function_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            stop_on_entry: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // blocks until a client connects, the machine starts halted for it
+    pub fn accept(&mut self) -> io::Result<SocketAddr> {
+        let (stream, address) = self.listener.accept()?;
+        self.attach(stream)?;
+        self.listener.set_nonblocking(true)?;
+        Ok(address)
+    }
+
+    fn attach(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        self.client = Some(Client { stream, input: Vec::new(), seq: 1 });
+        self.debugger.halt(StopReason::Attach);
+        self.debugger.take_stop();
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        self.client = None;
+        self.source_breakpoints.clear();
+        self.function_breakpoints.clear();
+        self.instruction_breakpoints.clear();
+        self.debugger.clear_breakpoints();
+        self.debugger.resume();
+    }
+
+    fn report_stop(&mut self) -> io::Result<()> {
+        let (reason, address) = match self.debugger.take_stop() {
+            Some(StopReason::Breakpoint(address)) => ("breakpoint", Some(address)),
+            Some(StopReason::Step) => ("step", None),
+            Some(StopReason::Interrupt) => ("pause", None),
+            Some(StopReason::Attach) => ("entry", None),
+            None => return Ok(()),
+        };
+        let mut body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        if let Some(address) = address {
+            body["description"] = json!(format!("breakpoint at {:#05X}", address));
+        }
+        self.send_event("stopped", body)
+    }
+
+    fn receive(&mut self, chip8: &mut Chip8) -> io::Result<Option<DebugEvent>> {
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => return Ok(None),
+        };
+        let mut buffer = [0; 4096];
+        loop {
+            match client.stream.read(&mut buffer) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "client closed the connection")),
+                Ok(read) => client.input.extend_from_slice(&buffer[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        while let Some(message) = self.next_message()? {
+            crate::log!(Debugger, Trace, "dap <- {}", message);
+            if message["type"] != "request" {
+                continue;
+            }
+            if let Some(event) = self.handle(&message, chip8)? {
+                return Ok(Some(event));
+            }
+        }
+        self.report_stop()?;
+        Ok(None)
+    }
+
+    // the next whole message, framed by a Content-Length header
+    fn next_message(&mut self) -> io::Result<Option<Value>> {
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => return Ok(None),
+        };
+        let header_end = match client.input.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(end) => end,
+            None if client.input.len() > MAX_MESSAGE_SIZE => {
+                return Err(io::Error::new(ErrorKind::InvalidData, "header too long"));
+            },
+            None => return Ok(None),
+        };
+        let header = String::from_utf8_lossy(&client.input[..header_end]).into_owned();
+        let length = header
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .filter(|length| *length <= MAX_MESSAGE_SIZE)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "missing Content-Length"))?;
+
+        let start = header_end + 4;
+        if client.input.len() < start + length {
+            return Ok(None);
+        }
+        let message: Vec<u8> = client.input.drain(..start + length).skip(start).collect();
+        serde_json::from_slice(&message).map(Some).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+    }
+
+    fn handle(&mut self, request: &Value, chip8: &mut Chip8) -> io::Result<Option<DebugEvent>> {
+        let command = request["command"].as_str().unwrap_or("");
+        let arguments = &request["arguments"];
+        let mut event = None;
+        // an empty machine has nothing to run, clients launch a rom first
+        let runs = matches!(command, "continue" | "next" | "stepIn" | "stepOut");
+        if runs && chip8.rom_sha1().is_none() {
+            return self.respond(request, Err(String::from("no rom is loaded"))).map(|_| None);
+        }
+        let result: Result<Value, String> = match command {
+            "initialize" => {
+                self.respond(request, Ok(capabilities()))?;
+                return self.send_event("initialized", json!({})).map(|_| None);
+            },
+            "launch" => {
+                let launched = self.launch(arguments, chip8);
+                if let Ok(path) = launched.as_ref() {
+                    event = Some(DebugEvent::Launched(path.clone()));
+                }
+                launched.map(|_| Value::Null)
+            },
+            "attach" => {
+                self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                match arguments["symbols"].as_str().map(|path| (path, Symbols::load(path))) {
+                    Some((path, Err(err))) => Err(format!("{}: {}", path, err)),
+                    Some((_, Ok(symbols))) => {
+                        chip8.set_symbols(Some(symbols));
+                        Ok(Value::Null)
+                    },
+                    None => Ok(Value::Null),
+                }
+            },
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.debugger.halt(StopReason::Attach);
+                } else if chip8.rom_sha1().is_some() {
+                    self.debugger.resume();
+                }
+                Ok(Value::Null)
+            },
+            "setBreakpoints" => Ok(self.set_source_breakpoints(chip8, arguments)),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(chip8, arguments)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(arguments)),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(stack_trace(chip8)),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+            ] })),
+            "variables" => Ok(variables(&chip8.cpu_state(), arguments["variablesReference"].as_i64())),
+            "setVariable" => set_variable(chip8, arguments),
+            "continue" => {
+                self.debugger.resume();
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "next" => {
+                // a CALL runs until it returns to the next instruction
+                let pc = chip8.pc();
+                if chip8.read_instruction(pc) & 0xF000 == 0x2000 {
+                    self.debugger.run_to(pc.wrapping_add(2));
+                } else {
+                    self.debugger.step();
+                }
+                Ok(Value::Null)
+            },
+            "stepIn" => {
+                self.debugger.step();
+                Ok(Value::Null)
+            },
+            "stepOut" => {
+                match chip8.cpu_state().stack.last() {
+                    Some(address) => self.debugger.run_to(*address),
+                    None => self.debugger.step(),
+                }
+                Ok(Value::Null)
+            },
+            "pause" => {
+                if self.debugger.is_running() {
+                    self.debugger.halt(StopReason::Interrupt);
+                }
+                Ok(Value::Null)
+            },
+            "readMemory" => read_memory(chip8, arguments),
+            "writeMemory" => write_memory(chip8, arguments),
+            "disassemble" => disassemble(chip8, arguments),
+            "disconnect" => {
+                self.respond(request, Ok(Value::Null))?;
+                if arguments["terminateDebuggee"].as_bool().unwrap_or(false) {
+                    return Ok(Some(DebugEvent::Kill));
+                }
+                self.detach();
+                return Ok(Some(DebugEvent::Detached));
+            },
+            "terminate" => {
+                self.respond(request, Ok(Value::Null))?;
+                self.send_event("terminated", json!({}))?;
+                return Ok(Some(DebugEvent::Kill));
+            },
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+        self.respond(request, result)?;
+        Ok(event)
+    }
+
+    // loads the rom in "program" and its symbols. Returns the rom path
+    fn launch(&mut self, arguments: &Value, chip8: &mut Chip8) -> Result<String, String> {
+        let path = arguments["program"].as_str().ok_or("launch needs a program")?;
+        chip8.reload_rom_file(path).map_err(|err| format!("{}: {}", path, err))?;
+        let symbols = match arguments["symbols"].as_str() {
+            Some(symbols) => Some(Symbols::load(symbols).map_err(|err| format!("{}: {}", symbols, err))?),
+            None => Symbols::load_for_rom(path).transpose().map_err(|err| err.to_string())?,
+        };
+        chip8.set_symbols(symbols);
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(String::from(path))
+    }
+
+    fn set_source_breakpoints(&mut self, chip8: &Chip8, arguments: &Value) -> Value {
+        let path = PathBuf::from(arguments["source"]["path"].as_str().unwrap_or(""));
+        let requested = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested.iter() {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as u32;
+            let found = chip8.symbols().and_then(|symbols| symbols.address_of_line(&path, line));
+            breakpoints.push(match found {
+                Some((address, line)) => {
+                    addresses.push(address);
+                    json!({ "verified": true, "line": line, "instructionReference": address_reference(address) })
+                },
+                None => json!({ "verified": false, "line": line, "message": "no code at this line" }),
+            });
+        }
+        self.source_breakpoints.insert(path, addresses);
+        self.update_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    // breakpoints on the labels of the symbol file
+    fn set_function_breakpoints(&mut self, chip8: &Chip8, arguments: &Value) -> Value {
+        let requested = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+        self.function_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested.iter() {
+            let name = breakpoint["name"].as_str().unwrap_or("");
+            breakpoints.push(match chip8.symbols().and_then(|symbols| symbols.address_of_label(name)) {
+                Some(address) => {
+                    self.function_breakpoints.push(address);
+                    json!({ "verified": true, "instructionReference": address_reference(address) })
+                },
+                None => json!({ "verified": false, "message": format!("no label '{}'", name) }),
+            });
+        }
+        self.update_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Value {
+        let requested = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+        self.instruction_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested.iter() {
+            let reference = breakpoint["instructionReference"].as_str().and_then(symbols::parse_address);
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+            let address = reference.and_then(|address| (address as i64).checked_add(offset));
+            let address = address.filter(|address| in_ram(*address));
+            breakpoints.push(match address {
+                Some(address) => {
+                    self.instruction_breakpoints.push(address as u16);
+                    json!({ "verified": true, "instructionReference": address_reference(address as u16) })
+                },
+                None => json!({ "verified": false, "message": "not an address in RAM" }),
+            });
+        }
+        self.update_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn update_breakpoints(&mut self) {
+        let addresses: BTreeSet<u16> = self
+            .source_breakpoints
+            .values()
+            .flatten()
+            .chain(self.function_breakpoints.iter())
+            .chain(self.instruction_breakpoints.iter())
+            .copied()
+            .collect();
+        self.debugger.clear_breakpoints();
+        for address in addresses {
+            self.debugger.add_breakpoint(address);
+        }
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        let mut response = json!({
+            "seq": client.seq,
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {},
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        client.send(&response)
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        match self.client.as_mut() {
+            Some(client) => {
+                let message = json!({ "seq": client.seq, "type": "event", "event": event, "body": body });
+                client.send(&message)
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl DebugServer for DapServer {
+    // accepts a new client when none is attached
+    fn poll(&mut self, chip8: &mut Chip8) -> Option<DebugEvent> {
+        if self.client.is_none() {
+            return match self.listener.accept() {
+                Ok((stream, address)) => self.attach(stream).ok().map(|_| DebugEvent::Attached(address)),
+                Err(_) => None,
+            };
+        }
+
+        match self.receive(chip8) {
+            Ok(event) => event,
+            Err(err) => {
+                crate::log!(Debugger, Warn, "dap connection lost: {}", err);
+                self.detach();
+                Some(DebugEvent::Detached)
+            },
+        }
+    }
+
+    // without a client the machine runs freely
+    fn run(&mut self, chip8: &mut Chip8) -> Run {
+        let run = self.debugger.run(chip8);
+        if let Err(err) = self.report_stop() {
+            crate::log!(Debugger, Warn, "dap connection lost: {}", err);
+            self.detach();
+        }
+        run
+    }
+
+    fn is_halted(&self) -> bool {
+        self.client.is_some() && !self.debugger.is_running()
+    }
+}
+
+impl Client {
+    fn send(&mut self, message: &Value) -> io::Result<()> {
+        crate::log!(Debugger, Trace, "dap -> {}", message);
+        self.seq += 1;
+        let body = message.to_string();
+        let mut data = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+        data.extend_from_slice(body.as_bytes());
+        // the socket is non-blocking, wait for large responses to go out
+        self.stream.set_nonblocking(false)?;
+        let written = self.stream.write_all(&data);
+        self.stream.set_nonblocking(true)?;
+        written
+    }
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsFunctionBreakpoints": true,
+        "supportsSetVariable": true,
+        "supportsReadMemoryRequest": true,
+        "supportsWriteMemoryRequest": true,
+        "supportsDisassembleRequest": true,
+        "supportsSteppingGranularity": false,
+        "supportsTerminateRequest": true,
+    })
+}
+
+fn address_reference(address: u16) -> String {
+    format!("0x{:04X}", address)
+}
+
+fn in_ram(address: i64) -> bool {
+    (0..rom::RAM_SIZE as i64).contains(&address)
+}
+
+fn source_json(path: &Path) -> Value {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    json!({ "name": name, "path": path.to_string_lossy() })
+}
+
+fn register_value(state: &CpuState, index: usize) -> String {
+    match index {
+        0..=15 => format!("0x{:02X}", state.v[index]),
+        16 => format!("0x{:04X}", state.i),
+        17 => format!("0x{:04X}", state.pc),
+        18 => format!("{}", state.stack.len()),
+        19 => format!("{}", state.delay_timer),
+        _ => format!("{}", state.sound_timer),
+    }
+}
+
+// the program counter, then the CALL of every return address on the stack
+fn stack_trace(chip8: &Chip8) -> Value {
+    let state = chip8.cpu_state();
+    let calls = state.stack.iter().rev().map(|address| address.wrapping_sub(2));
+    let frames: Vec<Value> = std::iter::once(state.pc)
+        .chain(calls)
+        .enumerate()
+        .map(|(id, address)| stack_frame(chip8.symbols(), id, address))
+        .collect();
+    json!({ "stackFrames": frames, "totalFrames": frames.len() })
+}
+
+// named after the label of the code it is in
+fn stack_frame(symbols: Option<&Symbols>, id: usize, address: u16) -> Value {
+    let name = symbols.and_then(|symbols| symbols.describe(address));
+    let mut frame = json!({
+        "id": id,
+        "name": name.unwrap_or_else(|| format!("{:#05X}", address)),
+        "line": 0,
+        "column": 0,
+        "instructionPointerReference": address_reference(address),
+    });
+    if let Some(source) = symbols.and_then(|symbols| symbols.source_line(address)) {
+        frame["source"] = source_json(&source.path);
+        frame["line"] = json!(source.line);
+        frame["column"] = json!(1);
+    }
+    frame
+}
+
+fn variables(state: &CpuState, reference: Option<i64>) -> Value {
+    let variables: Vec<Value> = match reference {
+        Some(REGISTERS_REFERENCE) => REGISTER_NAMES
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                json!({ "name": name, "value": register_value(state, index), "variablesReference": 0 })
+            })
+            .collect(),
+        // innermost return address first
+        Some(STACK_REFERENCE) => state
+            .stack
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(depth, address)| {
+                json!({
+                    "name": format!("#{}", depth),
+                    "value": address_reference(*address),
+                    "variablesReference": 0,
+                    "memoryReference": address_reference(*address),
+                })
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    json!({ "variables": variables })
+}
+
+// registers other than SP can be set, the stack has no addresses to grow with
+fn set_variable(chip8: &mut Chip8, arguments: &Value) -> Result<Value, String> {
+    if arguments["variablesReference"].as_i64() != Some(REGISTERS_REFERENCE) {
+        return Err(String::from("only registers can be set"));
+    }
+    let name = arguments["name"].as_str().unwrap_or("");
+    let index = REGISTER_NAMES.iter().position(|register| *register == name).ok_or("unknown register")?;
+    let value = arguments["value"].as_str().and_then(symbols::parse_address).ok_or("not a number")?;
+
+    let mut state = chip8.cpu_state();
+    let byte = || u8::try_from(value).map_err(|_| String::from("the register is 8 bits"));
+    match index {
+        0..=15 => state.v[index] = byte()?,
+        16 => state.i = value,
+        17 if !in_ram(value as i64 + 1) => return Err(String::from("PC must be an instruction in RAM")),
+        17 => state.pc = value,
+        19 => state.delay_timer = byte()?,
+        20 => state.sound_timer = byte()?,
+        _ => return Err(String::from("SP is read only")),
+    }
+    chip8.set_cpu_state(&state);
+    Ok(json!({ "value": register_value(&state, index) }))
+}
+
+fn memory_address(arguments: &Value) -> Result<i64, String> {
+    let reference = arguments["memoryReference"].as_str().and_then(symbols::parse_address);
+    let reference = reference.ok_or("bad memory reference")?;
+    let offset = arguments["offset"].as_i64().unwrap_or(0);
+    (reference as i64).checked_add(offset).ok_or_else(|| String::from("offset out of range"))
+}
+
+// bytes past the end of RAM are unreadable
+fn read_memory(chip8: &Chip8, arguments: &Value) -> Result<Value, String> {
+    let address = memory_address(arguments)?;
+    let count = arguments["count"].as_i64().unwrap_or(0).max(0);
+    let end = address.checked_add(count).ok_or("count out of range")?;
+    let readable: Vec<u8> = (address..end)
+        .take_while(|address| in_ram(*address))
+        .map(|address| chip8.read_memory(address as u16))
+        .collect();
+    Ok(json!({
+        "address": address_reference(address.clamp(0, u16::MAX as i64) as u16),
+        "data": base64_encode(&readable),
+        "unreadableBytes": count - readable.len() as i64,
+    }))
+}
+
+fn write_memory(chip8: &mut Chip8, arguments: &Value) -> Result<Value, String> {
+    let address = memory_address(arguments)?;
+    let data = base64_decode(arguments["data"].as_str().unwrap_or("")).ok_or("data is not base64")?;
+    if !in_ram(address) || !in_ram(address + data.len() as i64 - 1) {
+        return Err(String::from("the write does not fit in RAM"));
+    }
+    for (offset, byte) in data.iter().enumerate() {
+        chip8.write_memory((address + offset as i64) as u16, *byte);
+    }
+    Ok(json!({ "bytesWritten": data.len() }))
+}
+
+// instructions are two bytes, the ones outside of RAM are shown as invalid.
+// There are no more instructions than RAM holds
+fn disassemble(chip8: &Chip8, arguments: &Value) -> Result<Value, String> {
+    let symbols = chip8.symbols();
+    let address = memory_address(arguments)?;
+    let offset = arguments["instructionOffset"].as_i64().unwrap_or(0).checked_mul(2);
+    let start = offset.and_then(|offset| address.checked_add(offset)).ok_or("instruction offset out of range")?;
+    let count = arguments["instructionCount"].as_i64().unwrap_or(0).clamp(0, rom::RAM_SIZE as i64 / 2);
+    let instructions: Vec<Value> = (0..count)
+        .map(|index| start.saturating_add(index * 2))
+        .map(|address| {
+            if !in_ram(address) || !in_ram(address + 1) {
+                let address = address.clamp(0, u16::MAX as i64) as u16;
+                let address = address_reference(address);
+                return json!({ "address": address, "instruction": "", "presentationHint": "invalid" });
+            }
+            let address = address as u16;
+            let instruction = chip8.read_instruction(address);
+            let mut json = json!({
+                "address": address_reference(address),
+                "instructionBytes": format!("{:02X} {:02X}", instruction >> 8, instruction & 0xFF),
+                "instruction": disasm::disassemble_with(instruction, symbols),
+            });
+            if let Some(label) = symbols.and_then(|symbols| symbols.label(address)) {
+                json["symbol"] = json!(label);
+            }
+            if let Some(source) = symbols.and_then(|symbols| symbols.source_line(address)) {
+                json["location"] = source_json(&source.path);
+                json["line"] = json!(source.line);
+            }
+            json
+        })
+        .collect();
+    Ok(json!({ "instructions": instructions }))
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| bits | (*byte as u32) << (16 - index * 8));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(BASE64_ALPHABET[(bits >> (18 - index * 6)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|c| *c != b'=' && !c.is_ascii_whitespace()) {
+        let value = BASE64_ALPHABET.iter().position(|known| *known == c)? as u32;
+        bits = bits << 6 | value;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_round_trip() {
+        for data in [&b""[..], b"f", b"fo", b"foo", b"foob", b"\x00\xFF\x10\x80\x7F"] {
+            let text = base64_encode(data);
+            assert_eq!(base64_decode(&text).as_deref(), Some(data));
+        }
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+    }
+
+    fn request(command: &str, arguments: Value) -> Value {
+        json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments })
+    }
+
+    // runs until the debugger halts, there is no client to tell
+    fn run_to_stop(server: &mut DapServer, chip8: &mut Chip8) {
+        for _ in 0..100 {
+            if server.run(chip8) == Run::Stopped {
+                return;
+            }
+        }
+        panic!("the machine did not stop");
+    }
+
+    // 200: CALL 206, 202: V1 = 1, 204: jump to 204, 206: V0 += 5, 208: RET
+    fn calling_machine() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x70, 0x05, 0x00, 0xEE]).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn messages_are_framed_by_content_length() {
+        let mut server = DapServer::bind(0).unwrap();
+        let _stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        server.accept().unwrap();
+
+        let first = r#"{"seq":1,"type":"request","command":"threads"}"#;
+        let second = r#"{"seq":2}"#;
+        let input = format!("Content-Length: {}\r\n\r\n{}content-length:{}\r\n\r\n{}", first.len(), first, 9, second);
+        let (head, tail) = input.as_bytes().split_at(input.len() - 3);
+        server.client.as_mut().unwrap().input.extend_from_slice(head);
+        assert_eq!(server.next_message().unwrap().unwrap()["command"], "threads");
+        // the second body is not all there yet
+        assert_eq!(server.next_message().unwrap(), None);
+        server.client.as_mut().unwrap().input.extend_from_slice(tail);
+        assert_eq!(server.next_message().unwrap(), Some(json!({ "seq": 2 })));
+        assert_eq!(server.next_message().unwrap(), None);
+
+        server.client.as_mut().unwrap().input.extend_from_slice(b"Content-Type: json\r\n\r\n{}");
+        assert_eq!(server.next_message().unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn source_breakpoints_move_to_the_next_line_with_code() {
+        let text = "chip8-symbols 1\nline 0x200 3 pong.8o\nline 0x202 7 pong.8o\nline 0x204 7 other.8o\n";
+        let mut chip8 = calling_machine();
+        chip8.set_symbols(Some(Symbols::parse(text, Path::new("roms")).unwrap()));
+        let mut server = DapServer::bind(0).unwrap();
+
+        let arguments = json!({
+            "source": { "path": "roms/pong.8o" },
+            "breakpoints": [{ "line": 3 }, { "line": 5 }, { "line": 8 }],
+        });
+        let body = server.set_source_breakpoints(&chip8, &arguments);
+        let breakpoints = body["breakpoints"].as_array().unwrap();
+        assert_eq!(breakpoints[0]["line"], 3);
+        assert_eq!(breakpoints[1]["line"], 7);
+        assert_eq!(breakpoints[1]["instructionReference"], "0x0202");
+        assert_eq!(breakpoints[2]["verified"], false);
+        assert_eq!(server.debugger.breakpoints().iter().copied().collect::<Vec<u16>>(), vec![0x200, 0x202]);
+
+        // setting the breakpoints of a file again replaces them
+        server.set_source_breakpoints(&chip8, &json!({ "source": { "path": "roms/pong.8o" }, "breakpoints": [] }));
+        assert!(server.debugger.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn next_steps_over_calls() {
+        let mut chip8 = calling_machine();
+        let mut server = DapServer::bind(0).unwrap();
+        server.handle(&request("next", Value::Null), &mut chip8).unwrap();
+        run_to_stop(&mut server, &mut chip8);
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(chip8.cpu_state().v[0], 5);
+
+        // other instructions are a single step
+        server.handle(&request("next", Value::Null), &mut chip8).unwrap();
+        run_to_stop(&mut server, &mut chip8);
+        assert_eq!(chip8.pc(), 0x204);
+        assert_eq!(chip8.cpu_state().v[1], 1);
+    }
+
+    #[test]
+    fn step_out_runs_to_the_return_address() {
+        let mut chip8 = calling_machine();
+        let mut server = DapServer::bind(0).unwrap();
+        server.handle(&request("stepIn", Value::Null), &mut chip8).unwrap();
+        run_to_stop(&mut server, &mut chip8);
+        assert_eq!(chip8.pc(), 0x206);
+        assert_eq!(stack_trace(&chip8)["totalFrames"], 2);
+
+        server.handle(&request("stepOut", Value::Null), &mut chip8).unwrap();
+        run_to_stop(&mut server, &mut chip8);
+        assert_eq!(chip8.pc(), 0x202);
+        assert!(chip8.cpu_state().stack.is_empty());
+    }
+
+    #[test]
+    fn memory_requests_stay_in_range() {
+        let mut chip8 = calling_machine();
+        let read = read_memory(&chip8, &json!({ "memoryReference": "0xFFE", "count": 4 })).unwrap();
+        assert_eq!(read["unreadableBytes"], 2);
+        let huge = json!({ "memoryReference": "0x200", "offset": i64::MAX });
+        assert!(read_memory(&chip8, &huge).is_err());
+        assert!(read_memory(&chip8, &json!({ "memoryReference": "0x200", "count": i64::MAX })).is_err());
+        let data = base64_encode(&[1, 2]);
+        assert!(write_memory(&mut chip8, &json!({ "memoryReference": "0xFFF", "data": data })).is_err());
+
+        let arguments = json!({ "memoryReference": "0x200", "instructionOffset": i64::MAX, "instructionCount": 1 });
+        assert!(disassemble(&chip8, &arguments).is_err());
+        let arguments = json!({ "memoryReference": "0xFFE", "instructionOffset": -1, "instructionCount": i64::MAX });
+        let instructions = disassemble(&chip8, &arguments).unwrap()["instructions"].as_array().unwrap().clone();
+        assert_eq!(instructions.len(), rom::RAM_SIZE / 2);
+        assert_eq!(instructions[0]["address"], "0x0FFC");
+        assert_eq!(instructions[2]["presentationHint"], "invalid");
+    }
+
+    #[test]
+    fn the_program_counter_stays_in_ram() {
+        let mut chip8 = calling_machine();
+        let set = |chip8: &mut Chip8, value: &str| {
+            let arguments = json!({ "variablesReference": REGISTERS_REFERENCE, "name": "PC", "value": value });
+            set_variable(chip8, &arguments)
+        };
+        assert!(set(&mut chip8, "0xFFFF").is_err());
+        assert!(set(&mut chip8, "0xFFF").is_err());
+        assert_eq!(chip8.pc(), 0x200);
+        assert_eq!(set(&mut chip8, "0xFFE").unwrap()["value"], "0x0FFE");
+        assert_eq!(chip8.pc(), 0xFFE);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::palette::{self, Palette};
+use crate::quirks::Quirks;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Per-ROM metadata keyed by SHA-1, in the programs.json format of the CHIP-8
+// community database: an array of programs, each with a "roms" object mapping
+// hashes to file name, platforms, tickrate, colors, keys and quirk overrides.
+
+const BUNDLED: &str = include_str!("../db/programs.json");
+
+// entries in this file replace bundled ones with the same hash
+pub const LOCAL_OVERRIDES_PATH: &str = "chip8-database.json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    pub file: Option<String>,
+    pub platform: Option<String>,
+    pub tickrate: Option<u32>,
+    pub quirks: Quirks,
+    pub palette: Option<Palette>,
+    // game actions ("up", "a", ...) to CHIP-8 keys
+    pub keys: HashMap<String, u8>,
+}
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::Io(err) => write!(f, "failed to read rom database: {}", err),
+            DatabaseError::Parse(message) => write!(f, "invalid rom database: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<io::Error> for DatabaseError {
+    fn from(err: io::Error) -> Self {
+        DatabaseError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDatabase {
+    pub fn new() -> RomDatabase {
+        RomDatabase { roms: HashMap::new() }
+    }
+
+    pub fn bundled() -> RomDatabase {
+        let mut database = RomDatabase::new();
+        database.merge_json(BUNDLED).expect("bundled rom database is invalid");
+        database
+    }
+
+    // the bundled database plus LOCAL_OVERRIDES_PATH if it exists
+    pub fn with_local_overrides() -> Result<RomDatabase, DatabaseError> {
+        let mut database = RomDatabase::bundled();
+        if Path::new(LOCAL_OVERRIDES_PATH).exists() {
+            database.merge_file(LOCAL_OVERRIDES_PATH)?;
+        }
+        Ok(database)
+    }
+
+    pub fn merge_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), DatabaseError> {
+        let text = fs::read_to_string(path)?;
+        self.merge_json(&text)
+    }
+
+    pub fn merge_json(&mut self, text: &str) -> Result<(), DatabaseError> {
+        let programs: Value = serde_json::from_str(text).map_err(|err| DatabaseError::Parse(err.to_string()))?;
+        let programs = programs
+            .as_array()
+            .ok_or_else(|| DatabaseError::Parse(String::from("expected an array of programs")))?;
+
+        for program in programs {
+            let title = program.get("title").and_then(Value::as_str).unwrap_or("Unknown");
+            let authors: Vec<String> = program
+                .get("authors")
+                .and_then(Value::as_array)
+                .map(|authors| authors.iter().filter_map(Value::as_str).map(String::from).collect())
+                .unwrap_or_default();
+            let release = program.get("release").and_then(Value::as_str).map(String::from);
+
+            let roms = match program.get("roms").and_then(Value::as_object) {
+                Some(roms) => roms,
+                None => continue,
+            };
+            for (hash, rom) in roms {
+                let info = parse_rom(title, &authors, &release, rom);
+                self.roms.insert(hash.to_lowercase(), info);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn get(&self, sha1: &str) -> Option<&RomInfo> {
+        self.roms.get(&sha1.to_lowercase())
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.get(&sha1_hex(rom))
+    }
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+fn parse_rom(title: &str, authors: &[String], release: &Option<String>, rom: &Value) -> RomInfo {
+    let platform = rom
+        .get("platforms")
+        .and_then(Value::as_array)
+        .and_then(|platforms| platforms.first())
+        .and_then(Value::as_str)
+        .map(String::from);
+
+    let mut quirks = platform
+        .as_deref()
+        .and_then(Quirks::for_platform)
+        .unwrap_or_default();
+    // quirkyPlatforms overrides individual quirks for the platform the rom runs on
+    if let Some(overrides) = platform
+        .as_deref()
+        .and_then(|platform| rom.get("quirkyPlatforms")?.get(platform))
+    {
+        apply_quirk_overrides(&mut quirks, overrides);
+    }
+
+    let tickrate = rom.get("tickrate").and_then(Value::as_u64).map(|rate| rate as u32);
+
+    let keys = rom
+        .get("keys")
+        .and_then(Value::as_object)
+        .map(|keys| {
+            keys.iter()
+                .filter_map(|(action, key)| Some((action.clone(), key.as_u64().filter(|key| *key < 16)? as u8)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    RomInfo {
+        title: String::from(title),
+        authors: authors.to_vec(),
+        release: release.clone(),
+        file: rom.get("file").and_then(Value::as_str).map(String::from),
+        platform,
+        tickrate,
+        quirks,
+        palette: rom.get("colors").and_then(parse_colors),
+        keys,
+    }
+}
+
+fn apply_quirk_overrides(quirks: &mut Quirks, overrides: &Value) {
+    let flag = |name: &str| overrides.get(name).and_then(Value::as_bool);
+
+    if let Some(shift) = flag("shift") {
+        quirks.shift = shift;
+    }
+    if let Some(leave_i) = flag("memoryLeaveIUnchanged") {
+        quirks.load_store = leave_i;
+    }
+    if let Some(logic) = flag("logic") {
+        quirks.vf_reset = logic;
+    }
+    if let Some(wrap) = flag("wrap") {
+        quirks.clip = !wrap;
+    }
+    if let Some(jump) = flag("jump") {
+        quirks.jump = jump;
+    }
+    if let Some(vblank) = flag("vblank") {
+        quirks.vblank = vblank;
+    }
+    if let Some(increment_by_x) = flag("memoryIncrementByX") {
+        quirks.increment_by_x = increment_by_x;
+    }
+    // the database has no equivalent of vf_order, it keeps the platform's default
+}
+
+fn parse_colors(colors: &Value) -> Option<Palette> {
+    let mut palette = Palette::default();
+    let pixels = colors.get("pixels")?.as_array()?;
+    for (index, color) in pixels.iter().take(palette.colors.len()).enumerate() {
+        palette.colors[index] = palette::parse_hex_color(color.as_str()?)?;
+    }
+    // with only two colors every set pixel uses the fill color
+    if pixels.len() == 2 {
+        palette.colors[2] = palette.colors[1];
+        palette.colors[3] = palette.colors[1];
+    }
+    if let Some(buzzer) = colors.get("buzzer").and_then(Value::as_str).and_then(palette::parse_hex_color) {
+        palette.buzzer = buzzer;
+    }
+    if let Some(silence) = colors.get("silence").and_then(Value::as_str).and_then(palette::parse_hex_color) {
+        palette.quiet = silence;
+    }
+    Some(palette)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // HASH is replaced by the sha1 of b"pong"
+    const PONG: &str = r##"[
+        {
+            "title": "Pong",
+            "authors": ["Paul Vervalin"],
+            "release": "1990",
+            "roms": {
+                "HASH": {
+                    "file": "pong.ch8",
+                    "platforms": ["chip48", "superchip"],
+                    "tickrate": 15,
+                    "keys": { "up": 1, "down": 4, "bogus": 16 },
+                    "quirkyPlatforms": {
+                        "chip48": { "shift": false, "memoryIncrementByX": false, "logic": true, "wrap": true },
+                        "superchip": { "jump": false }
+                    },
+                    "colors": { "pixels": ["#000000", "#FF8000"], "buzzer": "#123456" }
+                }
+            }
+        }
+    ]"##;
+
+    fn pong_json() -> String {
+        PONG.replace("HASH", &sha1_hex(b"pong").to_uppercase())
+    }
+
+    #[test]
+    fn entries_are_found_by_hash_in_any_case() {
+        let mut database = RomDatabase::new();
+        database.merge_json(&pong_json()).unwrap();
+        assert_eq!(database.len(), 1);
+
+        let info = database.lookup(b"pong").unwrap();
+        assert_eq!(info.title, "Pong");
+        assert_eq!(info.authors, vec![String::from("Paul Vervalin")]);
+        assert_eq!(info.release.as_deref(), Some("1990"));
+        assert_eq!(info.file.as_deref(), Some("pong.ch8"));
+        assert_eq!(info.platform.as_deref(), Some("chip48"));
+        assert_eq!(info.tickrate, Some(15));
+        assert!(database.get(&sha1_hex(b"pong").to_uppercase()).is_some());
+        assert!(database.lookup(b"ping").is_none());
+    }
+
+    #[test]
+    fn platform_quirks_take_the_overrides_of_the_first_platform() {
+        let mut database = RomDatabase::new();
+        database.merge_json(&pong_json()).unwrap();
+        let quirks = database.lookup(b"pong").unwrap().quirks;
+
+        // chip48 defaults with shift and increment_by_x turned off, vf_reset on and wrapping
+        let expected = Quirks { vf_reset: true, jump: true, ..Quirks::default() };
+        assert_eq!(quirks, expected);
+    }
+
+    #[test]
+    fn keys_out_of_range_are_dropped_and_two_colors_fill_both_planes() {
+        let mut database = RomDatabase::new();
+        database.merge_json(&pong_json()).unwrap();
+        let info = database.lookup(b"pong").unwrap();
+
+        assert_eq!(info.keys.len(), 2);
+        assert_eq!(info.keys["up"], 1);
+        assert_eq!(info.keys["down"], 4);
+
+        let palette = info.palette.unwrap();
+        assert_eq!(palette.colors, [0x000000, 0xFF8000, 0xFF8000, 0xFF8000]);
+        assert_eq!(palette.buzzer, 0x123456);
+        assert_eq!(palette.quiet, Palette::default().quiet);
+    }
+
+    #[test]
+    fn later_files_replace_entries_with_the_same_hash() {
+        let mut database = RomDatabase::new();
+        database.merge_json(&pong_json()).unwrap();
+
+        let path = std::env::temp_dir().join(format!("chip8-database-test-{}.json", std::process::id()));
+        let hash = sha1_hex(b"pong");
+        let local = format!(r#"[{{"title": "My Pong", "roms": {{"{}": {{"platforms": ["xochip"]}}}}}}]"#, hash);
+        fs::write(&path, local).unwrap();
+        let merged = database.merge_file(&path);
+        fs::remove_file(&path).unwrap();
+        merged.unwrap();
+
+        assert_eq!(database.len(), 1);
+        let info = database.lookup(b"pong").unwrap();
+        assert_eq!(info.title, "My Pong");
+        assert_eq!(info.quirks, Quirks::default());
+        assert_eq!(info.tickrate, None);
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        let mut database = RomDatabase::new();
+        assert!(matches!(database.merge_json("{"), Err(DatabaseError::Parse(_))));
+        assert!(matches!(database.merge_json("{}"), Err(DatabaseError::Parse(_))));
+        assert!(matches!(database.merge_file("/nonexistent/chip8-database.json"), Err(DatabaseError::Io(_))));
+    }
+}
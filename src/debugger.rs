@@ -0,0 +1,226 @@
+use crate::chip8::Chip8;
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+
+// Execution control shared by the debugger frontends: breakpoints on
+// instruction addresses, halting, stepping and continuing. The frontend loop
+// calls run once per emulated frame instead of Chip8::run_frame.
+
+// A debugger protocol served to a client, polled by the frontend loop
+pub trait DebugServer {
+    // handles what the client sent since the last call
+    fn poll(&mut self, chip8: &mut Chip8) -> Option<DebugEvent>;
+
+    // runs `chip8` like Debugger::run and reports stops to the client
+    fn run(&mut self, chip8: &mut Chip8) -> Run;
+
+    // true while a client keeps the machine halted
+    fn is_halted(&self) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugEvent {
+    Attached(SocketAddr),
+    // the client detached or the connection dropped, the machine runs freely
+    Detached,
+    // the client loaded this rom file
+    Launched(String),
+    // the client ended the session, the emulator should quit
+    Kill,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    // the debugger attached, the machine starts halted
+    Attach,
+    Breakpoint(u16),
+    Step,
+    // the client asked to halt
+    Interrupt,
+}
+
+// what a call to Debugger::run did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Run {
+    // a frame finished, its dirty region is up to date
+    Frame,
+    // instructions ran and the machine halted in the middle of a frame
+    Stopped,
+    // the machine is halted, nothing ran
+    Idle,
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    running: bool,
+    // one instruction was requested while halted
+    step: bool,
+    // running again from where the machine halted, a breakpoint there does
+    // not stop it before it moved on
+    resuming: bool,
+    // reported by take_stop once
+    stop: Option<StopReason>,
+    // a one-off breakpoint that stops like a step, for stepping over calls
+    target: Option<u16>,
+}
+
+impl Debugger {
+    // halted until resumed
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn resume(&mut self) {
+        self.running = true;
+        self.resuming = true;
+    }
+
+    // runs until the instruction at `address` or a breakpoint
+    pub fn run_to(&mut self, address: u16) {
+        self.resume();
+        self.target = Some(address);
+    }
+
+    pub fn halt(&mut self, reason: StopReason) {
+        self.running = false;
+        self.step = false;
+        self.target = None;
+        self.stop = Some(reason);
+    }
+
+    // runs one instruction, then halts again
+    pub fn step(&mut self) {
+        self.running = false;
+        self.step = true;
+    }
+
+    // why the machine halted since the last call
+    pub fn take_stop(&mut self) -> Option<StopReason> {
+        self.stop.take()
+    }
+
+    // runs `chip8` until the frame ends or the machine halts
+    pub fn run(&mut self, chip8: &mut Chip8) -> Run {
+        if std::mem::take(&mut self.step) {
+            chip8.step();
+            self.stop = Some(StopReason::Step);
+            return Run::Stopped;
+        }
+        if !self.running {
+            return Run::Idle;
+        }
+
+        if std::mem::take(&mut self.resuming) && self.breakpoints.contains(&chip8.pc()) && chip8.step() {
+            return Run::Frame;
+        }
+        let (breakpoints, target) = (&self.breakpoints, self.target);
+        if chip8.run_until(|chip8| breakpoints.contains(&chip8.pc()) || target == Some(chip8.pc())) {
+            return Run::Frame;
+        }
+        let pc = chip8.pc();
+        if self.breakpoints.contains(&pc) {
+            self.halt(StopReason::Breakpoint(pc));
+        } else {
+            self.halt(StopReason::Step);
+        }
+        Run::Stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200: V0 = 5, 202: V0 += 1, 204: jump to 202
+    fn counting_machine() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x02]).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn breakpoints_stop_before_their_instruction() {
+        let mut chip8 = counting_machine();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.run(&mut chip8), Run::Idle);
+
+        debugger.add_breakpoint(0x204);
+        debugger.resume();
+        assert_eq!(debugger.run(&mut chip8), Run::Stopped);
+        assert_eq!(debugger.take_stop(), Some(StopReason::Breakpoint(0x204)));
+        assert_eq!((chip8.pc(), chip8.cpu_state().v[0]), (0x204, 6));
+        assert!(!debugger.is_running());
+        assert_eq!(debugger.run(&mut chip8), Run::Idle);
+    }
+
+    #[test]
+    fn resuming_on_a_breakpoint_moves_past_it() {
+        let mut chip8 = counting_machine();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x204);
+        debugger.resume();
+        debugger.run(&mut chip8);
+        debugger.take_stop();
+
+        // once around the loop to the same breakpoint
+        debugger.resume();
+        assert_eq!(debugger.run(&mut chip8), Run::Stopped);
+        assert_eq!(debugger.take_stop(), Some(StopReason::Breakpoint(0x204)));
+        assert_eq!((chip8.pc(), chip8.cpu_state().v[0]), (0x204, 7));
+
+        // without breakpoints whole frames run
+        debugger.clear_breakpoints();
+        debugger.resume();
+        assert_eq!(debugger.run(&mut chip8), Run::Frame);
+        assert_eq!(debugger.take_stop(), None);
+    }
+
+    #[test]
+    fn steps_run_one_instruction() {
+        let mut chip8 = counting_machine();
+        let mut debugger = Debugger::new();
+        // a breakpoint on the next instruction does not hold a step back
+        debugger.add_breakpoint(0x200);
+        debugger.step();
+        assert_eq!(debugger.run(&mut chip8), Run::Stopped);
+        assert_eq!(debugger.take_stop(), Some(StopReason::Step));
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(chip8.instructions_executed(), 1);
+        assert_eq!(debugger.run(&mut chip8), Run::Idle);
+    }
+
+    #[test]
+    fn run_to_stops_like_a_step_once() {
+        let mut chip8 = counting_machine();
+        let mut debugger = Debugger::new();
+        debugger.run_to(0x204);
+        assert_eq!(debugger.run(&mut chip8), Run::Stopped);
+        assert_eq!(debugger.take_stop(), Some(StopReason::Step));
+        assert_eq!(chip8.pc(), 0x204);
+
+        // the target is gone after the stop
+        debugger.resume();
+        assert_eq!(debugger.run(&mut chip8), Run::Frame);
+    }
+}
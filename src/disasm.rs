@@ -0,0 +1,74 @@
+use crate::symbols::Symbols;
+
+// Disassembler using Cowgod's mnemonics
+
+pub fn disassemble(instruction: u16) -> String {
+    disassemble_with(instruction, None)
+}
+
+// with the labels of `symbols` in place of the addresses they name
+pub fn disassemble_with(instruction: u16, symbols: Option<&Symbols>) -> String {
+    let nnn = instruction & 0x0FFF;
+    let target = match symbols.and_then(|symbols| symbols.label(nnn)) {
+        Some(label) => String::from(label),
+        None => format!("{:#05X}", nnn),
+    };
+    let nn = instruction & 0x00FF;
+    let n = instruction & 0x000F;
+    let x = (instruction & 0x0F00) >> 8;
+    let y = (instruction & 0x00F0) >> 4;
+
+    match (instruction & 0xF000) >> 12 {
+        0x0 => match instruction {
+            0x00E0 => String::from("CLS"),
+            0x00EE => String::from("RET"),
+            _ => format!("SYS {}", target),
+        },
+        0x1 => format!("JP {}", target),
+        0x2 => format!("CALL {}", target),
+        0x3 => format!("SE V{:X}, {:#04X}", x, nn),
+        0x4 => format!("SNE V{:X}, {:#04X}", x, nn),
+        0x5 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6 => format!("LD V{:X}, {:#04X}", x, nn),
+        0x7 => format!("ADD V{:X}, {:#04X}", x, nn),
+        0x8 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => data_word(instruction),
+        },
+        0x9 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, {}", target),
+        0xB => format!("JP V0, {}", target),
+        0xC => format!("RND V{:X}, {:#04X}", x, nn),
+        0xD => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE => match nn {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => data_word(instruction),
+        },
+        0xF => match nn {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            _ => data_word(instruction),
+        },
+        _ => data_word(instruction),
+    }
+}
+
+fn data_word(instruction: u16) -> String {
+    format!("DW {:#06X}", instruction)
+}
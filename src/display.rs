@@ -1,15 +1,101 @@
 
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+// a rectangle of display pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// The pixels that changed since the display was last read: which rows and the
+// bounding rectangle of every changed pixel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirtyRegion {
+    rows: [bool; HEIGHT],
+    bounds: Option<Rect>,
+}
+
+impl DirtyRegion {
+    pub fn empty() -> DirtyRegion {
+        DirtyRegion {
+            rows: [false; HEIGHT],
+            bounds: None,
+        }
+    }
+
+    pub fn full() -> DirtyRegion {
+        DirtyRegion {
+            rows: [true; HEIGHT],
+            bounds: Some(Rect { x: 0, y: 0, width: WIDTH, height: HEIGHT }),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_none()
+    }
+
+    pub fn is_row_dirty(&self, y: usize) -> bool {
+        self.rows.get(y).copied().unwrap_or(false)
+    }
+
+    // the dirty rows from top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..HEIGHT).filter(|y| self.rows[*y])
+    }
+
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+
+    pub fn mark(&mut self, x: usize, y: usize) {
+        self.rows[y] = true;
+        self.include(Rect { x, y, width: 1, height: 1 });
+    }
+
+    // adds the changes of `other`, for regions collected over several reads
+    pub fn merge(&mut self, other: &DirtyRegion) {
+        if let Some(rect) = other.bounds {
+            self.include(rect);
+            for (row, dirty) in self.rows.iter_mut().zip(other.rows.iter()) {
+                *row |= *dirty;
+            }
+        }
+    }
+
+    fn include(&mut self, rect: Rect) {
+        self.bounds = Some(match self.bounds {
+            None => rect,
+            Some(bounds) => {
+                let (left, top) = (bounds.x.min(rect.x), bounds.y.min(rect.y));
+                let right = (bounds.x + bounds.width).max(rect.x + rect.width);
+                let bottom = (bounds.y + bounds.height).max(rect.y + rect.height);
+                Rect { x: left, y: top, width: right - left, height: bottom - top }
+            },
+        });
+    }
+}
+
+impl Default for DirtyRegion {
+    fn default() -> Self {
+        DirtyRegion::empty()
+    }
+}
 
 pub struct Display {
     screen: [u8; WIDTH * HEIGHT],
+    dirty: DirtyRegion,
 }
 
 impl Display {
     pub fn new() -> Display {
         Display {
             screen: [0; WIDTH * HEIGHT],
+            // the first read draws everything
+            dirty: DirtyRegion::full(),
         }
     }
 
@@ -17,29 +103,57 @@ impl Display {
         y * WIDTH + x
     }
 
-    pub fn debug_draw_byte(&mut self, byte: u8, x: u8, y: u8) -> bool {
+    // XORs an 8 pixel wide sprite onto the screen and returns true if any pixel
+    // was erased. The starting coordinates wrap around the screen; pixels past the
+    // right or bottom edge are clipped, or wrapped to the other side if `clip` is false
+    pub fn draw_sprite(&mut self, sprite: &[u8], x: u8, y: u8, clip: bool) -> bool {
+        let start_x = x as usize % WIDTH;
+        let start_y = y as usize % HEIGHT;
         let mut erased = false;
-        let mut coord_x = x as usize;
-        let coord_y = y as usize;
-        let mut b = byte;
-        
-        for _ in 0..8 {
-            let index = Display::get_index_from_coords(coord_x, coord_y);
 
-            if index >= WIDTH * HEIGHT {
-                continue;
+        for (row, byte) in sprite.iter().enumerate() {
+            let mut coord_y = start_y + row;
+            if coord_y >= HEIGHT {
+                if clip {
+                    break;
+                }
+                coord_y %= HEIGHT;
             }
 
+            if self.draw_byte(*byte, start_x, coord_y, clip) {
+                erased = true;
+            }
+        }
+
+        erased
+    }
+
+    fn draw_byte(&mut self, byte: u8, x: usize, y: usize, clip: bool) -> bool {
+        let mut erased = false;
+        let mut b = byte;
+
+        for offset in 0..8 {
             let bit = (b & 0b1000_0000) >> 7;
+            b <<= 1;
+
+            let mut coord_x = x + offset;
+            if coord_x >= WIDTH {
+                if clip {
+                    break;
+                }
+                coord_x %= WIDTH;
+            }
+
+            let index = Display::get_index_from_coords(coord_x, y);
             let prev_value = self.screen[index];
             self.screen[index] ^= bit;
 
+            if bit != 0 {
+                self.dirty.mark(coord_x, y);
+            }
             if prev_value == 1 && self.screen[index] == 0 {
                 erased = true;
             }
-
-            coord_x += 1;
-            b = b << 1;
         }
 
         erased
@@ -49,9 +163,131 @@ impl Display {
         for pixel in self.screen.iter_mut() {
             *pixel = 0;
         }
+        self.dirty = DirtyRegion::full();
+    }
+
+    // the pixels drawn or cleared since the last take
+    pub fn dirty(&self) -> &DirtyRegion {
+        &self.dirty
+    }
+
+    pub fn take_dirty(&mut self) -> DirtyRegion {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn take_changed(&mut self) -> bool {
+        !self.take_dirty().is_empty()
     }
 
     pub fn get_display_buffer(&self) -> &[u8] {
         &self.screen
     }
-}
\ No newline at end of file
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(display: &Display, x: usize, y: usize) -> u8 {
+        display.get_display_buffer()[Display::get_index_from_coords(x, y)]
+    }
+
+    #[test]
+    fn starting_coordinates_wrap() {
+        let mut display = Display::new();
+        display.draw_sprite(&[0b1000_0000], (WIDTH + 3) as u8, (HEIGHT + 2) as u8, true);
+        assert_eq!(pixel(&display, 3, 2), 1);
+    }
+
+    #[test]
+    fn right_edge_is_clipped_without_bleeding_into_next_row() {
+        let mut display = Display::new();
+        display.draw_sprite(&[0xFF], 60, 0, true);
+        for x in 60..WIDTH {
+            assert_eq!(pixel(&display, x, 0), 1);
+        }
+        for x in 0..4 {
+            assert_eq!(pixel(&display, x, 0), 0);
+            assert_eq!(pixel(&display, x, 1), 0);
+        }
+    }
+
+    #[test]
+    fn right_edge_wraps_when_not_clipping() {
+        let mut display = Display::new();
+        display.draw_sprite(&[0xFF], 60, 5, false);
+        for x in (60..WIDTH).chain(0..4) {
+            assert_eq!(pixel(&display, x, 5), 1);
+        }
+        assert_eq!(pixel(&display, 4, 5), 0);
+        assert_eq!(pixel(&display, 0, 6), 0);
+    }
+
+    #[test]
+    fn bottom_edge_is_clipped() {
+        let mut display = Display::new();
+        display.draw_sprite(&[0x80, 0x80, 0x80, 0x80], 0, 30, true);
+        assert_eq!(pixel(&display, 0, 30), 1);
+        assert_eq!(pixel(&display, 0, 31), 1);
+        assert_eq!(pixel(&display, 0, 0), 0);
+        assert_eq!(pixel(&display, 0, 1), 0);
+    }
+
+    #[test]
+    fn bottom_edge_wraps_when_not_clipping() {
+        let mut display = Display::new();
+        display.draw_sprite(&[0x80, 0x80, 0x80, 0x80], 0, 30, false);
+        for y in [30, 31, 0, 1] {
+            assert_eq!(pixel(&display, 0, y), 1);
+        }
+    }
+
+    #[test]
+    fn bottom_right_corner_clips_in_both_directions() {
+        let mut display = Display::new();
+        display.draw_sprite(&[0xFF, 0xFF], 63, 31, true);
+        assert_eq!(pixel(&display, 63, 31), 1);
+        assert_eq!(display.get_display_buffer().iter().filter(|p| **p == 1).count(), 1);
+    }
+
+    #[test]
+    fn erasing_a_pixel_reports_collision() {
+        let mut display = Display::new();
+        assert!(!display.draw_sprite(&[0xF0], 10, 10, true));
+        assert!(display.draw_sprite(&[0x10], 10, 10, true));
+        assert!(!display.draw_sprite(&[0x01], 10, 10, true));
+    }
+
+    #[test]
+    fn drawing_marks_the_touched_rows_and_bounds() {
+        let mut display = Display::new();
+        display.take_dirty();
+        display.draw_sprite(&[0x80, 0x00, 0x01], 10, 4, true);
+        let dirty = display.take_dirty();
+        assert_eq!(dirty.rows().collect::<Vec<_>>(), vec![4, 6]);
+        assert_eq!(dirty.bounds(), Some(Rect { x: 10, y: 4, width: 8, height: 3 }));
+        assert!(display.dirty().is_empty());
+    }
+
+    #[test]
+    fn clearing_marks_everything() {
+        let mut display = Display::new();
+        display.take_dirty();
+        display.clear();
+        assert_eq!(display.take_dirty(), DirtyRegion::full());
+    }
+
+    #[test]
+    fn clipped_pixels_do_not_report_collision() {
+        let mut display = Display::new();
+        display.draw_sprite(&[0xFF], 0, 0, true);
+        assert!(!display.draw_sprite(&[0xFF], 60, 0, true));
+        assert_eq!(pixel(&display, 0, 0), 1);
+    }
+}
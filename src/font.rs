@@ -0,0 +1,167 @@
+// A 3x5 pixel font for text drawn into frames, like the rom browser and the
+// on-screen overlay. Letters are uppercase only, lowercase is drawn the same
+
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+// a glyph and the space after it
+pub const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+pub const CELL_HEIGHT: usize = GLYPH_HEIGHT + 1;
+
+// rows from the top, bit 2 is the left pixel
+const GLYPHS: [(char, [u8; GLYPH_HEIGHT]); 69] = [
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('"', [0b101, 0b101, 0b000, 0b000, 0b000]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+    ('$', [0b011, 0b110, 0b010, 0b011, 0b110]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('&', [0b010, 0b101, 0b010, 0b101, 0b011]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('*', [0b000, 0b101, 0b010, 0b101, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b011, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    (';', [0b000, 0b010, 0b000, 0b010, 0b100]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('?', [0b111, 0b001, 0b011, 0b000, 0b010]),
+    ('@', [0b010, 0b101, 0b111, 0b100, 0b011]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('[', [0b110, 0b100, 0b100, 0b100, 0b110]),
+    ('\\', [0b100, 0b100, 0b010, 0b001, 0b001]),
+    (']', [0b011, 0b001, 0b001, 0b001, 0b011]),
+    ('^', [0b010, 0b101, 0b000, 0b000, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('`', [0b100, 0b010, 0b000, 0b000, 0b000]),
+    ('{', [0b011, 0b010, 0b110, 0b010, 0b011]),
+    ('|', [0b010, 0b010, 0b010, 0b010, 0b010]),
+    ('}', [0b110, 0b010, 0b011, 0b010, 0b110]),
+    ('~', [0b000, 0b000, 0b011, 0b110, 0b000]),
+];
+
+// the rows of `c`, a question mark for characters the font does not have
+pub fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    let find = |c: char| GLYPHS.iter().find(|(known, _)| *known == c).map(|(_, rows)| *rows);
+    find(c.to_ascii_uppercase()).or_else(|| find('?')).unwrap_or_default()
+}
+
+pub fn text_width(text: &str) -> usize {
+    (text.chars().count() * CELL_WIDTH).saturating_sub(1)
+}
+
+// draws `text` with its top left corner at `x`, `y` into a `width` pixels wide
+// 0RGB image, clipping at the edges
+pub fn draw_text(pixels: &mut [u32], width: usize, x: usize, y: usize, text: &str, color: u32) {
+    draw_text_scaled(pixels, width, x, y, text, color, 1);
+}
+
+// like draw_text with every font pixel drawn as a `scale` x `scale` square
+pub fn draw_text_scaled(pixels: &mut [u32], width: usize, x: usize, y: usize, text: &str, color: u32, scale: usize) {
+    let height = pixels.len() / width.max(1);
+    for (index, c) in text.chars().enumerate() {
+        let left = x + index * CELL_WIDTH * scale;
+        if left >= width {
+            break;
+        }
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in (0..GLYPH_WIDTH).filter(|column| bits & (0b100 >> column) != 0) {
+                for py in (y + row * scale..y + (row + 1) * scale).take_while(|py| *py < height) {
+                    let start = left + column * scale;
+                    let end = (start + scale).min(width);
+                    if start < end {
+                        pixels[py * width + start..py * width + end].fill(color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ON: u32 = 0xFFFFFF;
+
+    #[test]
+    fn unknown_and_lowercase_characters() {
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('\u{e9}'), glyph('?'));
+        assert_eq!(text_width(""), 0);
+        assert_eq!(text_width("AB"), 7);
+    }
+
+    #[test]
+    fn scaled_text_draws_squares_per_font_pixel() {
+        let width = 8;
+        let mut pixels = vec![0; width * 10];
+        draw_text_scaled(&mut pixels, width, 1, 0, "-", ON, 2);
+        // the dash is the middle row, 3 font pixels wide
+        for y in 0..10 {
+            let row = &pixels[y * width..(y + 1) * width];
+            if y == 4 || y == 5 {
+                assert_eq!(row, [0, ON, ON, ON, ON, ON, ON, 0]);
+            } else {
+                assert_eq!(row, [0; 8]);
+            }
+        }
+    }
+
+    #[test]
+    fn text_is_clipped_at_the_edges() {
+        let width = 5;
+        let mut pixels = vec![0; width * 3];
+        draw_text_scaled(&mut pixels, width, 3, 1, "HHHH", ON, 2);
+        // rows 1 and 2 are the top of the first H, its left column at x 3 and 4
+        assert_eq!(&pixels[..5], [0; 5]);
+        assert_eq!(&pixels[5..10], [0, 0, 0, ON, ON]);
+        assert_eq!(&pixels[10..], [0, 0, 0, ON, ON]);
+
+        // nothing to draw into
+        draw_text(&mut [], 0, 0, 0, "H", ON);
+        let mut pixels = vec![0; 4];
+        draw_text(&mut pixels, 2, 7, 7, "H", ON);
+        assert_eq!(pixels, [0; 4]);
+    }
+}
@@ -0,0 +1,639 @@
+use crate::chip8::{Chip8, CpuState};
+use crate::debugger::{DebugEvent, DebugServer, Debugger, Run, StopReason};
+use crate::rom;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+
+// GDB remote serial protocol stub on a local TCP port, so debugger frontends
+// can attach to the emulated machine. The register file is described to the
+// client by target.xml:
+//
+//     0-15  V0-VF   8 bits
+//     16    I       16 bits
+//     17    PC      16 bits
+//     18    SP      8 bits, the depth of the return stack
+//     19    DT      8 bits
+//     20    ST      8 bits
+//
+// Multi-byte registers are big endian like the CHIP-8 itself. Memory is the
+// 4K of RAM, software and hardware breakpoints both stop before the
+// instruction at their address.
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const REGISTER_COUNT: usize = 21;
+const MAX_PACKET_SIZE: usize = 4096;
+
+struct Client {
+    stream: TcpStream,
+    // bytes received that do not form a whole packet yet
+    input: Vec<u8>,
+    no_ack: bool,
+    // a continue or step is waiting for its stop reply
+    waiting: bool,
+}
+
+pub struct GdbServer {
+    listener: TcpListener,
+    client: Option<Client>,
+    debugger: Debugger,
+}
+
+impl GdbServer {
+    // listens on `port` of the loopback interface
+    pub fn bind(port: u16) -> io::Result<GdbServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        Ok(GdbServer { listener, client: None, debugger: Debugger::new() })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // blocks until a client connects, the machine starts halted for it
+    pub fn accept(&mut self) -> io::Result<SocketAddr> {
+        let (stream, address) = self.listener.accept()?;
+        self.attach(stream)?;
+        self.listener.set_nonblocking(true)?;
+        Ok(address)
+    }
+
+    fn attach(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        self.client = Some(Client { stream, input: Vec::new(), no_ack: false, waiting: false });
+        self.debugger.halt(StopReason::Attach);
+        self.debugger.take_stop();
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        self.client = None;
+        self.debugger.clear_breakpoints();
+        self.debugger.resume();
+    }
+
+    fn report_stop(&mut self) -> io::Result<()> {
+        let client = match self.client.as_mut() {
+            Some(client) if client.waiting => client,
+            _ => return Ok(()),
+        };
+        let signal = match self.debugger.take_stop() {
+            Some(StopReason::Interrupt) => SIGINT,
+            Some(_) => SIGTRAP,
+            None => return Ok(()),
+        };
+        client.waiting = false;
+        client.send(&format!("S{:02x}", signal))
+    }
+
+    fn receive(&mut self, chip8: &mut Chip8) -> io::Result<Option<DebugEvent>> {
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => return Ok(None),
+        };
+        let mut buffer = [0; 1024];
+        loop {
+            match client.stream.read(&mut buffer) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "client closed the connection")),
+                Ok(read) => client.input.extend_from_slice(&buffer[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        while let Some(packet) = self.next_packet()? {
+            crate::log!(Debugger, Trace, "gdb <- {}", packet);
+            if let Some(event) = self.handle(&packet, chip8)? {
+                return Ok(Some(event));
+            }
+        }
+        self.report_stop()?;
+        Ok(None)
+    }
+
+    // the next whole packet, acknowledged. Ctrl-C arrives outside of packets
+    // and halts the machine
+    fn next_packet(&mut self) -> io::Result<Option<String>> {
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => return Ok(None),
+        };
+        loop {
+            match client.input.first() {
+                None => return Ok(None),
+                Some(b'$') => break,
+                Some(0x03) => {
+                    client.input.remove(0);
+                    if self.debugger.is_running() {
+                        self.debugger.halt(StopReason::Interrupt);
+                    }
+                },
+                // acks and anything else between packets
+                Some(_) => {
+                    client.input.remove(0);
+                },
+            }
+        }
+
+        let end = match client.input.iter().position(|byte| *byte == b'#') {
+            Some(end) if client.input.len() >= end + 3 => end,
+            _ => {
+                if client.input.len() > MAX_PACKET_SIZE * 2 {
+                    return Err(io::Error::new(ErrorKind::InvalidData, "packet too long"));
+                }
+                return Ok(None);
+            },
+        };
+        let packet: Vec<u8> = client.input.drain(..end + 3).collect();
+        let data = &packet[1..end];
+        let checksum = std::str::from_utf8(&packet[end + 1..]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if !client.no_ack {
+            if checksum != Some(checksum_of(data)) {
+                // the client sends it again
+                client.stream.write_all(b"-")?;
+                return self.next_packet();
+            }
+            client.stream.write_all(b"+")?;
+        }
+        Ok(Some(String::from_utf8_lossy(data).into_owned()))
+    }
+
+    fn handle(&mut self, packet: &str, chip8: &mut Chip8) -> io::Result<Option<DebugEvent>> {
+        let mut reply = String::new();
+        let mut event = None;
+        let (command, arguments) = if packet.is_char_boundary(1) { packet.split_at(1) } else { ("", packet) };
+        match command {
+            "?" => reply = format!("S{:02x}", SIGTRAP),
+            "g" => reply = encode_registers(&chip8.cpu_state()),
+            "G" => {
+                let state = decode_registers(arguments, chip8.cpu_state());
+                reply = ok_or_error(state.map(|state| chip8.set_cpu_state(&state)));
+            },
+            "p" => {
+                let index = usize::from_str_radix(arguments, 16).ok();
+                reply = match index.and_then(|index| register_hex(&chip8.cpu_state(), index)) {
+                    Some(hex) => hex,
+                    None => String::from("E01"),
+                };
+            },
+            "P" => {
+                let written = arguments.split_once('=').and_then(|(index, value)| {
+                    let mut state = chip8.cpu_state();
+                    set_register(&mut state, usize::from_str_radix(index, 16).ok()?, value)?;
+                    chip8.set_cpu_state(&state);
+                    Some(())
+                });
+                reply = ok_or_error(written);
+            },
+            "m" => reply = read_memory(chip8, arguments).unwrap_or_else(|| String::from("E01")),
+            "M" => reply = ok_or_error(write_memory(chip8, arguments)),
+            "Z" | "z" => {
+                let breakpoint = parse_breakpoint(arguments);
+                reply = match breakpoint {
+                    Some(address) if command == "Z" => {
+                        self.debugger.add_breakpoint(address);
+                        String::from("OK")
+                    },
+                    Some(address) => {
+                        self.debugger.remove_breakpoint(address);
+                        String::from("OK")
+                    },
+                    // watchpoints are not supported
+                    None => String::new(),
+                };
+            },
+            "c" | "C" | "s" | "S" => {
+                // an address to resume at, `C` and `S` pass a signal first which is ignored
+                let address = match command {
+                    "c" | "s" => arguments,
+                    _ => arguments.split_once(';').map(|(_, address)| address).unwrap_or(""),
+                };
+                if let Ok(address) = u16::from_str_radix(address, 16) {
+                    if !is_pc(address) {
+                        return self.send("E01").map(|_| None);
+                    }
+                    let mut state = chip8.cpu_state();
+                    state.pc = address;
+                    chip8.set_cpu_state(&state);
+                }
+                self.resume(command.eq_ignore_ascii_case("s"));
+                return Ok(None);
+            },
+            "v" => {
+                if arguments == "Cont?" {
+                    reply = String::from("vCont;c;C;s;S");
+                } else if let Some(actions) = arguments.strip_prefix("Cont;") {
+                    // one thread, so the first action decides
+                    let action = actions.split(';').next().unwrap_or("");
+                    match action.chars().next() {
+                        Some('c' | 'C') => {
+                            self.resume(false);
+                            return Ok(None);
+                        },
+                        Some('s' | 'S') => {
+                            self.resume(true);
+                            return Ok(None);
+                        },
+                        _ => reply = String::from("E01"),
+                    }
+                }
+            },
+            "q" => reply = self.query(arguments),
+            "Q" if arguments == "StartNoAckMode" => {
+                self.send("OK")?;
+                if let Some(client) = self.client.as_mut() {
+                    client.no_ack = true;
+                }
+                return Ok(None);
+            },
+            "H" | "T" => reply = String::from("OK"),
+            "D" => {
+                self.send("OK")?;
+                self.detach();
+                return Ok(Some(DebugEvent::Detached));
+            },
+            "k" => {
+                self.detach();
+                event = Some(DebugEvent::Kill);
+            },
+            _ => {},
+        }
+        if event.is_none() {
+            self.send(&reply)?;
+        }
+        Ok(event)
+    }
+
+    fn query(&self, query: &str) -> String {
+        let (name, arguments) = query.split_once(':').unwrap_or((query, ""));
+        match name {
+            "Supported" => format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", MAX_PACKET_SIZE),
+            "Attached" => String::from("1"),
+            "C" => String::from("QC1"),
+            "fThreadInfo" => String::from("m1"),
+            "sThreadInfo" => String::from("l"),
+            "Xfer" => match arguments.strip_prefix("features:read:target.xml:") {
+                Some(range) => {
+                    let (offset, length) = range.split_once(',').unwrap_or(("", ""));
+                    match (usize::from_str_radix(offset, 16), usize::from_str_radix(length, 16)) {
+                        (Ok(offset), Ok(length)) => xfer_chunk(&target_xml(), offset, length),
+                        _ => String::from("E01"),
+                    }
+                },
+                None => String::new(),
+            },
+            _ => String::new(),
+        }
+    }
+
+    fn resume(&mut self, step: bool) {
+        if step {
+            self.debugger.step();
+        } else {
+            self.debugger.resume();
+        }
+        if let Some(client) = self.client.as_mut() {
+            client.waiting = true;
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        match self.client.as_mut() {
+            Some(client) => client.send(data),
+            None => Ok(()),
+        }
+    }
+}
+
+impl DebugServer for GdbServer {
+    // accepts a new client when none is attached
+    fn poll(&mut self, chip8: &mut Chip8) -> Option<DebugEvent> {
+        if self.client.is_none() {
+            return match self.listener.accept() {
+                Ok((stream, address)) => self.attach(stream).ok().map(|_| DebugEvent::Attached(address)),
+                Err(_) => None,
+            };
+        }
+
+        match self.receive(chip8) {
+            Ok(event) => event,
+            Err(err) => {
+                crate::log!(Debugger, Warn, "gdb connection lost: {}", err);
+                self.detach();
+                Some(DebugEvent::Detached)
+            },
+        }
+    }
+
+    // without a client the machine runs freely
+    fn run(&mut self, chip8: &mut Chip8) -> Run {
+        let run = self.debugger.run(chip8);
+        if let Err(err) = self.report_stop() {
+            crate::log!(Debugger, Warn, "gdb connection lost: {}", err);
+            self.detach();
+        }
+        run
+    }
+
+    fn is_halted(&self) -> bool {
+        self.client.is_some() && !self.debugger.is_running()
+    }
+}
+
+impl Client {
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        crate::log!(Debugger, Trace, "gdb -> {}", data);
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        for byte in data.bytes() {
+            // binary data escapes the characters framing packets
+            if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+                packet.extend_from_slice(&[b'}', byte ^ 0x20]);
+            } else {
+                packet.push(byte);
+            }
+        }
+        let checksum = checksum_of(&packet[1..]);
+        packet.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+        // the socket is non-blocking, but replies are small enough for its buffer
+        self.stream.set_nonblocking(false)?;
+        let written = self.stream.write_all(&packet);
+        self.stream.set_nonblocking(true)?;
+        written
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    String::from(if result.is_some() { "OK" } else { "E01" })
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from("<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n");
+    xml.push_str("<target version=\"1.0\">\n<feature name=\"org.chip8.cpu\">\n");
+    for index in 0..16 {
+        xml.push_str(&format!("<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>\n", index));
+    }
+    xml.push_str("<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\n");
+    xml.push_str("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\n");
+    for name in ["sp", "dt", "st"] {
+        xml.push_str(&format!("<reg name=\"{}\" bitsize=\"8\" type=\"uint8\"/>\n", name));
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+// `length` bytes of `document` from `offset`, prefixed with l for the last part
+fn xfer_chunk(document: &str, offset: usize, length: usize) -> String {
+    let rest = document.get(offset.min(document.len())..).unwrap_or("");
+    if rest.len() <= length {
+        format!("l{}", rest)
+    } else {
+        format!("m{}", &rest[..length])
+    }
+}
+
+fn register_hex(state: &CpuState, index: usize) -> Option<String> {
+    let hex = match index {
+        0..=15 => format!("{:02x}", state.v[index]),
+        16 => format!("{:04x}", state.i),
+        17 => format!("{:04x}", state.pc),
+        18 => format!("{:02x}", state.stack.len()),
+        19 => format!("{:02x}", state.delay_timer),
+        20 => format!("{:02x}", state.sound_timer),
+        _ => return None,
+    };
+    Some(hex)
+}
+
+// an instruction address, both of its bytes in RAM
+fn is_pc(address: u16) -> bool {
+    (address as usize) < rom::RAM_SIZE - 1
+}
+
+fn encode_registers(state: &CpuState) -> String {
+    (0..REGISTER_COUNT).filter_map(|index| register_hex(state, index)).collect()
+}
+
+// the stack can only shrink, since there are no return addresses to grow it with
+fn set_register(state: &mut CpuState, index: usize, hex: &str) -> Option<()> {
+    let value = u16::from_str_radix(hex, 16).ok()?;
+    let byte = || u8::try_from(value).ok();
+    match index {
+        0..=15 => state.v[index] = byte()?,
+        16 => state.i = value,
+        17 if is_pc(value) => state.pc = value,
+        18 if value as usize <= state.stack.len() => state.stack.truncate(value as usize),
+        19 => state.delay_timer = byte()?,
+        20 => state.sound_timer = byte()?,
+        _ => return None,
+    }
+    Some(())
+}
+
+// `state` with the registers of a G packet, so the return addresses below the
+// stack depth are kept
+fn decode_registers(hex: &str, mut state: CpuState) -> Option<CpuState> {
+    let widths = (0..REGISTER_COUNT).map(|index| if index == 16 || index == 17 { 4 } else { 2 });
+    let mut offset = 0;
+    for (index, width) in widths.enumerate() {
+        let value = hex.get(offset..offset + width)?;
+        offset += width;
+        set_register(&mut state, index, value)?;
+    }
+    Some(state)
+}
+
+fn parse_address_length(arguments: &str) -> Option<(usize, usize)> {
+    let (address, length) = arguments.split_once(',')?;
+    Some((usize::from_str_radix(address, 16).ok()?, usize::from_str_radix(length, 16).ok()?))
+}
+
+// reads stop at the end of RAM
+fn read_memory(chip8: &Chip8, arguments: &str) -> Option<String> {
+    let (address, length) = parse_address_length(arguments)?;
+    if address >= rom::RAM_SIZE {
+        return None;
+    }
+    let end = address.checked_add(length)?.min(rom::RAM_SIZE);
+    Some((address..end).map(|address| format!("{:02x}", chip8.read_memory(address as u16))).collect())
+}
+
+fn write_memory(chip8: &mut Chip8, arguments: &str) -> Option<()> {
+    let (range, data) = arguments.split_once(':')?;
+    let (address, length) = parse_address_length(range)?;
+    if address.checked_add(length)? > rom::RAM_SIZE || data.len() != length.checked_mul(2)? {
+        return None;
+    }
+    let bytes = (0..length)
+        .map(|index| u8::from_str_radix(data.get(index * 2..index * 2 + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    for (offset, byte) in bytes.iter().enumerate() {
+        chip8.write_memory((address + offset) as u16, *byte);
+    }
+    Some(())
+}
+
+// software (0) and hardware (1) breakpoints, `type,address,kind`
+fn parse_breakpoint(arguments: &str) -> Option<u16> {
+    let mut parts = arguments.split(',');
+    match parts.next()? {
+        "0" | "1" => u16::from_str_radix(parts.next()?, 16).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_round_trip() {
+        let state = CpuState {
+            v: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 0xFF],
+            i: 0x0ABC,
+            pc: 0x0246,
+            stack: vec![0x0202, 0x0304],
+            delay_timer: 30,
+            sound_timer: 2,
+        };
+        let hex = encode_registers(&state);
+        assert_eq!(hex.len(), 46);
+        assert!(hex.starts_with("00010203"));
+        let base = CpuState { v: [0; 16], i: 0, pc: 0, stack: state.stack.clone(), delay_timer: 0, sound_timer: 0 };
+        assert_eq!(decode_registers(&hex, base.clone()), Some(state));
+
+        // the stack depth can drop return addresses but not make them up
+        let shallower = format!("{}01{}", &hex[..40], &hex[42..]);
+        assert_eq!(decode_registers(&shallower, base.clone()).unwrap().stack, vec![0x0202]);
+        let deeper = format!("{}03{}", &hex[..40], &hex[42..]);
+        assert_eq!(decode_registers(&deeper, base.clone()), None);
+
+        // the program counter stays on instructions in RAM
+        let outside = format!("{}0fff{}", &hex[..36], &hex[40..]);
+        assert_eq!(decode_registers(&outside, base.clone()), None);
+        let mut state = base;
+        assert_eq!(set_register(&mut state, 17, "0ffe"), Some(()));
+        assert_eq!(set_register(&mut state, 17, "ffff"), None);
+        assert_eq!(state.pc, 0x0FFE);
+    }
+
+    #[test]
+    fn memory_packets_stay_in_ram() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(write_memory(&mut chip8, "ffe,2:1234"), Some(()));
+        assert_eq!(read_memory(&chip8, "ffe,10").as_deref(), Some("1234"));
+        assert_eq!(write_memory(&mut chip8, "fff,2:1234"), None);
+        assert_eq!(read_memory(&chip8, "1000,1"), None);
+        assert_eq!(read_memory(&chip8, "1,ffffffffffffffff"), None);
+        assert_eq!(write_memory(&mut chip8, "ffffffffffffffff,1:00"), None);
+        assert_eq!(write_memory(&mut chip8, "1,ffffffffffffffff:00"), None);
+    }
+
+    struct TestClient {
+        stream: TcpStream,
+        received: Vec<u8>,
+    }
+
+    impl TestClient {
+        fn send(&mut self, data: &str) {
+            let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+            self.stream.write_all(packet.as_bytes()).unwrap();
+        }
+
+        // polls and runs the server until a whole reply arrives, checking the ack before it
+        fn reply(&mut self, server: &mut GdbServer, chip8: &mut Chip8, acked: bool) -> String {
+            for _ in 0..2000 {
+                server.poll(chip8);
+                server.run(chip8);
+                let mut buffer = [0; 1024];
+                match self.stream.read(&mut buffer) {
+                    Ok(read) => self.received.extend_from_slice(&buffer[..read]),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {},
+                    Err(err) => panic!("{}", err),
+                }
+                if let Some(end) = self.received.iter().position(|byte| *byte == b'#') {
+                    if self.received.len() >= end + 3 {
+                        let packet: Vec<u8> = self.received.drain(..end + 3).collect();
+                        let start = if acked { 1 } else { 0 };
+                        if acked {
+                            assert_eq!(packet[0], b'+');
+                        }
+                        assert_eq!(packet[start], b'$');
+                        let data = &packet[start + 1..end];
+                        assert_eq!(format!("{:02x}", checksum_of(data)).as_bytes(), &packet[end + 1..]);
+                        return String::from_utf8(data.to_vec()).unwrap();
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            panic!("no reply from the server");
+        }
+
+        // `c` and `s` are acked when they arrive and answered by a stop packet later
+        fn expect_ack(&mut self, server: &mut GdbServer, chip8: &mut Chip8) {
+            for _ in 0..2000 {
+                server.poll(chip8);
+                let mut buffer = [0; 1];
+                if let Ok(1) = self.stream.read(&mut buffer) {
+                    assert_eq!(buffer[0], b'+');
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            panic!("no ack from the server");
+        }
+    }
+
+    #[test]
+    fn clients_talk_to_the_stub_over_tcp() {
+        let mut chip8 = Chip8::new();
+        // 200: V0 = 5, 202: V0 += 1, 204: jump to 202
+        chip8.load_rom(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x02]).unwrap();
+        let mut server = GdbServer::bind(0).unwrap();
+        let stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        server.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut client = TestClient { stream, received: Vec::new() };
+        assert!(server.is_halted());
+
+        client.send("qSupported:swbreak+");
+        let supported = client.reply(&mut server, &mut chip8, true);
+        assert_eq!(supported, "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+");
+
+        client.send("g");
+        let registers = client.reply(&mut server, &mut chip8, true);
+        assert_eq!(&registers[36..40], "0200");
+
+        client.send("m200,6");
+        assert_eq!(client.reply(&mut server, &mut chip8, true), "600570011202");
+
+        client.send("Z0,204,2");
+        assert_eq!(client.reply(&mut server, &mut chip8, true), "OK");
+
+        client.send("c");
+        client.expect_ack(&mut server, &mut chip8);
+        assert_eq!(client.reply(&mut server, &mut chip8, false), "S05");
+        let state = chip8.cpu_state();
+        assert_eq!((state.pc, state.v[0]), (0x204, 6));
+
+        client.send("s");
+        client.expect_ack(&mut server, &mut chip8);
+        assert_eq!(client.reply(&mut server, &mut chip8, false), "S05");
+        assert_eq!(chip8.cpu_state().pc, 0x202);
+        assert!(server.is_halted());
+
+        // program counters past the last instruction in RAM are refused
+        client.send("P11=0fff");
+        assert_eq!(client.reply(&mut server, &mut chip8, true), "E01");
+        client.send("cffff");
+        assert_eq!(client.reply(&mut server, &mut chip8, true), "E01");
+        assert_eq!(chip8.cpu_state().pc, 0x202);
+        assert!(server.is_halted());
+    }
+}
@@ -0,0 +1,80 @@
+use super::{ButtonMapping, Buttons, InputSource};
+use minifb::Key;
+
+// A gamepad polled once per frame for the buttons held down
+pub trait Gamepad {
+    fn buttons(&mut self) -> Buttons;
+}
+
+// Gamepad buttons pressing CHIP-8 keys through a per game mapping
+pub struct GamepadInput<G: Gamepad> {
+    gamepad: G,
+    mapping: ButtonMapping,
+}
+
+impl<G: Gamepad> GamepadInput<G> {
+    pub fn new(gamepad: G, mapping: ButtonMapping) -> GamepadInput<G> {
+        GamepadInput { gamepad, mapping }
+    }
+}
+
+impl<G: Gamepad> InputSource for GamepadInput<G> {
+    fn keys_down(&mut self, _host_keys: &[Key]) -> u16 {
+        self.mapping.keys_down(self.gamepad.buttons())
+    }
+}
+
+#[cfg(feature = "gamepad")]
+pub use self::gilrs_gamepad::GilrsGamepad;
+
+#[cfg(feature = "gamepad")]
+mod gilrs_gamepad {
+    use super::super::{Button, Buttons};
+    use super::Gamepad;
+    use gilrs::{Axis, Gilrs};
+    use std::io;
+
+    // how far a stick has to be pushed to count as the D-pad
+    const STICK_THRESHOLD: f32 = 0.5;
+
+    // Every connected gamepad pressing the same buttons
+    pub struct GilrsGamepad {
+        gilrs: Gilrs,
+    }
+
+    impl GilrsGamepad {
+        pub fn new() -> io::Result<GilrsGamepad> {
+            let gilrs = Gilrs::new().map_err(|err| io::Error::other(err.to_string()))?;
+            Ok(GilrsGamepad { gilrs })
+        }
+    }
+
+    impl Gamepad for GilrsGamepad {
+        fn buttons(&mut self) -> Buttons {
+            // the events update the gamepads' state
+            while self.gilrs.next_event().is_some() {}
+
+            let mut buttons = Buttons::default();
+            for (_, gamepad) in self.gilrs.gamepads() {
+                let pressed = [
+                    (Button::Up, gamepad.is_pressed(gilrs::Button::DPadUp) || gamepad.value(Axis::LeftStickY) > STICK_THRESHOLD),
+                    (Button::Down, gamepad.is_pressed(gilrs::Button::DPadDown) || gamepad.value(Axis::LeftStickY) < -STICK_THRESHOLD),
+                    (Button::Left, gamepad.is_pressed(gilrs::Button::DPadLeft) || gamepad.value(Axis::LeftStickX) < -STICK_THRESHOLD),
+                    (Button::Right, gamepad.is_pressed(gilrs::Button::DPadRight) || gamepad.value(Axis::LeftStickX) > STICK_THRESHOLD),
+                    (Button::A, gamepad.is_pressed(gilrs::Button::South)),
+                    (Button::B, gamepad.is_pressed(gilrs::Button::East)),
+                    (Button::X, gamepad.is_pressed(gilrs::Button::West)),
+                    (Button::Y, gamepad.is_pressed(gilrs::Button::North)),
+                    (Button::Start, gamepad.is_pressed(gilrs::Button::Start)),
+                    (Button::Select, gamepad.is_pressed(gilrs::Button::Select)),
+                ];
+                for (button, is_pressed) in pressed {
+                    if is_pressed {
+                        buttons.press(button);
+                    }
+                }
+            }
+            buttons
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use super::InputSource;
+use crate::keymap::Keymap;
+use minifb::Key;
+
+// The frontend's keyboard through a keymap
+pub struct KeyboardInput {
+    keymap: Keymap,
+}
+
+impl KeyboardInput {
+    pub fn new(keymap: Keymap) -> KeyboardInput {
+        KeyboardInput { keymap }
+    }
+}
+
+impl InputSource for KeyboardInput {
+    fn keys_down(&mut self, host_keys: &[Key]) -> u16 {
+        self.keymap.keys_down(host_keys)
+    }
+}
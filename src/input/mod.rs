@@ -0,0 +1,192 @@
+use minifb::Key;
+use std::collections::HashMap;
+
+mod gamepad;
+mod keyboard;
+mod script;
+
+#[cfg(feature = "gamepad")]
+pub use self::gamepad::GilrsGamepad;
+pub use self::gamepad::{Gamepad, GamepadInput};
+pub use self::keyboard::KeyboardInput;
+pub use self::script::{InputScriptError, ScriptedGamepad};
+
+// A source of CHIP-8 keypad input, polled once per 60Hz frame
+pub trait InputSource {
+    // the CHIP-8 keys held down this frame, bit N for key N. `host_keys` are the
+    // keys the frontend reports held, for sources that read the keyboard
+    fn keys_down(&mut self, host_keys: &[Key]) -> u16;
+}
+
+// Gamepad buttons, named like the actions of the rom database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    X,
+    Y,
+    Start,
+    Select,
+}
+
+impl Button {
+    pub const ALL: [Button; 10] = [
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+        Button::A,
+        Button::B,
+        Button::X,
+        Button::Y,
+        Button::Start,
+        Button::Select,
+    ];
+
+    pub fn parse(name: &str) -> Option<Button> {
+        let button = match name.to_ascii_lowercase().as_str() {
+            "up" => Button::Up,
+            "down" => Button::Down,
+            "left" => Button::Left,
+            "right" => Button::Right,
+            "a" => Button::A,
+            "b" => Button::B,
+            "x" => Button::X,
+            "y" => Button::Y,
+            "start" => Button::Start,
+            "select" => Button::Select,
+            _ => return None,
+        };
+        Some(button)
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+// the buttons held down on a gamepad
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Buttons(u16);
+
+impl Buttons {
+    pub fn press(&mut self, button: Button) {
+        self.0 |= button.bit();
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.0 & button.bit() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+// Gamepad buttons to the CHIP-8 keys they press in one game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ButtonMapping {
+    keys: HashMap<Button, u8>,
+}
+
+impl ButtonMapping {
+    // the rom database's actions ("up", "a", ...) to CHIP-8 keys. Actions that
+    // are not buttons are ignored, no actions at all gives the default mapping
+    pub fn from_actions(actions: &HashMap<String, u8>) -> ButtonMapping {
+        let keys: HashMap<Button, u8> = actions
+            .iter()
+            .filter(|(_, key)| **key < 16)
+            .filter_map(|(action, key)| Some((Button::parse(action)?, *key)))
+            .collect();
+        if keys.is_empty() {
+            return ButtonMapping::default();
+        }
+        ButtonMapping { keys }
+    }
+
+    // false for keys past F, which are not bound
+    pub fn bind(&mut self, button: Button, chip8_key: u8) -> bool {
+        if chip8_key >= 16 {
+            return false;
+        }
+        self.keys.insert(button, chip8_key);
+        true
+    }
+
+    pub fn keys_down(&self, buttons: Buttons) -> u16 {
+        self.keys
+            .iter()
+            .filter(|(button, _)| buttons.is_pressed(**button))
+            .fold(0, |keys_down, (_, chip8_key)| keys_down | 1 << chip8_key)
+    }
+}
+
+impl Default for ButtonMapping {
+    // the D-pad on 2 4 6 8 with A on 5, the layout most games use
+    fn default() -> Self {
+        let keys = [(Button::Up, 0x2), (Button::Down, 0x8), (Button::Left, 0x4), (Button::Right, 0x6), (Button::A, 0x5)];
+        ButtonMapping { keys: keys.into_iter().collect() }
+    }
+}
+
+// Several sources pressing keys together
+#[derive(Default)]
+pub struct Inputs {
+    sources: Vec<Box<dyn InputSource>>,
+}
+
+impl Inputs {
+    pub fn new() -> Inputs {
+        Inputs { sources: Vec::new() }
+    }
+
+    pub fn push<S: InputSource + 'static>(&mut self, source: S) {
+        self.sources.push(Box::new(source));
+    }
+}
+
+impl InputSource for Inputs {
+    fn keys_down(&mut self, host_keys: &[Key]) -> u16 {
+        self.sources.iter_mut().fold(0, |keys_down, source| keys_down | source.keys_down(host_keys))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(buttons: &[Button]) -> Buttons {
+        let mut pressed = Buttons::default();
+        for button in buttons {
+            pressed.press(*button);
+        }
+        pressed
+    }
+
+    #[test]
+    fn buttons_press_their_bound_keys() {
+        let mut mapping = ButtonMapping::default();
+        assert_eq!(mapping.keys_down(pressed(&[Button::Up, Button::A])), 1 << 0x2 | 1 << 0x5);
+        assert!(mapping.bind(Button::Start, 0xF));
+        assert_eq!(mapping.keys_down(pressed(&[Button::Start, Button::B])), 1 << 0xF);
+    }
+
+    #[test]
+    fn keys_past_f_are_not_bound() {
+        let mut mapping = ButtonMapping::default();
+        assert!(!mapping.bind(Button::B, 16));
+        assert!(!mapping.bind(Button::Up, 0xFF));
+        assert_eq!(mapping.keys_down(pressed(&[Button::B, Button::Up])), 1 << 0x2);
+
+        let actions: HashMap<String, u8> = [("a", 7), ("b", 16), ("jump", 3)]
+            .iter()
+            .map(|(action, key)| (String::from(*action), *key))
+            .collect();
+        let mapping = ButtonMapping::from_actions(&actions);
+        assert_eq!(mapping.keys_down(pressed(&Button::ALL)), 1 << 7);
+    }
+}
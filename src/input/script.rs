@@ -0,0 +1,133 @@
+use super::{Button, Buttons, Gamepad};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// A virtual gamepad playing back a script, for driving games without hardware.
+// Every line is a frame number and the buttons held from that frame until the
+// next line, frames count from the first poll:
+//
+//     # wait for the title screen, then start and move left for a second
+//     0
+//     120 a
+//     122
+//     130 left
+//     190 left a
+
+#[derive(Debug)]
+pub enum InputScriptError {
+    Io(io::Error),
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for InputScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputScriptError::Io(err) => write!(f, "failed to read input script: {}", err),
+            InputScriptError::Invalid { line, message } => write!(f, "input script line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for InputScriptError {}
+
+impl From<io::Error> for InputScriptError {
+    fn from(err: io::Error) -> Self {
+        InputScriptError::Io(err)
+    }
+}
+
+pub struct ScriptedGamepad {
+    // (first frame, buttons held) in frame order
+    steps: Vec<(u64, Buttons)>,
+    next_step: usize,
+    buttons: Buttons,
+    frame: u64,
+}
+
+impl ScriptedGamepad {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ScriptedGamepad, InputScriptError> {
+        let text = fs::read_to_string(path)?;
+        ScriptedGamepad::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<ScriptedGamepad, InputScriptError> {
+        let mut steps: Vec<(u64, Buttons)> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |message: String| InputScriptError::Invalid { line: index + 1, message };
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let frame = match words.next() {
+                Some(frame) => frame.parse::<u64>().map_err(|_| invalid(format!("'{}' is not a frame number", frame)))?,
+                None => continue,
+            };
+            if steps.last().is_some_and(|(previous, _)| *previous >= frame) {
+                return Err(invalid(String::from("frames must increase from line to line")));
+            }
+
+            let mut buttons = Buttons::default();
+            for name in words {
+                let button = Button::parse(name).ok_or_else(|| invalid(format!("unknown button '{}'", name)))?;
+                buttons.press(button);
+            }
+            steps.push((frame, buttons));
+        }
+
+        Ok(ScriptedGamepad {
+            steps,
+            next_step: 0,
+            buttons: Buttons::default(),
+            frame: 0,
+        })
+    }
+
+    // true once every line has been played
+    pub fn is_finished(&self) -> bool {
+        self.next_step >= self.steps.len()
+    }
+}
+
+impl Gamepad for ScriptedGamepad {
+    fn buttons(&mut self) -> Buttons {
+        while let Some((frame, buttons)) = self.steps.get(self.next_step) {
+            if *frame > self.frame {
+                break;
+            }
+            self.buttons = *buttons;
+            self.next_step += 1;
+        }
+        self.frame += 1;
+        self.buttons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ButtonMapping, GamepadInput, InputSource};
+
+    #[test]
+    fn buttons_are_held_until_the_next_line() {
+        let mut gamepad = ScriptedGamepad::parse("# comment\n2 a left\n4\n").unwrap();
+        let held: Vec<bool> = (0..5).map(|_| gamepad.buttons().is_pressed(Button::A)).collect();
+        assert_eq!(held, vec![false, false, true, true, false]);
+        assert!(gamepad.is_finished());
+    }
+
+    #[test]
+    fn script_presses_mapped_chip8_keys() {
+        let gamepad = ScriptedGamepad::parse("0 up a").unwrap();
+        let mut input = GamepadInput::new(gamepad, ButtonMapping::default());
+        assert_eq!(input.keys_down(&[]), 1 << 0x2 | 1 << 0x5);
+    }
+
+    #[test]
+    fn invalid_lines_report_their_line_number() {
+        match ScriptedGamepad::parse("0\n\n5 jump") {
+            Err(InputScriptError::Invalid { line, .. }) => assert_eq!(line, 3),
+            _ => panic!("expected an invalid line"),
+        }
+        assert!(ScriptedGamepad::parse("5\n3").is_err());
+    }
+}
@@ -1,27 +1,39 @@
 pub struct Keyboard {
-    key_pressed: Option<u8>,
+    // bit N is set while CHIP-8 key N is held down
+    keys_down: u16,
 }
 
-impl<'a> Keyboard {
+impl Keyboard {
     pub fn new() -> Keyboard {
         Keyboard {
-            key_pressed: None,
+            keys_down: 0,
         }
     }
 
-    // Todo implement proper key handling
     pub fn is_key_pressed(&self, key_code:u8) -> bool {
-        match self.key_pressed {
-            Some(key) => key == key_code,
-            _=> false
-        }
+        self.keys_down & (1 << (key_code & 0xF)) != 0
+    }
+
+    pub fn set_keys_down(&mut self, keys_down: u16) {
+        self.keys_down = keys_down;
     }
 
-    pub fn set_key_pressed(&mut self, key: Option<u8>) {
-        self.key_pressed = key;
+    pub fn get_keys_down(&self) -> u16 {
+        self.keys_down
     }
 
+    // lowest numbered key that is held down
     pub fn get_key_pressed(&self) -> Option<u8> {
-        self.key_pressed
+        if self.keys_down == 0 {
+            None
+        } else {
+            Some(self.keys_down.trailing_zeros() as u8)
+        }
     }
-}
\ No newline at end of file
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Keyboard::new()
+    }
+}
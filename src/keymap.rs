@@ -0,0 +1,288 @@
+use minifb::Key;
+use std::collections::HashMap;
+
+// Host keys bound to the 16 CHIP-8 keys, laid out as
+//
+//     1 2 3 C
+//     4 5 6 D
+//     7 8 9 E
+//     A 0 B F
+//
+// Several host keys can press the same CHIP-8 key. Presets bind the keypad to
+// the left hand side of common keyboard layouts, or the numeric keypad.
+
+pub const PRESET_NAMES: [&str; 5] = ["qwerty", "azerty", "qwertz", "dvorak", "numpad"];
+
+// CHIP-8 keys in keypad order, row by row
+const KEYPAD: [u8; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<Key, u8>,
+}
+
+impl Keymap {
+    pub fn empty() -> Keymap {
+        Keymap { bindings: HashMap::new() }
+    }
+
+    pub fn preset(name: &str) -> Option<Keymap> {
+        use Key::*;
+        let keys: [Key; 16] = match name {
+            "qwerty" => [Key1, Key2, Key3, Key4, Q, W, E, R, A, S, D, F, Z, X, C, V],
+            "azerty" => [Key1, Key2, Key3, Key4, A, Z, E, R, Q, S, D, F, W, X, C, V],
+            "qwertz" => [Key1, Key2, Key3, Key4, Q, W, E, R, A, S, D, F, Y, X, C, V],
+            "dvorak" => [Key1, Key2, Key3, Key4, Apostrophe, Comma, Period, P, A, O, E, U, Semicolon, Q, J, K],
+            // the digits on their own keys, A to F on the keys around them
+            "numpad" => {
+                let mut keymap = Keymap::empty();
+                let keys = [
+                    NumPad0, NumPad1, NumPad2, NumPad3, NumPad4, NumPad5, NumPad6, NumPad7, NumPad8, NumPad9,
+                    NumPadSlash, NumPadAsterisk, NumPadMinus, NumPadPlus, NumPadEnter, NumPadDot,
+                ];
+                for (chip8_key, key) in keys.iter().enumerate() {
+                    keymap.bindings.insert(*key, chip8_key as u8);
+                }
+                return Some(keymap);
+            },
+            _ => return None,
+        };
+
+        let mut keymap = Keymap::empty();
+        for (chip8_key, key) in KEYPAD.iter().zip(keys.iter()) {
+            keymap.bindings.insert(*key, *chip8_key);
+        }
+        Some(keymap)
+    }
+
+    // replaces the host keys of `chip8_key`. A host key bound to another CHIP-8
+    // key moves to this one
+    pub fn bind(&mut self, chip8_key: u8, keys: &[Key]) {
+        self.bindings.retain(|_, bound| *bound != chip8_key);
+        for key in keys {
+            self.bindings.insert(*key, chip8_key);
+        }
+    }
+
+    pub fn chip8_key(&self, key: Key) -> Option<u8> {
+        self.bindings.get(&key).copied()
+    }
+
+    pub fn host_keys(&self, chip8_key: u8) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == chip8_key)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    // the CHIP-8 keys held down as a bitmask, bit N for key N
+    pub fn keys_down(&self, host_keys: &[Key]) -> u16 {
+        host_keys
+            .iter()
+            .filter_map(|key| self.chip8_key(*key))
+            .fold(0, |keys_down, chip8_key| keys_down | 1 << chip8_key)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::preset("qwerty").unwrap_or_else(Keymap::empty)
+    }
+}
+
+// A preset and individual key bindings applied on top of a keymap, as read from
+// the config file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyBindings {
+    pub preset: Option<String>,
+    pub keys: Vec<(u8, Vec<Key>)>,
+}
+
+impl KeyBindings {
+    pub fn apply(&self, keymap: &mut Keymap) {
+        if let Some(preset) = self.preset.as_deref().and_then(Keymap::preset) {
+            *keymap = preset;
+        }
+        for (chip8_key, keys) in self.keys.iter() {
+            keymap.bind(*chip8_key, keys);
+        }
+    }
+}
+
+// "0" to "F", case insensitive
+pub fn parse_chip8_key(name: &str) -> Option<u8> {
+    if name.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(name, 16).ok()
+}
+
+// the name of a host key as written in the config, case insensitive. Digits are
+// the number row, "NumPad0" to "NumPad9" the numeric keypad
+pub fn parse_key(name: &str) -> Option<Key> {
+    let name = name.to_ascii_lowercase();
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        let key = match c {
+            '0' => Key::Key0,
+            '1' => Key::Key1,
+            '2' => Key::Key2,
+            '3' => Key::Key3,
+            '4' => Key::Key4,
+            '5' => Key::Key5,
+            '6' => Key::Key6,
+            '7' => Key::Key7,
+            '8' => Key::Key8,
+            '9' => Key::Key9,
+            'a' => Key::A,
+            'b' => Key::B,
+            'c' => Key::C,
+            'd' => Key::D,
+            'e' => Key::E,
+            'f' => Key::F,
+            'g' => Key::G,
+            'h' => Key::H,
+            'i' => Key::I,
+            'j' => Key::J,
+            'k' => Key::K,
+            'l' => Key::L,
+            'm' => Key::M,
+            'n' => Key::N,
+            'o' => Key::O,
+            'p' => Key::P,
+            'q' => Key::Q,
+            'r' => Key::R,
+            's' => Key::S,
+            't' => Key::T,
+            'u' => Key::U,
+            'v' => Key::V,
+            'w' => Key::W,
+            'x' => Key::X,
+            'y' => Key::Y,
+            'z' => Key::Z,
+            '\'' => Key::Apostrophe,
+            ',' => Key::Comma,
+            '.' => Key::Period,
+            ';' => Key::Semicolon,
+            '/' => Key::Slash,
+            '-' => Key::Minus,
+            '=' => Key::Equal,
+            '[' => Key::LeftBracket,
+            ']' => Key::RightBracket,
+            '\\' => Key::Backslash,
+            '`' => Key::Backquote,
+            _ => return None,
+        };
+        return Some(key);
+    }
+
+    let key = match name.as_str() {
+        "numpad0" => Key::NumPad0,
+        "numpad1" => Key::NumPad1,
+        "numpad2" => Key::NumPad2,
+        "numpad3" => Key::NumPad3,
+        "numpad4" => Key::NumPad4,
+        "numpad5" => Key::NumPad5,
+        "numpad6" => Key::NumPad6,
+        "numpad7" => Key::NumPad7,
+        "numpad8" => Key::NumPad8,
+        "numpad9" => Key::NumPad9,
+        "numpaddot" => Key::NumPadDot,
+        "numpadslash" => Key::NumPadSlash,
+        "numpadasterisk" => Key::NumPadAsterisk,
+        "numpadminus" => Key::NumPadMinus,
+        "numpadplus" => Key::NumPadPlus,
+        "numpadenter" => Key::NumPadEnter,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "space" => Key::Space,
+        "enter" => Key::Enter,
+        "tab" => Key::Tab,
+        "backspace" => Key::Backspace,
+        "leftshift" => Key::LeftShift,
+        "rightshift" => Key::RightShift,
+        "leftctrl" => Key::LeftCtrl,
+        "rightctrl" => Key::RightCtrl,
+        "leftalt" => Key::LeftAlt,
+        "rightalt" => Key::RightAlt,
+        "apostrophe" => Key::Apostrophe,
+        "comma" => Key::Comma,
+        "period" => Key::Period,
+        "semicolon" => Key::Semicolon,
+        "slash" => Key::Slash,
+        "minus" => Key::Minus,
+        "equal" => Key::Equal,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_names_are_case_insensitive() {
+        assert_eq!(parse_key("w"), Some(Key::W));
+        assert_eq!(parse_key("W"), Some(Key::W));
+        assert_eq!(parse_key("7"), Some(Key::Key7));
+        assert_eq!(parse_key("NumPad7"), Some(Key::NumPad7));
+        assert_eq!(parse_key("LEFT"), Some(Key::Left));
+        assert_eq!(parse_key(";"), Some(Key::Semicolon));
+        assert_eq!(parse_key("Escape"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn chip8_keys_are_one_hex_digit() {
+        assert_eq!(parse_chip8_key("0"), Some(0x0));
+        assert_eq!(parse_chip8_key("a"), Some(0xA));
+        assert_eq!(parse_chip8_key("F"), Some(0xF));
+        assert_eq!(parse_chip8_key("10"), None);
+        assert_eq!(parse_chip8_key("G"), None);
+        assert_eq!(parse_chip8_key("+"), None);
+    }
+
+    #[test]
+    fn presets_cover_the_whole_keypad() {
+        for name in PRESET_NAMES {
+            let keymap = Keymap::preset(name).unwrap();
+            for chip8_key in 0..16 {
+                assert_eq!(keymap.host_keys(chip8_key).len(), 1, "{} key {:X}", name, chip8_key);
+            }
+        }
+        assert_eq!(Keymap::preset("colemak"), None);
+
+        let qwerty = Keymap::default();
+        assert_eq!(qwerty.chip8_key(Key::Key1), Some(0x1));
+        assert_eq!(qwerty.chip8_key(Key::V), Some(0xF));
+        assert_eq!(Keymap::preset("azerty").unwrap().chip8_key(Key::W), Some(0xA));
+        assert_eq!(Keymap::preset("numpad").unwrap().chip8_key(Key::NumPadDot), Some(0xF));
+    }
+
+    #[test]
+    fn binding_a_key_moves_it_from_its_old_chip8_key() {
+        let mut keymap = Keymap::default();
+        keymap.bind(0x5, &[Key::W, Key::Up]);
+        assert_eq!(keymap.chip8_key(Key::W), Some(0x5));
+        assert_eq!(keymap.chip8_key(Key::Up), Some(0x5));
+        // W was 0x5 already, Q is the key 0x4 keeps
+        assert_eq!(keymap.host_keys(0x4), vec![Key::Q]);
+
+        keymap.bind(0x4, &[Key::W]);
+        assert_eq!(keymap.host_keys(0x5), vec![Key::Up]);
+        assert_eq!(keymap.keys_down(&[Key::W, Key::Up, Key::Escape]), 1 << 0x4 | 1 << 0x5);
+    }
+
+    #[test]
+    fn bindings_apply_after_their_preset() {
+        let bindings = KeyBindings { preset: Some(String::from("azerty")), keys: vec![(0x0, vec![Key::Space])] };
+        let mut keymap = Keymap::default();
+        bindings.apply(&mut keymap);
+        assert_eq!(keymap.chip8_key(Key::Z), Some(0x5));
+        assert_eq!(keymap.host_keys(0x0), vec![Key::Space]);
+    }
+}
@@ -0,0 +1,33 @@
+pub mod browser;
+pub mod bus;
+pub mod cartridge;
+pub mod chip8;
+pub mod config;
+pub mod cpu;
+pub mod dap;
+pub mod database;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod font;
+pub mod gdb;
+pub mod input;
+pub mod keyboard;
+pub mod keymap;
+pub mod log;
+pub mod movie;
+pub mod octo;
+pub mod overlay;
+pub mod palette;
+pub mod persistence;
+pub mod quirks;
+pub mod ram;
+pub mod recording;
+pub mod renderer;
+pub mod rom;
+pub mod scaler;
+pub mod screenshot;
+pub mod speed;
+pub mod symbols;
+pub mod timing;
+pub mod watch;
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+// Leveled diagnostics with one level per category, all off by default. Levels
+// can be changed at any time, e.g. from the CHIP8_LOG environment variable:
+//     CHIP8_LOG=cpu=trace,display=debug
+//     CHIP8_LOG=info              (every category)
+// Messages go to stderr through the log! macro, or to the file given to set_file
+// while a frontend draws on the terminal.
+
+pub const ENV_VAR: &str = "CHIP8_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off = 0,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Cpu = 0,
+    Display,
+    Input,
+    Timers,
+    Debugger,
+    // memory, timers and keys as a whole
+    Bus,
+}
+
+pub const CATEGORIES: [Category; 6] =
+    [Category::Cpu, Category::Display, Category::Input, Category::Timers, Category::Debugger, Category::Bus];
+
+static LEVELS: [AtomicU8; 6] = [
+    AtomicU8::new(Level::Off as u8),
+    AtomicU8::new(Level::Off as u8),
+    AtomicU8::new(Level::Off as u8),
+    AtomicU8::new(Level::Off as u8),
+    AtomicU8::new(Level::Off as u8),
+    AtomicU8::new(Level::Off as u8),
+];
+
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Level> {
+        let level = match name.to_ascii_lowercase().as_str() {
+            "off" => Level::Off,
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            "trace" => Level::Trace,
+            _ => return None,
+        };
+        Some(level)
+    }
+
+    fn from_u8(value: u8) -> Level {
+        match value {
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            5 => Level::Trace,
+            _ => Level::Off,
+        }
+    }
+}
+
+impl Category {
+    pub fn parse(name: &str) -> Option<Category> {
+        CATEGORIES.iter().copied().find(|category| category.name() == name.to_ascii_lowercase())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Cpu => "cpu",
+            Category::Display => "display",
+            Category::Input => "input",
+            Category::Timers => "timers",
+            Category::Debugger => "debugger",
+            Category::Bus => "bus",
+        }
+    }
+}
+
+pub fn level(category: Category) -> Level {
+    Level::from_u8(LEVELS[category as usize].load(Ordering::Relaxed))
+}
+
+pub fn set_level(category: Category, level: Level) {
+    LEVELS[category as usize].store(level as u8, Ordering::Relaxed);
+}
+
+pub fn set_all_levels(level: Level) {
+    for category in CATEGORIES {
+        set_level(category, level);
+    }
+}
+
+// true if any category logs anything
+pub fn any_enabled() -> bool {
+    CATEGORIES.iter().any(|category| level(*category) != Level::Off)
+}
+
+pub fn enabled(category: Category, level: Level) -> bool {
+    level != Level::Off && level <= self::level(category)
+}
+
+// applies a comma separated list of `category=level` or bare `level` entries
+pub fn configure(spec: &str) -> Result<(), String> {
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        match entry.split_once('=') {
+            Some((category, level)) => {
+                let category = Category::parse(category.trim())
+                    .ok_or_else(|| format!("unknown log category '{}'", category))?;
+                let level = Level::parse(level.trim()).ok_or_else(|| format!("unknown log level '{}'", level))?;
+                set_level(category, level);
+            },
+            None => {
+                let level = Level::parse(entry).ok_or_else(|| format!("unknown log level '{}'", entry))?;
+                set_all_levels(level);
+            },
+        }
+    }
+    Ok(())
+}
+
+pub fn configure_from_env() -> Result<(), String> {
+    match std::env::var(ENV_VAR) {
+        Ok(spec) => configure(&spec),
+        Err(_) => Ok(()),
+    }
+}
+
+// messages go to `file` instead of stderr, None goes back to stderr
+pub fn set_file(file: Option<File>) {
+    *FILE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = file;
+}
+
+pub fn write(category: Category, level: Level, args: fmt::Arguments) {
+    let mut file = FILE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _ = match file.as_mut() {
+        Some(file) => writeln!(file, "[{:?} {}] {}", level, category.name(), args),
+        None => writeln!(std::io::stderr().lock(), "[{:?} {}] {}", level, category.name(), args),
+    };
+}
+
+#[macro_export]
+macro_rules! log {
+    ($category:ident, $level:ident, $($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Category::$category, $crate::log::Level::$level) {
+            $crate::log::write(
+                $crate::log::Category::$category,
+                $crate::log::Level::$level,
+                format_args!($($arg)+),
+            );
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_parse_in_any_case() {
+        assert_eq!(Level::parse("TRACE"), Some(Level::Trace));
+        assert_eq!(Level::parse("verbose"), None);
+        for category in CATEGORIES {
+            assert_eq!(Category::parse(&category.name().to_uppercase()), Some(category));
+        }
+        assert_eq!(Category::parse("sound"), None);
+    }
+
+    // the levels are global, so the whole sequence runs in one test
+    #[test]
+    fn specs_set_the_levels_in_order() {
+        configure("warn, cpu=trace ,,bus = debug").unwrap();
+        assert_eq!(level(Category::Cpu), Level::Trace);
+        assert_eq!(level(Category::Bus), Level::Debug);
+        assert_eq!(level(Category::Display), Level::Warn);
+        assert!(any_enabled());
+        assert!(enabled(Category::Display, Level::Error));
+        assert!(!enabled(Category::Display, Level::Info));
+        assert!(!enabled(Category::Display, Level::Off));
+
+        // a bare level later in the spec overrides the categories before it
+        configure("cpu=info,error").unwrap();
+        assert_eq!(level(Category::Cpu), Level::Error);
+
+        assert_eq!(configure("gpu=trace"), Err(String::from("unknown log category 'gpu'")));
+        assert_eq!(configure("cpu=loud"), Err(String::from("unknown log level 'loud'")));
+        assert_eq!(configure("loud"), Err(String::from("unknown log level 'loud'")));
+
+        configure("off").unwrap();
+        for category in CATEGORIES {
+            assert_eq!(level(category), Level::Off);
+        }
+        assert!(!any_enabled());
+    }
+}
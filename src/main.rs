@@ -1,111 +1,863 @@
-use chip8::Chip8;
-use minifb::{Key, KeyRepeat, Window, WindowOptions};
-use std::io::Read;
+use chip8_rust::browser::{self, RomBrowser};
+use chip8_rust::chip8::Chip8;
+use chip8_rust::config::{self, Config};
+use chip8_rust::database::RomDatabase;
+use chip8_rust::debugger::{DebugEvent, DebugServer, Run};
+use chip8_rust::dap::DapServer;
+use chip8_rust::gdb::GdbServer;
+use chip8_rust::input::{ButtonMapping, GamepadInput, InputSource, Inputs, KeyboardInput, ScriptedGamepad};
+use chip8_rust::keymap::{Keymap, PRESET_NAMES};
+use chip8_rust::log;
+use chip8_rust::movie::{Movie, MovieHeader, MoviePlayer, MovieRecorder};
+use chip8_rust::overlay::{Overlay, OverlayView};
+use chip8_rust::palette::{Palette, PALETTE_NAMES};
+use chip8_rust::persistence::{FlickerFilter, Persistence};
+use chip8_rust::recording::{self, Recorder};
+use chip8_rust::disasm;
+use chip8_rust::renderer::{self, DebugView, Frame, Glyphs, Renderer, RendererKind, RendererOptions};
+use chip8_rust::scaler::Scaler;
+use chip8_rust::screenshot;
+use chip8_rust::speed::{FrameClock, Speed};
+use chip8_rust::symbols::{self, Symbols};
+use chip8_rust::timing::TimingMode;
+use chip8_rust::watch::FileWatcher;
+use minifb::Key;
+use std::env;
 use std::fs::File;
-use std::time::{Instant, Duration};
-use crate::display::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-mod bus;
-mod chip8;
-mod cpu;
-mod display;
-mod keyboard;
-mod ram;
+struct Args {
+    // no rom starts the rom browser
+    rom_path: Option<String>,
+    // directory listed by the rom browser
+    browse: String,
+    // instructions per frame, overrides the rom database
+    ipf: Option<u32>,
+    timing: TimingMode,
+    renderer: RendererKind,
+    // file written by the image renderer
+    output: String,
+    // stop after this many frames
+    frames: Option<u64>,
+    // braille instead of half block characters in the tui
+    braille: bool,
+    config_path: Option<String>,
+    palette: Option<String>,
+    // comma separated hex colors applied on top of the palette
+    colors: Option<String>,
+    flicker: Option<FlickerFilter>,
+    scaler: Option<Scaler>,
+    // on or off over the config's setting, None keeps it
+    scanlines: Option<bool>,
+    grid: Option<bool>,
+    screenshot_scale: usize,
+    // keymap preset, overrides the config's
+    keymap: Option<String>,
+    // read connected gamepads, needs the gamepad feature
+    gamepad: bool,
+    // a virtual gamepad playing back this script
+    gamepad_script: Option<String>,
+    // recording started at launch, a .gif file or a directory of frames
+    record: Option<String>,
+    record_audio: bool,
+    record_scale: usize,
+    // seed of the random numbers, random if not given
+    seed: Option<u64>,
+    record_movie: Option<String>,
+    play_movie: Option<String>,
+    speed: Speed,
+    // start paused, for frame advance from the first frame
+    paused: bool,
+    // reload the rom whenever the file changes
+    watch: bool,
+    // FPS and IPS counters in the overlay
+    show_fps: bool,
+    // port of the GDB remote protocol stub
+    gdb: Option<u16>,
+    // port of the Debug Adapter Protocol server
+    dap: Option<u16>,
+    // symbol file of the rom on the command line, instead of the .sym next to it
+    symbols: Option<String>,
+}
+
+fn parse_args<I: IntoIterator<Item = String>>(arguments: I) -> Args {
+    let mut args = Args {
+        rom_path: None,
+        browse: String::from(browser::DEFAULT_DIRECTORY),
+        ipf: None,
+        timing: TimingMode::Fixed,
+        renderer: RendererKind::Window,
+        output: String::from("frame.ppm"),
+        frames: None,
+        braille: false,
+        config_path: None,
+        palette: None,
+        colors: None,
+        flicker: None,
+        scaler: None,
+        scanlines: None,
+        grid: None,
+        screenshot_scale: 1,
+        keymap: None,
+        gamepad: false,
+        gamepad_script: None,
+        record: None,
+        record_audio: false,
+        record_scale: 1,
+        seed: None,
+        record_movie: None,
+        play_movie: None,
+        speed: Speed::Normal,
+        paused: false,
+        watch: false,
+        show_fps: false,
+        gdb: None,
+        dap: None,
+        symbols: None,
+    };
+
+    let mut iter = arguments.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--ipf" => {
+                args.ipf = iter.next().and_then(|value| value.parse().ok());
+                if args.ipf.is_none() {
+                    usage();
+                }
+            },
+            "--log" => match iter.next() {
+                Some(spec) => {
+                    if let Err(err) = log::configure(&spec) {
+                        eprintln!("{}", err);
+                        usage();
+                    }
+                },
+                None => usage(),
+            },
+            "--timing" => {
+                args.timing = iter.next().as_deref().and_then(TimingMode::parse).unwrap_or_else(|| usage());
+            },
+            "--renderer" => {
+                args.renderer = iter.next().as_deref().and_then(RendererKind::parse).unwrap_or_else(|| usage());
+            },
+            "--output" => args.output = iter.next().unwrap_or_else(|| usage()),
+            "--frames" => {
+                args.frames = iter.next().and_then(|value| value.parse().ok());
+                if args.frames.is_none() {
+                    usage();
+                }
+            },
+            "--braille" => args.braille = true,
+            "--config" => args.config_path = Some(iter.next().unwrap_or_else(|| usage())),
+            "--palette" => args.palette = Some(iter.next().unwrap_or_else(|| usage())),
+            "--colors" => args.colors = Some(iter.next().unwrap_or_else(|| usage())),
+            "--flicker" => {
+                args.flicker = Some(iter.next().as_deref().and_then(FlickerFilter::parse).unwrap_or_else(|| usage()));
+            },
+            "--scaler" => {
+                args.scaler = Some(iter.next().as_deref().and_then(Scaler::parse).unwrap_or_else(|| usage()));
+            },
+            "--scanlines" => args.scanlines = Some(true),
+            "--no-scanlines" => args.scanlines = Some(false),
+            "--grid" => args.grid = Some(true),
+            "--no-grid" => args.grid = Some(false),
+            "--screenshot-scale" => {
+                args.screenshot_scale = iter.next().and_then(|value| value.parse().ok()).unwrap_or_else(|| usage());
+            },
+            "--keymap" => {
+                let preset = iter.next().unwrap_or_else(|| usage());
+                if Keymap::preset(&preset).is_none() {
+                    usage();
+                }
+                args.keymap = Some(preset);
+            },
+            "--gamepad" => args.gamepad = true,
+            "--gamepad-script" => args.gamepad_script = Some(iter.next().unwrap_or_else(|| usage())),
+            "--record" => args.record = Some(iter.next().unwrap_or_else(|| usage())),
+            "--record-audio" => args.record_audio = true,
+            "--record-scale" => {
+                args.record_scale = iter.next().and_then(|value| value.parse().ok()).unwrap_or_else(|| usage());
+            },
+            "--seed" => {
+                args.seed = Some(iter.next().and_then(|value| value.parse().ok()).unwrap_or_else(|| usage()));
+            },
+            "--record-movie" => args.record_movie = Some(iter.next().unwrap_or_else(|| usage())),
+            "--play-movie" => args.play_movie = Some(iter.next().unwrap_or_else(|| usage())),
+            "--speed" => args.speed = iter.next().as_deref().and_then(Speed::parse).unwrap_or_else(|| usage()),
+            "--paused" => args.paused = true,
+            "--watch" => args.watch = true,
+            "--show-fps" => args.show_fps = true,
+            "--gdb" => args.gdb = Some(iter.next().and_then(|value| value.parse().ok()).unwrap_or_else(|| usage())),
+            "--dap" => args.dap = Some(iter.next().and_then(|value| value.parse().ok()).unwrap_or_else(|| usage())),
+            "--symbols" => args.symbols = Some(iter.next().unwrap_or_else(|| usage())),
+            "--browse" => args.browse = iter.next().unwrap_or_else(|| usage()),
+            _ if arg.starts_with("--") => usage(),
+            _ => args.rom_path = Some(arg),
+        }
+    }
 
-fn get_chip8_keycode_for(key: Option<Key>) -> Option<u8> {
-    match key {
-        Some(Key::Key1) => Some(0x1),
-        Some(Key::Key2) => Some(0x2),
-        Some(Key::Key3) => Some(0x3),
-        Some(Key::Key4) => Some(0xC),
+    args
+}
 
-        Some(Key::Q) => Some(0x4),
-        Some(Key::W) => Some(0x5),
-        Some(Key::E) => Some(0x6),
-        Some(Key::R) => Some(0xD),
+fn usage() -> ! {
+    eprintln!("usage: chip8-rust [--ipf <instructions per frame>] [--timing fixed|vip] [--log <spec>]");
+    eprintln!("                  [--renderer window|terminal|tui|image|null] [--braille] [--output <file>]
+                  [--frames <n>] [--config <file>] [--palette <name>] [--colors <hex,...>]
+                  [--flicker off|blend|fade[:<decay>]] [--scaler nearest|bilinear|scale2x]
+                  [--[no-]scanlines] [--[no-]grid] [--screenshot-scale <n>]
+                  [--keymap qwerty|azerty|qwertz|dvorak|numpad] [--gamepad] [--gamepad-script <file>]
+                  [--record <file.gif|directory>] [--record-audio] [--record-scale <n>]
+                  [--seed <n>] [--record-movie <file>] [--play-movie <file>]
+                  [--speed 25|50|100|max] [--paused] [--watch] [--show-fps]
+                  [--gdb <port>] [--dap <port>] [--symbols <file>]
+                  [--browse <directory>] [rom]");
+    eprintln!("  without a rom the rom browser lists {}/, F1 returns to it", browser::DEFAULT_DIRECTORY);
+    eprintln!("  F2 soft resets, F3 hard resets, F4 reloads the rom from disk");
+    eprintln!("  F5 pauses, F6 advances one frame, F7 cycles slow motion, F8 toggles fast-forward");
+    eprintln!("  F10 shows and hides the FPS and IPS counters");
+    eprintln!("  F11 starts and stops recording a GIF to {}/", recording::DEFAULT_DIRECTORY);
+    eprintln!("  F12 saves a screenshot to {}/", screenshot::DEFAULT_DIRECTORY);
+    eprintln!("  --gdb and --dap wait for a debugger on 127.0.0.1:<port> and start halted,");
+    eprintln!("  --dap without a rom waits for the client to launch one");
+    eprintln!("  debuggers, the debug view and cpu=trace use the labels in the rom's .{} file", symbols::EXTENSION);
+    eprintln!("  palettes: {}", PALETTE_NAMES.join(", "));
+    eprintln!("  keymaps: {}, or [keys] in the config", PRESET_NAMES.join(", "));
+    eprintln!("  --log takes the same spec as ${}, e.g. cpu=trace,display=debug", log::ENV_VAR);
+    eprintln!("  with the tui renderer the log goes to {}", TUI_LOG_PATH);
+    process::exit(2);
+}
 
-        Some(Key::A) => Some(0x7),
-        Some(Key::S) => Some(0x8),
-        Some(Key::D) => Some(0x9),
-        Some(Key::F) => Some(0xE),
+const DISASSEMBLY_BEFORE_PC: u16 = 4;
+const DISASSEMBLY_LINES: u16 = 12;
 
-        Some(Key::Z) => Some(0xA),
-        Some(Key::X) => Some(0x0),
-        Some(Key::C) => Some(0xB),
-        Some(Key::V) => Some(0xF),
+fn debug_view(chip8: &Chip8) -> DebugView {
+    let cpu = chip8.cpu_state();
+    let start = cpu.pc.saturating_sub(DISASSEMBLY_BEFORE_PC * 2);
+    let disassembly = (0..DISASSEMBLY_LINES)
+        .map(|line| start.wrapping_add(line * 2))
+        .map(|address| {
+            let instruction = chip8.read_instruction(address);
+            (address, instruction, disasm::disassemble_with(instruction, chip8.symbols()))
+        })
+        .collect();
+    DebugView { cpu, disassembly }
+}
 
-        _ => None,
+// the command line wins over the config file, which wins over the rom's colors
+fn user_palette(args: &Args, config: &Config) -> Option<Palette> {
+    let mut palette = match args.palette.as_deref() {
+        Some(name) => Some(Palette::named(name).unwrap_or_else(|| {
+            eprintln!("unknown palette '{}', expected one of {}", name, PALETTE_NAMES.join(", "));
+            process::exit(2);
+        })),
+        None => config.palette,
+    };
+    if let Some(colors) = args.colors.as_deref() {
+        palette = Some(palette.unwrap_or_default().with_colors(colors).unwrap_or_else(|| {
+            eprintln!("--colors takes 1 to 4 comma separated hex colors");
+            process::exit(2);
+        }));
     }
+    palette
 }
 
-fn main() {
-    let mut file = File::open("data/TETRIS").unwrap();
-    let mut data = Vec::<u8>::new();
-    file.read_to_end(&mut data).expect("Failed to read rom fil/File Not Found");
-
-    let WIDTH = 640;
-    let HEIGHT = 320;
-
-    // A buffer than contains the color of each pixel of the screen in ARGB format
-    let mut buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
-
-    let mut window = Window::new(
-        "Rust - Chip8 Emulator | ESC to exit",
-        WIDTH,
-        HEIGHT,
-        WindowOptions::default(),
-    )
-    .unwrap_or_else(|e| {
-        panic!("{}", e);
+#[cfg(feature = "gamepad")]
+fn add_gamepad(inputs: &mut Inputs, buttons: ButtonMapping) {
+    match chip8_rust::input::GilrsGamepad::new() {
+        Ok(gamepad) => inputs.push(GamepadInput::new(gamepad, buttons)),
+        Err(err) => eprintln!("gamepads unavailable: {}", err),
+    }
+}
+
+#[cfg(not(feature = "gamepad"))]
+fn add_gamepad(_inputs: &mut Inputs, _buttons: ButtonMapping) {
+    eprintln!("gamepad support is not compiled in, build with --features gamepad");
+}
+
+// the symbols given with --symbols for the rom on the command line, or the ones
+// next to `rom_path` if there are any
+fn load_symbols(args: &Args, chip8: &mut Chip8, rom_path: &str) -> Result<(), String> {
+    chip8.set_symbols(None);
+    let given = args.symbols.as_deref().filter(|_| args.rom_path.as_deref() == Some(rom_path));
+    let path = match given {
+        Some(path) => PathBuf::from(path),
+        None => Path::new(rom_path).with_extension(symbols::EXTENSION),
+    };
+    if given.is_none() && (rom_path.is_empty() || !path.is_file()) {
+        return Ok(());
+    }
+    let symbols = Symbols::load(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    chip8.set_symbols(Some(symbols));
+    Ok(())
+}
+
+// waits for the client of --gdb or --dap
+fn start_debug_server(args: &Args) -> Option<Box<dyn DebugServer>> {
+    let server: io::Result<Box<dyn DebugServer>> = if let Some(port) = args.gdb {
+        GdbServer::bind(port).and_then(|mut server| {
+            eprintln!("waiting for gdb on {}", server.local_addr()?);
+            eprintln!("gdb attached from {}", server.accept()?);
+            Ok(Box::new(server) as Box<dyn DebugServer>)
+        })
+    } else if let Some(port) = args.dap {
+        DapServer::bind(port).and_then(|mut server| {
+            eprintln!("waiting for a DAP client on {}", server.local_addr()?);
+            eprintln!("DAP client attached from {}", server.accept()?);
+            Ok(Box::new(server) as Box<dyn DebugServer>)
+        })
+    } else {
+        return None;
+    };
+    Some(server.unwrap_or_else(|err| {
+        eprintln!("debugger: {}", err);
+        process::exit(1);
+    }))
+}
+
+// log messages go here while the tui draws on the terminal
+const TUI_LOG_PATH: &str = "chip8.log";
+
+// cleared while the renderer owns the terminal
+static ECHO_MESSAGES: AtomicBool = AtomicBool::new(true);
+
+// status messages go to the overlay, and to stderr unless the renderer draws there
+fn notify(overlay: &mut Overlay, message: String) {
+    if ECHO_MESSAGES.load(Ordering::Relaxed) {
+        eprintln!("{}", message);
+    }
+    overlay.message(message);
+}
+
+// the renderer is dropped first so a terminal frontend gives back the terminal
+fn exit_with_error(renderer: Box<dyn Renderer>, message: String) -> ! {
+    drop(renderer);
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn stop_recording(recorder: Recorder, overlay: &mut Overlay) {
+    let path = recorder.path().to_path_buf();
+    let frames = recorder.frames();
+    match recorder.finish() {
+        Ok(()) => notify(overlay, format!("recorded {} frames to {}", frames, path.display())),
+        Err(err) => notify(overlay, format!("failed to finish recording {}: {}", path.display(), err)),
+    }
+}
+
+// settings from the command line and config that win over the rom's, applied
+// again after hard resets, reloads and every rom launch
+fn apply_user_settings(chip8: &mut Chip8, args: &Args, palette: Option<Palette>) {
+    chip8.set_timing(args.timing);
+    if let Some(ipf) = args.ipf {
+        chip8.set_tickrate(ipf);
+    }
+    if let Some(seed) = args.seed {
+        chip8.set_seed(seed);
+    }
+    if let Some(palette) = palette {
+        chip8.set_palette(palette);
+    }
+}
+
+// keyboard through the keymap for `rom_path`, plus gamepads mapped for the rom
+fn build_inputs(args: &Args, config: &Config, chip8: &Chip8, rom_path: &str) -> Inputs {
+    let rom_file = Path::new(rom_path);
+    let rom_names = [
+        rom_file.file_name().map(|name| name.to_string_lossy().into_owned()),
+        rom_file.file_stem().map(|stem| stem.to_string_lossy().into_owned()),
+        chip8.rom_info().map(|info| info.title.clone()),
+    ];
+    let rom_names: Vec<&str> = rom_names.iter().flatten().map(String::as_str).collect();
+
+    let mut inputs = Inputs::new();
+    inputs.push(KeyboardInput::new(config.keymap(&rom_names)));
+    let buttons = chip8.rom_info().map(|info| ButtonMapping::from_actions(&info.keys)).unwrap_or_default();
+    if args.gamepad {
+        add_gamepad(&mut inputs, buttons.clone());
+    }
+    if let Some(path) = args.gamepad_script.as_deref() {
+        let gamepad = ScriptedGamepad::load(path).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        });
+        inputs.push(GamepadInput::new(gamepad, buttons));
+    }
+    inputs
+}
+
+// the roms in the browsed directory, with `rom_path` selected
+fn open_browser(args: &Args, chip8: &Chip8, rom_path: &str) -> RomBrowser {
+    let entries = browser::scan(&args.browse, chip8.database()).unwrap_or_else(|err| {
+        eprintln!("{}: {}", args.browse, err);
+        Vec::new()
     });
+    let mut browser = RomBrowser::new(&args.browse, entries);
+    browser.select_path(Path::new(rom_path));
+    browser
+}
 
-    //window.limit_update_rate(Some(std::time::Duration::from_micros(50)));
+fn main() {
+    if let Err(err) = log::configure_from_env() {
+        eprintln!("{}: {}", log::ENV_VAR, err);
+    }
+    let args = parse_args(env::args().skip(1));
+    // empty until the browser launches a rom
+    let mut rom_path = args.rom_path.clone().unwrap_or_default();
+    if args.gdb.is_some() && args.dap.is_some() {
+        eprintln!("--gdb and --dap can not be combined");
+        process::exit(2);
+    }
+    if (args.gdb.is_some() || args.dap.is_some()) && (args.play_movie.is_some() || args.record_movie.is_some()) {
+        eprintln!("movies can not be combined with a debugger");
+        process::exit(2);
+    }
+    if rom_path.is_empty() && (args.play_movie.is_some() || args.record_movie.is_some()) {
+        eprintln!("movies need a rom on the command line");
+        process::exit(2);
+    }
 
     let mut chip8 = Chip8::new();
-    chip8.load_rom(&data);
+    match RomDatabase::with_local_overrides() {
+        Ok(database) => chip8.set_database(database),
+        Err(err) => {
+            eprintln!("{}", err);
+            chip8.set_database(RomDatabase::bundled());
+        },
+    }
+    if !rom_path.is_empty() {
+        if let Err(err) = chip8.load_rom_file(&rom_path) {
+            eprintln!("{}: {}", rom_path, err);
+            process::exit(1);
+        }
+    }
+    if let Err(err) = load_symbols(&args, &mut chip8, &rom_path) {
+        eprintln!("{}", err);
+    }
+
+    let config = match args.config_path.as_deref() {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    };
+    let mut config = config.unwrap_or_else(|err| {
+        eprintln!("{}: {}", args.config_path.as_deref().unwrap_or(config::DEFAULT_PATH), err);
+        process::exit(1);
+    });
+    let palette = user_palette(&args, &config);
+    apply_user_settings(&mut chip8, &args, palette);
+
+    if let Some(preset) = args.keymap.clone() {
+        config.keys.preset = Some(preset);
+    }
+
+    // a movie brings the settings it was recorded with
+    let mut movie_player = args.play_movie.as_deref().map(|path| {
+        let movie = Movie::load(path).and_then(|movie| movie.header.apply(&mut chip8).map(|_| movie));
+        let movie = movie.unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        });
+        MoviePlayer::new(&movie)
+    });
+    let mut movie_recorder = args.record_movie.as_deref().map(|path| {
+        let recorder = MovieHeader::for_chip8(&chip8)
+            .map_err(|err| err.to_string())
+            .and_then(|header| MovieRecorder::create(path, &header).map_err(|err| err.to_string()));
+        recorder.unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        })
+    });
+
+    let mut inputs = build_inputs(&args, &config, &chip8, &rom_path);
+    let mut browser = (rom_path.is_empty() && args.dap.is_none()).then(|| open_browser(&args, &chip8, &rom_path));
 
-    println!("printing ram {:?}", chip8.print_ram());
+    let title = match chip8.rom_info() {
+        Some(info) => format!("Rust - Chip8 Emulator | {} | ESC to exit", info.title),
+        None => String::from("Rust - Chip8 Emulator | ESC to exit"),
+    };
+    let mut filters = config.filters;
+    if let Some(scaler) = args.scaler {
+        filters.scaler = scaler;
+    }
+    filters.scanlines = args.scanlines.unwrap_or(filters.scanlines);
+    filters.grid = args.grid.unwrap_or(filters.grid);
+    // before the renderer takes over the terminal
+    let mut debug_server = start_debug_server(&args);
+
+    let options = RendererOptions {
+        title,
+        output: args.output.clone(),
+        glyphs: if args.braille { Glyphs::Braille } else { Glyphs::HalfBlock },
+        filters,
+    };
+    let mut overlay = Overlay::new(args.show_fps);
+    let mut renderer = renderer::create(args.renderer, &options).unwrap_or_else(|err| {
+        eprintln!("failed to create renderer: {}", err);
+        process::exit(1);
+    });
+    if renderer.owns_terminal() {
+        ECHO_MESSAGES.store(false, Ordering::Relaxed);
+        if log::any_enabled() {
+            match File::create(TUI_LOG_PATH) {
+                Ok(file) => log::set_file(Some(file)),
+                Err(err) => notify(&mut overlay, format!("{}: {}", TUI_LOG_PATH, err)),
+            }
+        }
+    }
 
-    let mut last_key_update_time = Instant::now();
-    let mut last_instruction_run_time = Instant::now();
+    log!(Cpu, Trace, "ram after loading {}:\n{}", rom_path, chip8.ram_dump());
 
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        let keys_pressed = window.get_keys_pressed(KeyRepeat::Yes);
-        let mut key = None;
+    let (width, height) = chip8.display_size();
+    let mut framebuffer: Vec<u32> = Vec::with_capacity(width * height);
+    let mut persistence = Persistence::new(args.flicker.or(config.flicker).unwrap_or(FlickerFilter::Off));
+    let mut last_keys_down: u16 = 0;
+    let mut previous_host_keys: Vec<Key> = Vec::new();
+    let mut frames: u64 = 0;
+    let mut recorder = args.record.as_deref().map(|path| {
+        Recorder::start(path, args.record_audio, args.record_scale).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        })
+    });
+    let mut watcher = (args.watch && !rom_path.is_empty()).then(|| FileWatcher::new(&rom_path));
+    let mut clock = FrameClock::new(args.speed);
+    clock.set_paused(args.paused);
+    // a changed frame was not presented, so the next one redraws everything
+    let mut missed_present = false;
+    let mut browser_changed = true;
+    // the view last given to the renderer
+    let mut overlay_view = OverlayView::default();
 
-        if keys_pressed.len() > 0 {
-            key = Some(keys_pressed[0]);
+    while renderer.is_open() && args.frames.is_none_or(|limit| frames < limit) {
+        let host_keys = renderer.keys_down();
+        if host_keys.contains(&Key::Escape) {
+            break;
+        }
+        match debug_server.as_mut().and_then(|server| server.poll(&mut chip8)) {
+            Some(DebugEvent::Attached(client)) => notify(&mut overlay, format!("debugger attached from {}", client)),
+            Some(DebugEvent::Detached) => notify(&mut overlay, String::from("debugger detached")),
+            // the client loaded the rom, the rest is set up like a launch from the browser
+            Some(DebugEvent::Launched(path)) => {
+                apply_user_settings(&mut chip8, &args, palette);
+                inputs = build_inputs(&args, &config, &chip8, &path);
+                watcher = args.watch.then(|| FileWatcher::new(&path));
+                persistence = Persistence::new(persistence.filter());
+                missed_present = true;
+                browser = None;
+                notify(&mut overlay, format!("launched {}", path));
+                rom_path = path;
+            },
+            Some(DebugEvent::Kill) => break,
+            None => {},
         }
 
-        let diff_update_time = Instant::now() - last_key_update_time;
-        let chip8_key = get_chip8_keycode_for(key);
+        // hotkeys act once when pressed, not on every frame they are held
+        let pressed = |key: Key| host_keys.contains(&key) && !previous_host_keys.contains(&key);
 
-        if chip8_key.is_some() || diff_update_time >= Duration::from_millis(500) {
-            last_key_update_time = Instant::now();
-            chip8.set_key_pressed(chip8_key);
+        if let Some(active) = browser.as_mut() {
+            let mut launch = None;
+            for key in host_keys.iter().filter(|key| !previous_host_keys.contains(key)) {
+                browser_changed = true;
+                if let Some(entry) = active.handle_key(*key) {
+                    launch = Some(entry.path.to_string_lossy().into_owned());
+                }
+            }
+            previous_host_keys.clone_from(&host_keys);
+
+            if let Some(path) = launch {
+                match chip8.reload_rom_file(&path) {
+                    Ok(()) => {
+                        apply_user_settings(&mut chip8, &args, palette);
+                        rom_path = path;
+                        if let Err(err) = load_symbols(&args, &mut chip8, &rom_path) {
+                            notify(&mut overlay, err);
+                        }
+                        inputs = build_inputs(&args, &config, &chip8, &rom_path);
+                        watcher = args.watch.then(|| FileWatcher::new(&rom_path));
+                        persistence = Persistence::new(persistence.filter());
+                        missed_present = true;
+                        browser = None;
+                        notify(&mut overlay, format!("launched {}", rom_path));
+                        continue;
+                    },
+                    Err(err) => notify(&mut overlay, format!("{}: {}", path, err)),
+                }
+            }
+
+            if browser_changed {
+                let frame = active.frame(&chip8.palette());
+                if let Err(err) = renderer.render(&frame) {
+                    exit_with_error(renderer, format!("failed to render frame: {}", err));
+                }
+                browser_changed = false;
+            } else {
+                renderer.poll();
+            }
+            frames += 1;
+            clock.wait();
+            continue;
+        }
+        if pressed(Key::F1) {
+            browser = Some(open_browser(&args, &chip8, &rom_path));
+            browser_changed = true;
+            overlay_view = OverlayView::default();
+            renderer.set_overlay(overlay_view.clone());
+            previous_host_keys.clone_from(&host_keys);
+            // a movie only replays a session that stays on its rom
+            movie_player = None;
+            if let Some(recorder) = movie_recorder.take() {
+                if let Err(err) = recorder.finish() {
+                    notify(&mut overlay, format!("failed to finish movie: {}", err));
+                }
+            }
+            continue;
         }
 
-        let diff_update_time = Instant::now() - last_instruction_run_time;
-        if diff_update_time > Duration::from_millis(8) {
-            chip8.run_instruction();
-            last_instruction_run_time = Instant::now();
+        if pressed(Key::F12) {
+            let saved = screenshot::next_path(screenshot::DEFAULT_DIRECTORY, &rom_path, "png")
+                .and_then(|path| chip8.save_screenshot(&path, args.screenshot_scale).map(|_| path));
+            match saved {
+                Ok(path) => notify(&mut overlay, format!("screenshot saved to {}", path.display())),
+                Err(err) => notify(&mut overlay, format!("failed to save screenshot: {}", err)),
+            }
+        }
+        if pressed(Key::F11) {
+            recorder = match recorder.take() {
+                Some(active) => {
+                    stop_recording(active, &mut overlay);
+                    None
+                },
+                None => {
+                    let started = screenshot::next_path(recording::DEFAULT_DIRECTORY, &rom_path, "gif")
+                        .and_then(|path| Recorder::start(path, args.record_audio, args.record_scale));
+                    match started {
+                        Ok(active) => {
+                            notify(&mut overlay, format!("recording to {}", active.path().display()));
+                            Some(active)
+                        },
+                        Err(err) => {
+                            notify(&mut overlay, format!("failed to start recording: {}", err));
+                            None
+                        },
+                    }
+                },
+            };
+        }
+        let mut reset = None;
+        if pressed(Key::F2) {
+            chip8.soft_reset();
+            reset = Some(String::from("soft reset"));
+        }
+        if pressed(Key::F3) {
+            match chip8.hard_reset() {
+                Ok(()) => {
+                    apply_user_settings(&mut chip8, &args, palette);
+                    reset = Some(String::from("hard reset"));
+                },
+                Err(err) => notify(&mut overlay, format!("hard reset failed: {}", err)),
+            }
+        }
+        let file_changed = watcher.as_mut().is_some_and(|watcher| watcher.has_changed());
+        if pressed(Key::F4) || file_changed {
+            match chip8.reload_rom_file(&rom_path) {
+                Ok(()) => {
+                    apply_user_settings(&mut chip8, &args, palette);
+                    // an assembler writes the symbols with the rom
+                    if let Err(err) = load_symbols(&args, &mut chip8, &rom_path) {
+                        notify(&mut overlay, err);
+                    }
+                    reset = Some(format!("reloaded {}", rom_path));
+                },
+                Err(err) => notify(&mut overlay, format!("{}: {}", rom_path, err)),
+            }
+        }
+        if let Some(reset) = reset {
+            notify(&mut overlay, reset);
+            persistence = Persistence::new(persistence.filter());
+            missed_present = true;
+            // a movie only replays a session without resets
+            if movie_player.take().is_some() {
+                notify(&mut overlay, String::from("movie playback stopped"));
+            }
+            if let Some(recorder) = movie_recorder.take() {
+                let frames = recorder.frames();
+                match recorder.finish() {
+                    Ok(()) => notify(&mut overlay, format!("movie recording stopped after {} frames", frames)),
+                    Err(err) => notify(&mut overlay, format!("failed to finish movie: {}", err)),
+                }
+            }
         }
 
-        let chip8_buffer = chip8.get_display_buffer();
+        if pressed(Key::F5) || pressed(Key::Pause) {
+            clock.set_paused(!clock.is_paused());
+            notify(&mut overlay, String::from(if clock.is_paused() { "paused" } else { "resumed" }));
+        }
+        if pressed(Key::F6) {
+            clock.advance();
+        }
+        if pressed(Key::F7) {
+            clock.set_speed(clock.speed().slower());
+            notify(&mut overlay, format!("speed {}", clock.speed().name()));
+        }
+        if pressed(Key::F8) {
+            let speed = if clock.speed() == Speed::Unlimited { Speed::Normal } else { Speed::Unlimited };
+            clock.set_speed(speed);
+            notify(&mut overlay, format!("speed {}", speed.name()));
+        }
+        if pressed(Key::F10) {
+            overlay.set_show_stats(!overlay.shows_stats());
+        }
+        previous_host_keys.clone_from(&host_keys);
+
+        overlay.set_paused(clock.is_paused() || debug_server.as_ref().is_some_and(|server| server.is_halted()));
+        overlay.set_speed(clock.speed());
+        let view = overlay.view();
+        let overlay_changed = view != overlay_view;
+        if overlay_changed {
+            renderer.set_overlay(view.clone());
+            overlay_view = view;
+        }
+
+        if !clock.should_run() {
+            // the last frame again, to show the overlay's changes while paused
+            if overlay_changed && !framebuffer.is_empty() {
+                if let Err(err) = renderer.render(&Frame { width, height, pixels: &framebuffer }) {
+                    exit_with_error(renderer, format!("failed to render frame: {}", err));
+                }
+            } else {
+                renderer.poll();
+            }
+            clock.wait();
+            continue;
+        }
+
+        let keys_down = match movie_player.as_mut() {
+            Some(player) if !player.is_finished() => player.keys_down(&host_keys),
+            Some(_) => {
+                notify(&mut overlay, format!("movie finished after {} frames", frames));
+                movie_player = None;
+                inputs.keys_down(&host_keys)
+            },
+            None => inputs.keys_down(&host_keys),
+        };
+        if keys_down != last_keys_down {
+            log!(Input, Debug, "keys down {:016b}", keys_down);
+            last_keys_down = keys_down;
+        }
+        chip8.set_keys_down(keys_down);
+        if let Some(recorder) = movie_recorder.as_mut() {
+            if let Err(err) = recorder.push_frame(keys_down) {
+                notify(&mut overlay, format!("failed to record movie: {}", err));
+                movie_recorder = None;
+            }
+        }
 
-        for y in 0..HEIGHT {
-            for x in 0..WIDTH {
-                let index = Display::get_index_from_coords(x / 10, y / 10);
-                let pixel = chip8_buffer[index];
-                let color_pixel = match pixel {
-                    0 => 0x0,
-                    1 => 0xffffff,
-                    _ => unreachable!(),
-                };
-                buffer[y * WIDTH + x] = color_pixel;
+        // the first frame is always presented so the output is never blank
+        let run = match debug_server.as_mut() {
+            Some(server) => server.run(&mut chip8),
+            None => {
+                chip8.run_frame();
+                Run::Frame
+            },
+        };
+        let changed = match run {
+            Run::Frame => !chip8.dirty_region().is_empty(),
+            // a frame stopped midway has no dirty region yet, so all of it is redrawn
+            Run::Stopped => {
+                missed_present = true;
+                true
+            },
+            Run::Idle => false,
+        };
+        if run != Run::Idle {
+            overlay.count_frame(chip8.instructions_executed());
+        }
+        if renderer.shows_debug_view() {
+            renderer.set_debug_view(debug_view(&chip8));
+        }
+        // debug views redraw every frame since the registers change anyway
+        let wants_present = changed
+            || frames == 0
+            || missed_present
+            || overlay_changed
+            || renderer.shows_debug_view()
+            || !persistence.is_settled();
+        let present = wants_present && clock.should_present();
+        // recordings keep every frame, fast-forward or not
+        if present || recorder.is_some() {
+            persistence.apply(chip8.get_display_buffer(), &chip8.palette(), &mut framebuffer);
+        }
+        if present {
+            let frame = Frame { width, height, pixels: &framebuffer };
+            // flicker filters change pixels the program did not draw
+            let result = if frames == 0 || missed_present || persistence.filter() != FlickerFilter::Off {
+                renderer.render(&frame)
+            } else {
+                renderer.render_region(&frame, chip8.dirty_region())
+            };
+            if let Err(err) = result {
+                exit_with_error(renderer, format!("failed to render frame: {}", err));
+            }
+            missed_present = false;
+        } else {
+            // nothing was drawn, or fast-forward skipped it, only poll for input
+            missed_present |= changed || overlay_changed;
+            renderer.poll();
+        }
+        if let Some(active) = recorder.as_mut() {
+            let frame = Frame { width, height, pixels: &framebuffer };
+            if let Err(err) = active.push_frame(&frame, chip8.is_sound_playing()) {
+                notify(&mut overlay, format!("failed to record frame: {}", err));
+                recorder = None;
             }
         }
+        frames += 1;
+        clock.wait();
+    }
+
+    // the last messages are printed after the terminal is given back
+    drop(renderer);
+    ECHO_MESSAGES.store(true, Ordering::Relaxed);
+    if let Some(active) = recorder {
+        stop_recording(active, &mut overlay);
+    }
+    if let Some(recorder) = movie_recorder {
+        let frames = recorder.frames();
+        match recorder.finish() {
+            Ok(()) => eprintln!("recorded a movie of {} frames", frames),
+            Err(err) => eprintln!("failed to finish movie: {}", err),
+        }
+    }
+}
 
-        window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(arguments: &[&str]) -> Args {
+        parse_args(arguments.iter().map(|argument| String::from(*argument)))
+    }
+
+    #[test]
+    fn browse_takes_a_directory() {
+        let args = parse(&["--browse", "games"]);
+        assert_eq!(args.browse, "games");
+        assert_eq!(args.rom_path, None);
+
+        let args = parse(&[]);
+        assert_eq!(args.browse, browser::DEFAULT_DIRECTORY);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn options_and_the_rom_mix_in_any_order() {
+        let args = parse(&["pong.ch8", "--timing", "vip", "--no-grid", "--ipf", "20", "--scanlines"]);
+        assert_eq!(args.rom_path.as_deref(), Some("pong.ch8"));
+        assert_eq!(args.timing, TimingMode::CosmacVip);
+        assert_eq!(args.ipf, Some(20));
+        assert_eq!(args.scanlines, Some(true));
+        assert_eq!(args.grid, Some(false));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::chip8::Chip8;
+use crate::input::InputSource;
+use crate::quirks::Quirks;
+use crate::timing::TimingMode;
+use minifb::Key;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Movies record the keypad state of every frame from power on, with the rom and
+// the settings that decide how it runs. Playing one back on the same rom gives
+// the same session, since timers and random numbers are deterministic:
+//
+//     chip8-movie 1
+//     rom 5a8d1ad16d7f7e6d3a4b0c2e4f3f5d1f0a9b8c7d
+//     seed 1234
+//     load_address 0x200
+//     tickrate 20
+//     timing fixed
+//     quirks vf_reset clip vblank
+//     frames
+//     0000 120
+//     0020 8
+//     0000
+//
+// Each frame line is the keys held as a hex bitmask, bit N for key N, and how
+// many frames in a row they were held if more than one.
+
+const MAGIC: &str = "chip8-movie 1";
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    Invalid { line: usize, message: String },
+    // the movie was recorded with another rom
+    RomMismatch { expected: String, actual: String },
+    // the movie was recorded with the rom loaded at another address
+    LoadAddressMismatch { expected: u16 },
+    NoRom,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Io(err) => write!(f, "failed to access movie: {}", err),
+            MovieError::Invalid { line, message } => write!(f, "invalid movie at line {}: {}", line, message),
+            MovieError::RomMismatch { expected, actual } => {
+                write!(f, "movie was recorded with rom {} but {} is loaded", expected, actual)
+            },
+            MovieError::LoadAddressMismatch { expected } => {
+                write!(f, "movie was recorded with the rom loaded at {:#05X}", expected)
+            },
+            MovieError::NoRom => write!(f, "no rom is loaded"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(err: io::Error) -> Self {
+        MovieError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MovieHeader {
+    pub rom_sha1: String,
+    pub seed: u64,
+    pub load_address: u16,
+    pub tickrate: u32,
+    pub timing: TimingMode,
+    pub quirks: Quirks,
+}
+
+impl MovieHeader {
+    // the settings of `chip8` with its rom loaded
+    pub fn for_chip8(chip8: &Chip8) -> Result<MovieHeader, MovieError> {
+        Ok(MovieHeader {
+            rom_sha1: String::from(chip8.rom_sha1().ok_or(MovieError::NoRom)?),
+            seed: chip8.seed(),
+            load_address: chip8.load_address(),
+            tickrate: chip8.tickrate(),
+            timing: chip8.timing(),
+            quirks: chip8.quirks(),
+        })
+    }
+
+    // checks that `chip8` has the movie's rom loaded and restores the settings.
+    // The rom is expected at the movie's load address already
+    pub fn apply(&self, chip8: &mut Chip8) -> Result<(), MovieError> {
+        let actual = chip8.rom_sha1().ok_or(MovieError::NoRom)?;
+        if actual != self.rom_sha1 {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_sha1.clone(),
+                actual: String::from(actual),
+            });
+        }
+        if chip8.load_address() != self.load_address {
+            return Err(MovieError::LoadAddressMismatch { expected: self.load_address });
+        }
+        chip8.set_seed(self.seed);
+        chip8.set_tickrate(self.tickrate);
+        chip8.set_timing(self.timing);
+        chip8.set_quirks(self.quirks);
+        Ok(())
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", MAGIC)?;
+        writeln!(writer, "rom {}", self.rom_sha1)?;
+        writeln!(writer, "seed {}", self.seed)?;
+        writeln!(writer, "load_address {:#05X}", self.load_address)?;
+        writeln!(writer, "tickrate {}", self.tickrate)?;
+        writeln!(writer, "timing {}", self.timing.name())?;
+        writeln!(writer, "quirks {}", self.quirks.enabled().join(" "))?;
+        writeln!(writer, "frames")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub header: MovieHeader,
+    // the keys held and for how many frames in a row, as in the file. Runs are
+    // not expanded, so a long count costs no memory
+    pub runs: Vec<(u16, u64)>,
+}
+
+impl Movie {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Movie, MovieError> {
+        let text = fs::read_to_string(path)?;
+        Movie::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, MovieError> {
+        let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+        let invalid = |line: usize, message: &str| MovieError::Invalid { line, message: String::from(message) };
+
+        match lines.next() {
+            Some((_, MAGIC)) => {},
+            _ => return Err(invalid(1, "not a chip8 movie")),
+        }
+
+        let (mut rom_sha1, mut seed, mut load_address) = (None, None, None);
+        let (mut tickrate, mut timing, mut quirks) = (None, None, None);
+        let mut last_line = 1;
+        for (number, line) in lines.by_ref() {
+            last_line = number;
+            if line == "frames" {
+                break;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            match name {
+                "rom" => rom_sha1 = Some(value.to_lowercase()),
+                "seed" => seed = Some(value.parse().map_err(|_| invalid(number, "seed must be a number"))?),
+                "load_address" => {
+                    let address = u16::from_str_radix(value.trim_start_matches("0x").trim_start_matches("0X"), 16);
+                    load_address = Some(address.map_err(|_| invalid(number, "load_address must be hex"))?);
+                },
+                "tickrate" => tickrate = Some(value.parse().map_err(|_| invalid(number, "tickrate must be a number"))?),
+                "timing" => timing = Some(TimingMode::parse(value).ok_or_else(|| invalid(number, "unknown timing"))?),
+                "quirks" => {
+                    quirks = Some(Quirks::from_names(value.split_whitespace()).ok_or_else(|| invalid(number, "unknown quirk"))?);
+                },
+                // newer versions may add settings this one does not know
+                _ => {},
+            }
+        }
+        let missing = |name: &str| MovieError::Invalid { line: last_line, message: format!("missing {}", name) };
+        let header = MovieHeader {
+            rom_sha1: rom_sha1.ok_or_else(|| missing("rom"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            load_address: load_address.unwrap_or(crate::cpu::PROGRAM_START),
+            tickrate: tickrate.ok_or_else(|| missing("tickrate"))?,
+            timing: timing.unwrap_or_default(),
+            quirks: quirks.unwrap_or_default(),
+        };
+
+        let mut runs = Vec::new();
+        for (number, line) in lines {
+            if line.is_empty() {
+                continue;
+            }
+            let (keys, count) = line.split_once(' ').unwrap_or((line, "1"));
+            let keys = u16::from_str_radix(keys, 16).map_err(|_| invalid(number, "keys must be a hex bitmask"))?;
+            let count: u64 = count.trim().parse().map_err(|_| invalid(number, "frame count must be a number"))?;
+            if count > 0 {
+                runs.push((keys, count));
+            }
+        }
+
+        Ok(Movie { header, runs })
+    }
+
+    // the length of the movie in frames
+    pub fn frames(&self) -> u64 {
+        self.runs.iter().fold(0, |frames, (_, count)| frames.saturating_add(*count))
+    }
+}
+
+// Writes a movie while the session runs, so it survives the emulator exiting
+pub struct MovieRecorder {
+    file: BufWriter<File>,
+    // keys of the run of identical frames not written yet, and its length
+    run: Option<(u16, u64)>,
+    frames: u64,
+}
+
+impl MovieRecorder {
+    pub fn create<P: AsRef<Path>>(path: P, header: &MovieHeader) -> io::Result<MovieRecorder> {
+        let mut file = BufWriter::new(File::create(path)?);
+        header.write(&mut file)?;
+        Ok(MovieRecorder { file, run: None, frames: 0 })
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn push_frame(&mut self, keys_down: u16) -> io::Result<()> {
+        self.frames += 1;
+        match self.run.as_mut() {
+            Some((keys, count)) if *keys == keys_down => {
+                *count += 1;
+                Ok(())
+            },
+            _ => {
+                self.write_run()?;
+                self.run = Some((keys_down, 1));
+                Ok(())
+            },
+        }
+    }
+
+    fn write_run(&mut self) -> io::Result<()> {
+        match self.run.take() {
+            Some((keys, 1)) => writeln!(self.file, "{:04X}", keys),
+            Some((keys, count)) => writeln!(self.file, "{:04X} {}", keys, count),
+            None => Ok(()),
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write_run()?;
+        self.file.flush()
+    }
+}
+
+// Plays back a movie's keys, one frame per poll, ignoring the host keys
+pub struct MoviePlayer {
+    runs: Vec<(u16, u64)>,
+    // the run being played and the frames of it already played
+    run: usize,
+    played: u64,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: &Movie) -> MoviePlayer {
+        MoviePlayer {
+            runs: movie.runs.clone(),
+            run: 0,
+            played: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.run >= self.runs.len()
+    }
+}
+
+impl InputSource for MoviePlayer {
+    // no keys once the movie is over
+    fn keys_down(&mut self, _host_keys: &[Key]) -> u16 {
+        let (keys, count) = match self.runs.get(self.run) {
+            Some(run) => *run,
+            None => return 0,
+        };
+        self.played += 1;
+        if self.played >= count {
+            self.run += 1;
+            self.played = 0;
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_movie_parses_back() {
+        let header = MovieHeader {
+            rom_sha1: String::from("f100197f0f2f05b4f3c8c31ab9c2c3930d3e9571"),
+            seed: 42,
+            load_address: 0x200,
+            tickrate: 15,
+            timing: TimingMode::CosmacVip,
+            quirks: Quirks { clip: true, vblank: true, ..Quirks::default() },
+        };
+        let frames = [0, 0, 0, 0x20, 0x20, 0, 0x8001];
+
+        let path = std::env::temp_dir().join(format!("chip8-movie-test-{}.txt", std::process::id()));
+        let mut recorder = MovieRecorder::create(&path, &header).unwrap();
+        for keys in frames {
+            recorder.push_frame(keys).unwrap();
+        }
+        recorder.finish().unwrap();
+        let movie = Movie::load(&path);
+        let _ = fs::remove_file(&path);
+
+        let movie = movie.unwrap();
+        assert_eq!(movie.header, header);
+        assert_eq!(movie.runs, [(0, 3), (0x20, 2), (0, 1), (0x8001, 1)]);
+        assert_eq!(movie.frames(), frames.len() as u64);
+
+        let mut player = MoviePlayer::new(&movie);
+        let played: Vec<u16> = (0..frames.len()).map(|_| player.keys_down(&[])).collect();
+        assert_eq!(played, frames);
+        assert!(player.is_finished());
+        assert_eq!(player.keys_down(&[]), 0);
+    }
+
+    #[test]
+    fn long_runs_are_not_expanded() {
+        let text = "chip8-movie 1\nrom abc\nseed 1\ntickrate 10\nframes\n0001 18446744073709551615\n0002 0\n";
+        let movie = Movie::parse(text).unwrap();
+        assert_eq!(movie.runs, [(0x1, u64::MAX)]);
+
+        let mut player = MoviePlayer::new(&movie);
+        assert_eq!(player.keys_down(&[]), 0x1);
+        assert!(!player.is_finished());
+        assert!(matches!(
+            Movie::parse("chip8-movie 1\nrom abc\nseed 1\ntickrate 10\nframes\n0001 -1\n"),
+            Err(MovieError::Invalid { line: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn movies_need_the_magic_line() {
+        assert!(matches!(Movie::parse("rom abc\n"), Err(MovieError::Invalid { line: 1, .. })));
+    }
+}
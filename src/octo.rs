@@ -0,0 +1,974 @@
+use crate::cpu::PROGRAM_START;
+use crate::rom::RAM_SIZE;
+use crate::symbols::Symbols;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+// Assembler for Octo, the language Octo cartridges carry their programs in:
+//
+//     :const SPEED 2
+//     : main
+//         v0 := 5
+//         loop
+//             draw_paddle
+//             v0 -= SPEED
+//             if v0 > 1 then
+//         again
+//
+// It covers the CHIP-8, SCHIP and XO-CHIP instructions, Octo's control flow,
+// :const, :alias, :unpack, :next, :org, :byte, :pointer, :call, :macro, :calc
+// and :assert. Expressions in :calc have no precedence and are evaluated from
+// right to left like Octo's. Numbers where a statement is expected are data
+// bytes. String modes are not supported. The labels and the address of the
+// first byte assembled from each line make the symbols of the program.
+
+// vF receives the flag of the subtraction that implements <, >, <= and >=
+const COMPARE_TEMP: &str = "compare-temp";
+// the registers :unpack loads
+const UNPACK_HI: &str = "unpack-hi";
+const UNPACK_LO: &str = "unpack-lo";
+// a macro calling itself would expand forever
+const MAX_EXPANSIONS: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: u32,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+// the program bytes, to load at 0x200
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_with_symbols(source, Path::new("")).map(|(rom, _)| rom)
+}
+
+// the program bytes and their symbols, with the lines of `source` under `path`
+pub fn assemble_with_symbols(source: &str, path: &Path) -> Result<(Vec<u8>, Symbols), AssembleError> {
+    let mut assembler = Assembler::new(tokenize(source));
+    assembler.run()?;
+
+    let mut symbols = Symbols::default();
+    // one name per address, the first in alphabetical order
+    let mut labels: Vec<(&String, &u16)> = assembler.labels.iter().collect();
+    labels.sort_by_key(|(name, address)| (**address, name.as_str()));
+    for (name, address) in labels {
+        if symbols.label(*address).is_none() {
+            symbols.add_label(*address, name);
+        }
+    }
+    for (line, address) in assembler.lines {
+        symbols.add_line(address, path.to_path_buf(), line);
+    }
+    Ok((assembler.rom, symbols))
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: u32,
+}
+
+// tokens are separated by whitespace, # starts a comment and quoted strings
+// are one token
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let mut rest = line.trim_start();
+        while !rest.is_empty() && !rest.starts_with('#') {
+            let end = match rest.strip_prefix('"') {
+                Some(string) => string.find('"').map_or(rest.len(), |end| end + 2),
+                None => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+            };
+            tokens.push(Token { text: String::from(&rest[..end]), line: index as u32 + 1 });
+            rest = rest[end..].trim_start();
+        }
+    }
+    tokens
+}
+
+// how an address that is only known at the end is filled in
+#[derive(Debug, Clone, Copy)]
+enum Patch {
+    // the low 12 bits of the instruction
+    Low12,
+    // 16 bits
+    Long,
+    // the two 6XNN instructions of :unpack, with this high nibble
+    Unpack(u8),
+    UnpackLong,
+}
+
+struct Forward {
+    name: String,
+    address: u16,
+    patch: Patch,
+    line: u32,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+// the right side of a comparison
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+    None,
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    position: usize,
+    // line of the last token read
+    line: u32,
+    // memory from 0x200 on
+    rom: Vec<u8>,
+    here: u16,
+    // the first two bytes hold a jump to main, unless main comes first
+    jump_slot: bool,
+    labels: HashMap<String, u16>,
+    // source line to the first address assembled from it
+    lines: BTreeMap<u32, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    forwards: Vec<Forward>,
+    // open loops: where they start and the jumps of their whiles
+    loops: Vec<(u16, Vec<u16>)>,
+    // jumps of `if ... begin` and `else` waiting for their target
+    branches: Vec<u16>,
+}
+
+impl Assembler {
+    fn new(tokens: Vec<Token>) -> Assembler {
+        let aliases = [(COMPARE_TEMP, 0xF), (UNPACK_HI, 0x0), (UNPACK_LO, 0x1)];
+        Assembler {
+            tokens,
+            position: 0,
+            line: 1,
+            rom: Vec::new(),
+            here: PROGRAM_START,
+            jump_slot: true,
+            labels: HashMap::new(),
+            lines: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: aliases.iter().map(|(name, register)| (String::from(*name), *register)).collect(),
+            macros: HashMap::new(),
+            expansions: 0,
+            forwards: Vec::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+        }
+    }
+
+    fn run(&mut self) -> Result<(), AssembleError> {
+        self.op(0)?;
+        while self.position < self.tokens.len() {
+            self.statement()?;
+        }
+        if !self.loops.is_empty() {
+            return Err(self.error("loop without again"));
+        }
+        if !self.branches.is_empty() {
+            return Err(self.error("begin without end"));
+        }
+
+        for forward in std::mem::take(&mut self.forwards) {
+            let address = match self.labels.get(&forward.name) {
+                Some(address) => *address,
+                None => {
+                    let message = format!("undefined label '{}'", forward.name);
+                    return Err(AssembleError { line: forward.line, message });
+                },
+            };
+            self.line = forward.line;
+            self.patch(forward.address, forward.patch, address)?;
+        }
+        if self.jump_slot {
+            let main = *self.labels.get("main").ok_or_else(|| self.error("the program has no main label"))?;
+            self.patch(PROGRAM_START, Patch::Long, 0x1000 | main)?;
+        }
+        Ok(())
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> AssembleError {
+        AssembleError { line: self.line, message: message.into() }
+    }
+
+    fn next(&mut self) -> Result<String, AssembleError> {
+        let token = self.tokens.get(self.position).ok_or_else(|| self.error("unexpected end of the program"))?;
+        self.line = token.line;
+        self.position += 1;
+        Ok(token.text.clone())
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AssembleError> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(self.error(format!("expected '{}', found '{}'", expected, token))),
+        }
+    }
+
+    fn byte(&mut self, value: u8) -> Result<(), AssembleError> {
+        if self.here as usize >= RAM_SIZE {
+            return Err(self.error("the program does not fit in memory"));
+        }
+        // the jump to main comes before any token
+        if self.position > 0 {
+            self.lines.entry(self.line).or_insert(self.here);
+        }
+        let index = (self.here - PROGRAM_START) as usize;
+        if self.rom.len() <= index {
+            self.rom.resize(index + 1, 0);
+        }
+        self.rom[index] = value;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn op(&mut self, instruction: u16) -> Result<(), AssembleError> {
+        self.byte((instruction >> 8) as u8)?;
+        self.byte(instruction as u8)
+    }
+
+    fn patch(&mut self, address: u16, patch: Patch, value: u16) -> Result<(), AssembleError> {
+        let index = (address - PROGRAM_START) as usize;
+        match patch {
+            Patch::Low12 => {
+                if value > 0xFFF {
+                    return Err(self.error(format!("address {:#X} does not fit in 12 bits", value)));
+                }
+                self.rom[index] = (self.rom[index] & 0xF0) | (value >> 8) as u8;
+                self.rom[index + 1] = value as u8;
+            },
+            Patch::Long => {
+                self.rom[index] = (value >> 8) as u8;
+                self.rom[index + 1] = value as u8;
+            },
+            Patch::Unpack(nibble) => {
+                if value > 0xFFF {
+                    return Err(self.error(format!("address {:#X} does not fit in 12 bits", value)));
+                }
+                self.rom[index + 1] = (nibble << 4) | (value >> 8) as u8;
+                self.rom[index + 3] = value as u8;
+            },
+            Patch::UnpackLong => {
+                self.rom[index + 1] = (value >> 8) as u8;
+                self.rom[index + 3] = value as u8;
+            },
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        match token.as_str() {
+            ":" => {
+                let name = self.name()?;
+                // a program starting with main needs no jump to it
+                if name == "main" && self.jump_slot && self.here == PROGRAM_START + 2 && self.labels.is_empty() {
+                    self.jump_slot = false;
+                    self.rom.clear();
+                    self.here = PROGRAM_START;
+                }
+                self.define_label(name, self.here)
+            },
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name, self.here + 1)
+            },
+            ":alias" => {
+                let name = self.name()?;
+                let register = if self.peek() == Some("{") {
+                    let value = self.calc()?;
+                    if !(0.0..16.0).contains(&value) {
+                        return Err(self.error(format!("{} is not a register", value)));
+                    }
+                    value as u8
+                } else {
+                    self.register()?
+                };
+                self.aliases.insert(name, register);
+                Ok(())
+            },
+            ":const" => {
+                let name = self.name()?;
+                let token = self.next()?;
+                let value = self.value_of(&token).ok_or_else(|| self.error(format!("'{}' is not a value", token)))?;
+                self.constants.insert(name, value);
+                Ok(())
+            },
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+                Ok(())
+            },
+            ":org" => {
+                let token = self.next()?;
+                let address = self.value_of(&token).ok_or_else(|| self.error(format!("'{}' is not a value", token)))?;
+                if address < PROGRAM_START as f64 || address >= RAM_SIZE as f64 {
+                    return Err(self.error(format!("{:#X} is outside of program memory", address as i64)));
+                }
+                self.here = address as u16;
+                Ok(())
+            },
+            ":byte" => {
+                let value = if self.peek() == Some("{") { self.calc()? } else { self.number()? };
+                let value = self.fit_byte(value)?;
+                self.byte(value)
+            },
+            ":pointer" => {
+                let address = self.address(Patch::Long, self.here)?;
+                self.op(address)
+            },
+            ":call" => {
+                let address = self.address(Patch::Low12, self.here)?;
+                self.op(0x2000 | address)
+            },
+            ":unpack" => self.unpack(),
+            ":macro" => self.define_macro(),
+            ":assert" => {
+                let message = match self.peek() {
+                    Some(text) if text.starts_with('"') => self.next()?.trim_matches('"').to_string(),
+                    _ => String::from("assertion failed"),
+                };
+                if self.calc()? == 0.0 {
+                    return Err(self.error(message));
+                }
+                Ok(())
+            },
+            // debugger directives of the Octo IDE
+            ":breakpoint" => self.next().map(|_| ()),
+            ":monitor" => {
+                self.next()?;
+                self.next().map(|_| ())
+            },
+            "return" | ";" => self.op(0x00EE),
+            "clear" => self.op(0x00E0),
+            "hires" => self.op(0x00FF),
+            "lores" => self.op(0x00FE),
+            "exit" => self.op(0x00FD),
+            "scroll-right" => self.op(0x00FB),
+            "scroll-left" => self.op(0x00FC),
+            "scroll-down" => {
+                let rows = self.nibble()?;
+                self.op(0x00C0 | rows as u16)
+            },
+            "scroll-up" => {
+                let rows = self.nibble()?;
+                self.op(0x00D0 | rows as u16)
+            },
+            "audio" => self.op(0xF002),
+            "plane" => {
+                let plane = self.nibble()?;
+                self.op(0xF001 | (plane as u16) << 8)
+            },
+            "bcd" => self.register_op(0xF033),
+            "saveflags" => self.register_op(0xF075),
+            "loadflags" => self.register_op(0xF085),
+            "save" | "load" => {
+                let x = self.register()? as u16;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()? as u16;
+                    let n = if token == "save" { 2 } else { 3 };
+                    self.op(0x5000 | x << 8 | y << 4 | n)
+                } else {
+                    let base = if token == "save" { 0xF055 } else { 0xF065 };
+                    self.op(base | x << 8)
+                }
+            },
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let n = self.nibble()? as u16;
+                self.op(0xD000 | x << 8 | y << 4 | n)
+            },
+            "jump" | "jump0" | "native" => {
+                let address = self.address(Patch::Low12, self.here)?;
+                let base = match token.as_str() {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                };
+                self.op(base | address)
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let base = match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.register_op(base)
+            },
+            "i" => self.i_statement(),
+            "if" => {
+                let (x, comparison, operand) = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.compare(x, &comparison, operand, false),
+                    "begin" => {
+                        self.compare(x, &comparison, operand, true)?;
+                        self.branches.push(self.here);
+                        self.op(0x1000)
+                    },
+                    other => Err(self.error(format!("expected 'then' or 'begin', found '{}'", other))),
+                }
+            },
+            "else" => {
+                let branch = self.branches.pop().ok_or_else(|| self.error("else without if ... begin"))?;
+                let jump = self.here;
+                self.op(0x1000)?;
+                self.patch(branch, Patch::Low12, self.here)?;
+                self.branches.push(jump);
+                Ok(())
+            },
+            "end" => {
+                let branch = self.branches.pop().ok_or_else(|| self.error("end without if ... begin"))?;
+                self.patch(branch, Patch::Low12, self.here)
+            },
+            "loop" => {
+                self.loops.push((self.here, Vec::new()));
+                Ok(())
+            },
+            "while" => {
+                let (x, comparison, operand) = self.condition()?;
+                self.compare(x, &comparison, operand, true)?;
+                let here = self.here;
+                match self.loops.last_mut() {
+                    Some((_, whiles)) => whiles.push(here),
+                    None => return Err(self.error("while outside of a loop")),
+                }
+                self.op(0x1000)
+            },
+            "again" => {
+                let (start, whiles) = self.loops.pop().ok_or_else(|| self.error("again without loop"))?;
+                self.op(0x1000 | start)?;
+                for jump in whiles {
+                    self.patch(jump, Patch::Low12, self.here)?;
+                }
+                Ok(())
+            },
+            _ if self.register_of(&token).is_some() => self.register_statement(&token),
+            _ if self.macros.contains_key(&token) => self.expand(&token),
+            _ if parse_number(&token).is_some() => {
+                let value = self.fit_byte(parse_number(&token).unwrap_or(0.0))?;
+                self.byte(value)
+            },
+            // anything else names a subroutine to call
+            _ if is_name(&token) && !self.constants.contains_key(&token) => {
+                self.position -= 1;
+                let address = self.address(Patch::Low12, self.here)?;
+                self.op(0x2000 | address)
+            },
+            _ => Err(self.error(format!("unexpected '{}'", token))),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, AssembleError> {
+        let name = self.next()?;
+        if !is_name(&name) || self.register_of(&name).is_some() {
+            return Err(self.error(format!("'{}' can not be used as a name", name)));
+        }
+        Ok(name)
+    }
+
+    fn define_label(&mut self, name: String, address: u16) -> Result<(), AssembleError> {
+        if self.labels.contains_key(&name) {
+            return Err(self.error(format!("label '{}' is already defined", name)));
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    fn register_of(&self, token: &str) -> Option<u8> {
+        let bytes = token.as_bytes();
+        if bytes.len() == 2 && (bytes[0] == b'v' || bytes[0] == b'V') {
+            if let Some(register) = (bytes[1] as char).to_digit(16) {
+                return Some(register as u8);
+            }
+        }
+        self.aliases.get(token).copied()
+    }
+
+    fn register(&mut self) -> Result<u8, AssembleError> {
+        let token = self.next()?;
+        self.register_of(&token).ok_or_else(|| self.error(format!("expected a register, found '{}'", token)))
+    }
+
+    fn is_register_next(&self) -> bool {
+        self.peek().is_some_and(|token| self.register_of(token).is_some())
+    }
+
+    // a number literal or constant
+    fn value_of(&self, token: &str) -> Option<f64> {
+        parse_number(token)
+            .or_else(|| self.constants.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|address| *address as f64))
+    }
+
+    fn number(&mut self) -> Result<f64, AssembleError> {
+        let token = self.next()?;
+        match parse_number(&token).or_else(|| self.constants.get(&token).copied()) {
+            Some(value) => Ok(value),
+            None => Err(self.error(format!("expected a number, found '{}'", token))),
+        }
+    }
+
+    // negative numbers are two's complement bytes
+    fn fit_byte(&self, value: f64) -> Result<u8, AssembleError> {
+        if !(-128.0..256.0).contains(&value) {
+            return Err(self.error(format!("{} does not fit in a byte", value)));
+        }
+        Ok((value as i64 & 0xFF) as u8)
+    }
+
+    fn short(&mut self) -> Result<u8, AssembleError> {
+        let value = self.number()?;
+        self.fit_byte(value)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AssembleError> {
+        let value = self.number()?;
+        if !(0.0..16.0).contains(&value) {
+            return Err(self.error(format!("{} does not fit in 4 bits", value)));
+        }
+        Ok(value as u8)
+    }
+
+    // a label, number or constant. Labels defined later are filled in at
+    // `at` when the program is complete, 0 stands in for them until then
+    fn address(&mut self, patch: Patch, at: u16) -> Result<u16, AssembleError> {
+        let token = self.next()?;
+        let max = match patch {
+            Patch::Low12 | Patch::Unpack(_) => 0xFFF,
+            Patch::Long | Patch::UnpackLong => 0xFFFF,
+        };
+        match self.value_of(&token) {
+            Some(value) if (0.0..=max as f64).contains(&value) => Ok(value as u16),
+            Some(value) => Err(self.error(format!("{} is not an address", value))),
+            None if is_name(&token) && self.register_of(&token).is_none() => {
+                self.forwards.push(Forward { name: token, address: at, patch, line: self.line });
+                Ok(0)
+            },
+            None => Err(self.error(format!("expected an address, found '{}'", token))),
+        }
+    }
+
+    fn register_op(&mut self, base: u16) -> Result<(), AssembleError> {
+        let x = self.register()? as u16;
+        self.op(base | x << 8)
+    }
+
+    fn register_statement(&mut self, token: &str) -> Result<(), AssembleError> {
+        let x = self.register_of(token).unwrap_or(0) as u16;
+        let operator = self.next()?;
+        if self.is_register_next() {
+            let y = self.register()? as u16;
+            let n = match operator.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return Err(self.error(format!("unknown operator '{}'", operator))),
+            };
+            return self.op(0x8000 | x << 8 | y << 4 | n);
+        }
+        match (operator.as_str(), self.peek()) {
+            (":=", Some("random")) => {
+                self.next()?;
+                let mask = self.short()? as u16;
+                self.op(0xC000 | x << 8 | mask)
+            },
+            (":=", Some("key")) => {
+                self.next()?;
+                self.op(0xF00A | x << 8)
+            },
+            (":=", Some("delay")) => {
+                self.next()?;
+                self.op(0xF007 | x << 8)
+            },
+            (":=", _) => {
+                let value = self.short()? as u16;
+                self.op(0x6000 | x << 8 | value)
+            },
+            ("+=", _) => {
+                let value = self.short()? as u16;
+                self.op(0x7000 | x << 8 | value)
+            },
+            ("-=", _) => {
+                let value = self.short()?.wrapping_neg() as u16;
+                self.op(0x7000 | x << 8 | value)
+            },
+            _ => Err(self.error(format!("'{}' needs a register on the right", operator))),
+        }
+    }
+
+    fn i_statement(&mut self) -> Result<(), AssembleError> {
+        match self.next()?.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") | Some("bighex") => {
+                    let base = if self.next()? == "hex" { 0xF029 } else { 0xF030 };
+                    self.register_op(base)
+                },
+                Some("long") => {
+                    self.next()?;
+                    let address = self.address(Patch::Long, self.here + 2)?;
+                    self.op(0xF000)?;
+                    self.op(address)
+                },
+                _ => {
+                    let address = self.address(Patch::Low12, self.here)?;
+                    self.op(0xA000 | address)
+                },
+            },
+            "+=" => self.register_op(0xF01E),
+            other => Err(self.error(format!("unknown operator '{}' for i", other))),
+        }
+    }
+
+    // :unpack loads the two bytes of an address, with a nibble on top, into
+    // the unpack-hi and unpack-lo registers
+    fn unpack(&mut self) -> Result<(), AssembleError> {
+        let hi = self.aliases[UNPACK_HI] as u16;
+        let lo = self.aliases[UNPACK_LO] as u16;
+        let (high, address) = if self.peek() == Some("long") {
+            self.next()?;
+            let address = self.address(Patch::UnpackLong, self.here)?;
+            (address >> 8, address & 0xFF)
+        } else {
+            let nibble = self.nibble()?;
+            let address = self.address(Patch::Unpack(nibble), self.here)?;
+            ((nibble as u16) << 4 | address >> 8, address & 0xFF)
+        };
+        self.op(0x6000 | hi << 8 | high)?;
+        self.op(0x6000 | lo << 8 | address)
+    }
+
+    fn condition(&mut self) -> Result<(u8, String, Operand), AssembleError> {
+        let x = self.register()?;
+        let comparison = self.next()?;
+        let operand = match comparison.as_str() {
+            "key" | "-key" => Operand::None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" if self.is_register_next() => Operand::Register(self.register()?),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Operand::Byte(self.short()?),
+            _ => return Err(self.error(format!("unknown comparison '{}'", comparison))),
+        };
+        Ok((x, comparison, operand))
+    }
+
+    // skips the next instruction when the comparison is false, or when it is
+    // true if `negated`
+    fn compare(&mut self, x: u8, comparison: &str, operand: Operand, negated: bool) -> Result<(), AssembleError> {
+        let comparison = match (negated, comparison) {
+            (false, comparison) => comparison,
+            (true, "==") => "!=",
+            (true, "!=") => "==",
+            (true, "<") => ">=",
+            (true, ">") => "<=",
+            (true, "<=") => ">",
+            (true, ">=") => "<",
+            (true, "key") => "-key",
+            (true, _) => "key",
+        };
+        let x = x as u16;
+        match (comparison, operand) {
+            ("==", Operand::Register(y)) => self.op(0x9000 | x << 8 | (y as u16) << 4),
+            ("==", _) => self.op(0x4000 | x << 8 | operand_byte(operand)),
+            ("!=", Operand::Register(y)) => self.op(0x5000 | x << 8 | (y as u16) << 4),
+            ("!=", _) => self.op(0x3000 | x << 8 | operand_byte(operand)),
+            ("key", _) => self.op(0xE0A1 | x << 8),
+            ("-key", _) => self.op(0xE09E | x << 8),
+            _ => {
+                // temp := operand, then a subtraction sets vF to 1 when there is
+                // no borrow
+                let temp = self.aliases[COMPARE_TEMP] as u16;
+                match operand {
+                    Operand::Register(y) => self.op(0x8000 | temp << 8 | (y as u16) << 4)?,
+                    _ => self.op(0x6000 | temp << 8 | operand_byte(operand))?,
+                }
+                let subtract = if comparison == ">" || comparison == "<=" { 0x5 } else { 0x7 };
+                self.op(0x8000 | temp << 8 | x << 4 | subtract)?;
+                self.op(if comparison == ">" || comparison == "<" { 0x3F01 } else { 0x4F01 })
+            },
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), AssembleError> {
+        let name = self.name()?;
+        let mut parameters = Vec::new();
+        loop {
+            match self.next()? {
+                token if token == "{" => break,
+                token => parameters.push(token),
+            }
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        while depth > 0 {
+            let token = self.tokens.get(self.position).cloned().ok_or_else(|| self.error("macro without }"))?;
+            self.position += 1;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {},
+            }
+            if depth > 0 {
+                body.push(token);
+            }
+        }
+        self.macros.insert(name, Macro { parameters, body });
+        Ok(())
+    }
+
+    // replaces the call with the body, its parameters replaced by the arguments
+    fn expand(&mut self, name: &str) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(format!("macro '{}' expands forever", name)));
+        }
+        let count = self.macros[name].parameters.len();
+        let mut arguments = HashMap::new();
+        for index in 0..count {
+            let argument = self.next()?;
+            arguments.insert(self.macros[name].parameters[index].clone(), argument);
+        }
+        let body: Vec<Token> = self.macros[name]
+            .body
+            .iter()
+            .map(|token| match arguments.get(&token.text) {
+                Some(argument) => Token { text: argument.clone(), line: token.line },
+                None => token.clone(),
+            })
+            .collect();
+        self.tokens.splice(self.position..self.position, body);
+        Ok(())
+    }
+
+    // { expression }
+    fn calc(&mut self) -> Result<f64, AssembleError> {
+        self.expect("{")?;
+        let value = self.expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn expression(&mut self) -> Result<f64, AssembleError> {
+        let token = self.next()?;
+        let value = match token.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                value
+            },
+            "-" => return Ok(-self.expression()?),
+            "~" => return Ok(!(self.expression()? as i64) as f64),
+            "!" => return Ok((self.expression()? == 0.0) as i64 as f64),
+            "sin" => return Ok(self.expression()?.sin()),
+            "cos" => return Ok(self.expression()?.cos()),
+            "tan" => return Ok(self.expression()?.tan()),
+            "exp" => return Ok(self.expression()?.exp()),
+            "log" => return Ok(self.expression()?.ln()),
+            "abs" => return Ok(self.expression()?.abs()),
+            "sqrt" => return Ok(self.expression()?.sqrt()),
+            "sign" => return Ok(self.expression()?.signum()),
+            "ceil" => return Ok(self.expression()?.ceil()),
+            "floor" => return Ok(self.expression()?.floor()),
+            "@" => {
+                let address = self.expression()?;
+                let index = address as i64 - PROGRAM_START as i64;
+                let byte = usize::try_from(index).ok().and_then(|index| self.rom.get(index));
+                return Ok(byte.copied().unwrap_or(0) as f64);
+            },
+            "HERE" => self.here as f64,
+            "PI" => std::f64::consts::PI,
+            "E" => std::f64::consts::E,
+            _ => self.value_of(&token).ok_or_else(|| self.error(format!("'{}' is not a value", token)))?,
+        };
+
+        let operator = match self.peek() {
+            Some(operator) if is_binary_operator(operator) => self.next()?,
+            _ => return Ok(value),
+        };
+        let rest = self.expression()?;
+        let (a, b) = (value as i64, rest as i64);
+        Ok(match operator.as_str() {
+            "+" => value + rest,
+            "-" => value - rest,
+            "*" => value * rest,
+            "/" => value / rest,
+            "%" => value % rest,
+            "pow" => value.powf(rest),
+            "min" => value.min(rest),
+            "max" => value.max(rest),
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.checked_shl(b as u32).unwrap_or(0) as f64,
+            ">>" => a.checked_shr(b as u32).unwrap_or(0) as f64,
+            "<" => (value < rest) as i64 as f64,
+            ">" => (value > rest) as i64 as f64,
+            "<=" => (value <= rest) as i64 as f64,
+            ">=" => (value >= rest) as i64 as f64,
+            "==" => (value == rest) as i64 as f64,
+            _ => (value != rest) as i64 as f64,
+        })
+    }
+}
+
+fn operand_byte(operand: Operand) -> u16 {
+    match operand {
+        Operand::Byte(value) => value as u16,
+        _ => 0,
+    }
+}
+
+fn is_binary_operator(token: &str) -> bool {
+    matches!(
+        token,
+        "+" | "-" | "*" | "/" | "%" | "pow" | "min" | "max" | "&" | "|" | "^" | "<<" | ">>" | "<" | ">" | "<="
+            | ">=" | "==" | "!="
+    )
+}
+
+// decimal, 0x hex or 0b binary, optionally negative
+fn parse_number(token: &str) -> Option<f64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value } as f64)
+}
+
+fn is_name(token: &str) -> bool {
+    let first = token.chars().next();
+    first.is_some_and(|first| first.is_alphabetic() || first == '_')
+        && token.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(program: &[u8]) -> Vec<u16> {
+        program.chunks(2).map(|pair| (pair[0] as u16) << 8 | pair[1] as u16).collect()
+    }
+
+    #[test]
+    fn main_first_needs_no_jump() {
+        let program = assemble(": main\n  v0 := 5\n  v1 += v0 # comment\n  i := hex v1\n  sprite v0 v1 5\n").unwrap();
+        assert_eq!(words(&program), vec![0x6005, 0x8104, 0xF129, 0xD015]);
+    }
+
+    #[test]
+    fn labels_resolve_forward_and_backward() {
+        let source = ": draw\n  sprite v0 v1 1\n  ;\n: main\n  draw\n  i := dot\n  jump main\n: dot\n  0x80\n";
+        let program = assemble(source).unwrap();
+        // a jump to main goes first, draw starts at 0x202
+        assert_eq!(words(&program[..12]), vec![0x1206, 0xD011, 0x00EE, 0x2202, 0xA20C, 0x1206]);
+        assert_eq!(program[12], 0x80);
+    }
+
+    #[test]
+    fn symbols_name_the_labels_and_lines() {
+        let source = ": draw\n  sprite v0 v1 1\n  ;\n\n# blank lines and comments have no code\n: main\n  draw\n  i := dot\n\
+                      jump main\n: dot\n  0x80\n";
+        let path = Path::new("pong.8o");
+        let (program, symbols) = assemble_with_symbols(source, path).unwrap();
+        assert_eq!(program, assemble(source).unwrap());
+
+        assert_eq!(symbols.label(0x202), Some("draw"));
+        assert_eq!(symbols.address_of_label("main"), Some(0x206));
+        assert_eq!(symbols.label(0x20C), Some("dot"));
+        // the jump to main at 0x200 comes from no line
+        assert_eq!(symbols.source_line(0x200), None);
+        let line = |address| symbols.source_line(address).map(|source| (source.path.as_path(), source.line));
+        assert_eq!(line(0x202), Some((path, 2)));
+        assert_eq!(line(0x204), Some((path, 3)));
+        assert_eq!(line(0x206), Some((path, 7)));
+        assert_eq!(line(0x20A), Some((path, 9)));
+        assert_eq!(line(0x20C), Some((path, 11)));
+        assert_eq!(symbols.address_of_line(path, 4), Some((0x206, 7)));
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = ": main
+            loop
+                while v0 != 3
+                if v0 > 1 begin
+                    v1 := 1
+                else
+                    v1 := 2
+                end
+                v0 += 1
+            again";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            words(&program),
+            vec![
+                0x4003, 0x1216, // while: skip the exit when v0 != 3
+                0x6F01, 0x8F05, 0x4F01, 0x1210, // v0 <= 1 jumps to else
+                0x6101, 0x1212, 0x6102, // the else branch
+                0x7001, 0x1200,
+            ]
+        );
+    }
+
+    #[test]
+    fn directives_and_macros() {
+        let source = ":const SIZE 3
+            :alias x v4
+            :macro twice A B { A += B A += B }
+            :calc DOUBLE { SIZE * 2 + 1 }
+            : main
+                x := SIZE
+                twice x 1
+                x -= DOUBLE
+                :unpack 0xA data
+                :byte { DOUBLE }
+            : data";
+        let program = assemble(source).unwrap();
+        assert_eq!(words(&program[..12]), vec![0x6403, 0x7401, 0x7401, 0x74F7, 0x60A2, 0x610D]);
+        assert_eq!(program[12], 9);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = assemble(": main\n  v0 := 300\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(assemble(": main\n  nowhere\n").unwrap_err().message.contains("nowhere"));
+        assert!(assemble("v0 := 1\n").is_err());
+        assert!(assemble(": main\n  loop\n").is_err());
+    }
+}
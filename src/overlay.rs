@@ -0,0 +1,251 @@
+use crate::font::{self, CELL_HEIGHT};
+use crate::speed::Speed;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Text the frontend shows over the game image: transient status messages, the
+// FPS and IPS counters and whether emulation is paused or not at normal speed.
+//
+//     60 FPS 660 IPS        PAUSED
+//
+//
+//     screenshot saved
+//
+// Renderers get an OverlayView and draw it at their own resolution, the window
+// composites it over the scaled frame with the built-in font.
+
+pub const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+const MAX_MESSAGES: usize = 3;
+// how often the counters are updated
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+const TEXT_COLOR: u32 = 0xFFFFFF;
+
+// what to draw, compared between frames so renderers only redraw on changes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OverlayView {
+    // top left
+    pub stats: Option<String>,
+    // top right
+    pub status: Option<String>,
+    // bottom left, oldest first
+    pub messages: Vec<String>,
+}
+
+impl OverlayView {
+    pub fn is_empty(&self) -> bool {
+        self.stats.is_none() && self.status.is_none() && self.messages.is_empty()
+    }
+
+    // draws the text on darkened boxes into a `width` x `height` 0RGB image. The
+    // font is scaled up with the image so it stays readable in a large window
+    pub fn draw(&self, pixels: &mut [u32], width: usize, height: usize) {
+        let scale = (height / 160).max(1);
+        let line_height = (CELL_HEIGHT + 1) * scale;
+
+        if let Some(stats) = self.stats.as_deref() {
+            draw_label(pixels, width, height, 0, 0, stats, scale);
+        }
+        if let Some(status) = self.status.as_deref() {
+            let x = width.saturating_sub(label_width(status, scale));
+            draw_label(pixels, width, height, x, 0, status, scale);
+        }
+        let top = height.saturating_sub(self.messages.len() * line_height);
+        for (index, message) in self.messages.iter().enumerate() {
+            draw_label(pixels, width, height, 0, top + index * line_height, message, scale);
+        }
+    }
+}
+
+fn label_width(text: &str, scale: usize) -> usize {
+    (font::text_width(text) + 2) * scale
+}
+
+// text with a one pixel margin on a box at a quarter of the image's brightness
+fn draw_label(pixels: &mut [u32], width: usize, height: usize, x: usize, y: usize, text: &str, scale: usize) {
+    let right = (x + label_width(text, scale)).min(width);
+    let bottom = (y + (CELL_HEIGHT + 1) * scale).min(height);
+    for row in y..bottom {
+        for pixel in pixels[row * width + x.min(right)..row * width + right].iter_mut() {
+            *pixel = (*pixel >> 2) & 0x3F3F3F;
+        }
+    }
+    font::draw_text_scaled(&mut pixels[..width * height], width, x + scale, y + scale, text, TEXT_COLOR, scale);
+}
+
+pub struct Overlay {
+    // text and when it disappears
+    messages: VecDeque<(String, Instant)>,
+    show_stats: bool,
+    stats: Option<String>,
+    paused: bool,
+    speed: Speed,
+    // frames and instructions counted since the counters were last updated
+    window_start: Instant,
+    window_frames: u64,
+    window_instructions: u64,
+    last_instructions: Option<u64>,
+}
+
+impl Overlay {
+    pub fn new(show_stats: bool) -> Overlay {
+        Overlay {
+            messages: VecDeque::new(),
+            show_stats,
+            stats: None,
+            paused: false,
+            speed: Speed::Normal,
+            window_start: Instant::now(),
+            window_frames: 0,
+            window_instructions: 0,
+            last_instructions: None,
+        }
+    }
+
+    // shows `text` for MESSAGE_DURATION, the oldest message goes when there
+    // are too many
+    pub fn message<S: Into<String>>(&mut self, text: S) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((text.into(), Instant::now() + MESSAGE_DURATION));
+    }
+
+    pub fn shows_stats(&self) -> bool {
+        self.show_stats
+    }
+
+    pub fn set_show_stats(&mut self, show_stats: bool) {
+        self.show_stats = show_stats;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    // called after every emulated frame with the instructions executed so far
+    pub fn count_frame(&mut self, instructions_executed: u64) {
+        let last = self.last_instructions.replace(instructions_executed).unwrap_or(instructions_executed);
+        self.window_frames += 1;
+        self.window_instructions += instructions_executed.saturating_sub(last);
+    }
+
+    // what to draw now, expired messages are dropped
+    pub fn view(&mut self) -> OverlayView {
+        let now = Instant::now();
+        self.messages.retain(|(_, until)| *until > now);
+        self.update_stats(now);
+
+        let status = if self.paused {
+            Some(String::from("paused"))
+        } else if self.speed != Speed::Normal {
+            Some(String::from(self.speed.name()))
+        } else {
+            None
+        };
+        OverlayView {
+            stats: self.stats.clone().filter(|_| self.show_stats),
+            status,
+            messages: self.messages.iter().map(|(text, _)| text.clone()).collect(),
+        }
+    }
+
+    fn update_stats(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed < STATS_INTERVAL {
+            return;
+        }
+        let seconds = elapsed.as_secs_f64();
+        let fps = (self.window_frames as f64 / seconds).round();
+        let ips = (self.window_instructions as f64 / seconds).round();
+        self.stats = Some(format!("{} FPS {} IPS", fps, ips));
+        self.window_start = now;
+        self.window_frames = 0;
+        self.window_instructions = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_expire_after_their_duration() {
+        let mut overlay = Overlay::new(false);
+        overlay.message("saved");
+        overlay.message("loaded");
+        assert_eq!(overlay.view().messages, ["saved", "loaded"]);
+
+        overlay.messages[0].1 = Instant::now() - Duration::from_millis(1);
+        assert_eq!(overlay.view().messages, ["loaded"]);
+    }
+
+    #[test]
+    fn the_oldest_message_makes_room() {
+        let mut overlay = Overlay::new(false);
+        for index in 0..MAX_MESSAGES + 1 {
+            overlay.message(format!("message {}", index));
+        }
+        assert_eq!(overlay.view().messages, ["message 1", "message 2", "message 3"]);
+    }
+
+    #[test]
+    fn counters_average_over_the_stats_interval() {
+        let mut overlay = Overlay::new(true);
+        // the first frame only starts counting instructions
+        for frame in 0..60 {
+            overlay.count_frame(1000 + frame * 10);
+        }
+        assert_eq!(overlay.view().stats, None);
+
+        overlay.window_start = Instant::now() - STATS_INTERVAL;
+        assert_eq!(overlay.view().stats.as_deref(), Some("60 FPS 590 IPS"));
+        assert_eq!(overlay.window_frames, 0);
+
+        overlay.set_show_stats(false);
+        assert_eq!(overlay.view().stats, None);
+    }
+
+    #[test]
+    fn status_shows_pause_before_speed() {
+        let mut overlay = Overlay::new(false);
+        overlay.set_speed(Speed::Half);
+        assert_eq!(overlay.view().status.as_deref(), Some("50%"));
+        overlay.set_paused(true);
+        assert_eq!(overlay.view().status.as_deref(), Some("paused"));
+        overlay.set_paused(false);
+        overlay.set_speed(Speed::Normal);
+        assert!(overlay.view().is_empty());
+    }
+
+    #[test]
+    fn labels_are_clipped_to_a_small_image() {
+        let view = OverlayView {
+            stats: Some(String::from("60 FPS 660 IPS")),
+            status: Some(String::from("paused")),
+            messages: vec![String::from("screenshot saved"), String::from("recording")],
+        };
+        let (width, height) = (6, 4);
+        let mut pixels = vec![0x808080; width * height];
+        view.draw(&mut pixels, width, height);
+        // the labels overlap and cover every pixel, text included
+        assert!(pixels.iter().all(|pixel| *pixel != 0x808080), "{:06X?}", pixels);
+        assert!(pixels.contains(&TEXT_COLOR));
+    }
+
+    #[test]
+    fn labels_scale_with_the_image() {
+        let view = OverlayView { stats: Some(String::from("1")), ..OverlayView::default() };
+        let (width, height) = (40, 320);
+        let mut pixels = vec![0; width * height];
+        view.draw(&mut pixels, width, height);
+        // at scale 2 the 1's top row is two pixels at x 4 and y 2 and 3
+        for y in 2..4 {
+            assert_eq!(&pixels[y * width + 2..y * width + 8], [0, 0, TEXT_COLOR, TEXT_COLOR, 0, 0]);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// Colors in 0RGB format as expected by the window buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    // indexed by pixel value: background, fill, second fill and blended fill.
+    // The last two are only used by XO-CHIP's second bitplane
+    pub colors: [u32; 4],
+    // shown while the sound timer is running
+    pub buzzer: u32,
+    pub quiet: u32,
+}
+
+pub const PALETTE_NAMES: [&str; 6] = ["classic", "green", "amber", "lcd", "octo", "inverted"];
+
+impl Palette {
+    pub fn color(&self, pixel: u8) -> u32 {
+        self.colors[(pixel & 0b11) as usize]
+    }
+
+    pub fn named(name: &str) -> Option<Palette> {
+        let palette = match name {
+            "classic" => Palette::default(),
+            // P1 green phosphor monitor
+            "green" => Palette {
+                colors: [0x0A1A0A, 0x33FF33, 0x1E9E1E, 0x66FF66],
+                buzzer: 0x0A1A0A,
+                quiet: 0x0A1A0A,
+            },
+            // P3 amber phosphor monitor
+            "amber" => Palette {
+                colors: [0x1A1000, 0xFFB000, 0x9E6D00, 0xFFCC4D],
+                buzzer: 0x1A1000,
+                quiet: 0x1A1000,
+            },
+            // greenish monochrome LCD, like a handheld
+            "lcd" => Palette {
+                colors: [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F],
+                buzzer: 0x9BBC0F,
+                quiet: 0x9BBC0F,
+            },
+            // Octo's default colors
+            "octo" => Palette {
+                colors: [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+                buzzer: 0xFFAA00,
+                quiet: 0x000000,
+            },
+            "inverted" => Palette {
+                colors: [0xFFFFFF, 0x000000, 0x000000, 0x000000],
+                buzzer: 0xFFFFFF,
+                quiet: 0xFFFFFF,
+            },
+            _ => return None,
+        };
+        Some(palette)
+    }
+
+    // replaces the first colors with a comma separated list of hex colors,
+    // two colors also set the XO-CHIP colors to the fill color
+    pub fn with_colors(mut self, list: &str) -> Option<Palette> {
+        let colors: Vec<u32> = list.split(',').map(parse_hex_color).collect::<Option<_>>()?;
+        if colors.is_empty() || colors.len() > self.colors.len() {
+            return None;
+        }
+        self.colors[..colors.len()].copy_from_slice(&colors);
+        if colors.len() == 2 {
+            self.colors[2] = colors[1];
+            self.colors[3] = colors[1];
+        }
+        Some(self)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: [0x000000, 0xFFFFFF, 0xFFFFFF, 0xFFFFFF],
+            buzzer: 0x000000,
+            quiet: 0x000000,
+        }
+    }
+}
+
+pub fn parse_hex_color(text: &str) -> Option<u32> {
+    let hex = text.trim().trim_start_matches('#');
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+
+    match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok(),
+        3 => {
+            // #RGB shorthand, each digit is doubled
+            let short = u32::from_str_radix(hex, 16).ok()?;
+            let r = (short >> 8) & 0xF;
+            let g = (short >> 4) & 0xF;
+            let b = short & 0xF;
+            Some(((r * 0x11) << 16) | ((g * 0x11) << 8) | (b * 0x11))
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colors_take_a_hash_or_0x_and_the_short_form() {
+        assert_eq!(parse_hex_color("#FFB000"), Some(0xFFB000));
+        assert_eq!(parse_hex_color(" 0x33ff33 "), Some(0x33FF33));
+        assert_eq!(parse_hex_color("123456"), Some(0x123456));
+        assert_eq!(parse_hex_color("#F80"), Some(0xFF8800));
+        assert_eq!(parse_hex_color("#FFB00"), None);
+        assert_eq!(parse_hex_color("#GGGGGG"), None);
+        assert_eq!(parse_hex_color(""), None);
+    }
+
+    #[test]
+    fn two_colors_also_fill_the_xo_chip_planes() {
+        let palette = Palette::named("octo").unwrap().with_colors("#000, #FFF").unwrap();
+        assert_eq!(palette.colors, [0x000000, 0xFFFFFF, 0xFFFFFF, 0xFFFFFF]);
+        assert_eq!(palette.buzzer, 0xFFAA00);
+
+        let palette = Palette::default().with_colors("#111111,#222222,#333333").unwrap();
+        assert_eq!(palette.colors, [0x111111, 0x222222, 0x333333, 0xFFFFFF]);
+        assert_eq!(Palette::default().with_colors("#111111").unwrap().colors[0], 0x111111);
+    }
+
+    #[test]
+    fn color_lists_must_be_one_to_four_valid_colors() {
+        assert_eq!(Palette::default().with_colors(""), None);
+        assert_eq!(Palette::default().with_colors("#000,#111,#222,#333,#444"), None);
+        assert_eq!(Palette::default().with_colors("#000,red"), None);
+    }
+
+    #[test]
+    fn every_palette_name_is_known() {
+        for name in PALETTE_NAMES {
+            assert!(Palette::named(name).is_some(), "{}", name);
+        }
+        assert_eq!(Palette::named("classic"), Some(Palette::default()));
+        assert_eq!(Palette::named("sepia"), None);
+        // pixel values past the second plane wrap to the four colors
+        assert_eq!(Palette::named("lcd").unwrap().color(0b101), 0x0F380F);
+    }
+}
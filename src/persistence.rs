@@ -0,0 +1,201 @@
+use crate::palette::Palette;
+
+// Reduces the flicker of XOR drawn sprites by post-processing display frames
+// before they are turned into colors
+
+pub const DEFAULT_DECAY: f32 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlickerFilter {
+    Off,
+    // lit pixels fade out like phosphor, keeping `decay` of their brightness per frame
+    Fade { decay: f32 },
+    // a pixel is lit if it was lit in this or the previous frame
+    Blend,
+}
+
+impl FlickerFilter {
+    // "off", "blend", "fade" or "fade:<decay>"
+    pub fn parse(text: &str) -> Option<FlickerFilter> {
+        match text.split_once(':') {
+            Some(("fade", decay)) => {
+                let decay: f32 = decay.parse().ok()?;
+                (0.0..1.0).contains(&decay).then_some(FlickerFilter::Fade { decay })
+            },
+            Some(_) => None,
+            None => match text {
+                "off" => Some(FlickerFilter::Off),
+                "fade" => Some(FlickerFilter::Fade { decay: DEFAULT_DECAY }),
+                "blend" => Some(FlickerFilter::Blend),
+                _ => None,
+            },
+        }
+    }
+}
+
+pub struct Persistence {
+    filter: FlickerFilter,
+    // brightness of every pixel, 1.0 while lit
+    brightness: Vec<f32>,
+    // the pixel value each pixel last had while lit, so it fades in its own color
+    last_lit: Vec<u8>,
+    previous: Vec<u8>,
+    // the last frame differed from the one before, so blending still shows it
+    blending: bool,
+}
+
+impl Persistence {
+    pub fn new(filter: FlickerFilter) -> Persistence {
+        Persistence {
+            filter,
+            brightness: Vec::new(),
+            last_lit: Vec::new(),
+            previous: Vec::new(),
+            blending: false,
+        }
+    }
+
+    pub fn filter(&self) -> FlickerFilter {
+        self.filter
+    }
+
+    // false while pixels are still fading, so frames have to be produced even if
+    // the display did not change
+    pub fn is_settled(&self) -> bool {
+        match self.filter {
+            FlickerFilter::Fade { .. } => self.brightness.iter().all(|b| *b == 0.0 || *b == 1.0),
+            FlickerFilter::Blend => !self.blending,
+            FlickerFilter::Off => true,
+        }
+    }
+
+    // writes the filtered display to `framebuffer` in 0RGB colors
+    pub fn apply(&mut self, display: &[u8], palette: &Palette, framebuffer: &mut Vec<u32>) {
+        framebuffer.clear();
+        if self.brightness.len() != display.len() {
+            self.brightness = vec![0.0; display.len()];
+            self.last_lit = vec![0; display.len()];
+            self.previous = display.to_vec();
+        }
+
+        match self.filter {
+            FlickerFilter::Off => {
+                framebuffer.extend(display.iter().map(|pixel| palette.color(*pixel)));
+            },
+            FlickerFilter::Blend => {
+                for (pixel, previous) in display.iter().zip(self.previous.iter()) {
+                    let lit = if *pixel != 0 { *pixel } else { *previous };
+                    framebuffer.push(palette.color(lit));
+                }
+            },
+            FlickerFilter::Fade { decay } => {
+                let background = palette.color(0);
+                for (index, pixel) in display.iter().enumerate() {
+                    let brightness = &mut self.brightness[index];
+                    if *pixel != 0 {
+                        *brightness = 1.0;
+                        self.last_lit[index] = *pixel;
+                    } else {
+                        *brightness *= decay;
+                        // settle on the background instead of fading forever
+                        if *brightness < 1.0 / 255.0 {
+                            *brightness = 0.0;
+                        }
+                    }
+                    framebuffer.push(mix(background, palette.color(self.last_lit[index]), *brightness));
+                }
+            },
+        }
+
+        self.blending = self.previous.as_slice() != display;
+        self.previous.clear();
+        self.previous.extend_from_slice(display);
+    }
+}
+
+// linear interpolation of each channel from `from` to `to`
+fn mix(from: u32, to: u32, amount: f32) -> u32 {
+    let channel = |shift: u32| {
+        let a = ((from >> shift) & 0xFF) as f32;
+        let b = ((to >> shift) & 0xFF) as f32;
+        ((a + (b - a) * amount).round() as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_parse_with_an_optional_decay() {
+        assert_eq!(FlickerFilter::parse("off"), Some(FlickerFilter::Off));
+        assert_eq!(FlickerFilter::parse("blend"), Some(FlickerFilter::Blend));
+        assert_eq!(FlickerFilter::parse("fade"), Some(FlickerFilter::Fade { decay: DEFAULT_DECAY }));
+        assert_eq!(FlickerFilter::parse("fade:0.25"), Some(FlickerFilter::Fade { decay: 0.25 }));
+        assert_eq!(FlickerFilter::parse("fade:1"), None);
+        assert_eq!(FlickerFilter::parse("fade:-0.5"), None);
+        assert_eq!(FlickerFilter::parse("fade:slow"), None);
+        assert_eq!(FlickerFilter::parse("blend:0.5"), None);
+        assert_eq!(FlickerFilter::parse("glow"), None);
+    }
+
+    #[test]
+    fn faded_pixels_lose_the_decay_every_frame_until_they_settle() {
+        let mut persistence = Persistence::new(FlickerFilter::Fade { decay: 0.5 });
+        let palette = Palette::default();
+        let mut framebuffer = Vec::new();
+
+        persistence.apply(&[1, 0], &palette, &mut framebuffer);
+        assert_eq!(framebuffer, [0xFFFFFF, 0x000000]);
+        assert!(persistence.is_settled());
+
+        let mut seen = Vec::new();
+        for _ in 0..8 {
+            persistence.apply(&[0, 0], &palette, &mut framebuffer);
+            seen.push(framebuffer[0]);
+            assert_eq!(framebuffer[1], 0x000000);
+        }
+        assert_eq!(&seen[..3], [0x808080, 0x404040, 0x202020]);
+        // 0.5^8 is under one step of brightness, so the last frame is background
+        assert_eq!(seen[7], 0x000000);
+        assert!(persistence.is_settled());
+    }
+
+    #[test]
+    fn fading_keeps_the_color_the_pixel_was_lit_in() {
+        let mut persistence = Persistence::new(FlickerFilter::Fade { decay: 0.5 });
+        let palette = Palette { colors: [0x000000, 0xFFFFFF, 0xFF0000, 0x0000FF], ..Palette::default() };
+        let mut framebuffer = Vec::new();
+        persistence.apply(&[2], &palette, &mut framebuffer);
+        persistence.apply(&[0], &palette, &mut framebuffer);
+        assert_eq!(framebuffer, [0x800000]);
+        assert!(!persistence.is_settled());
+    }
+
+    #[test]
+    fn blending_shows_pixels_lit_in_the_last_frame() {
+        let mut persistence = Persistence::new(FlickerFilter::Blend);
+        let palette = Palette::default();
+        let mut framebuffer = Vec::new();
+
+        persistence.apply(&[1, 0, 0], &palette, &mut framebuffer);
+        persistence.apply(&[0, 1, 0], &palette, &mut framebuffer);
+        assert_eq!(framebuffer, [0xFFFFFF, 0xFFFFFF, 0x000000]);
+        assert!(!persistence.is_settled());
+
+        persistence.apply(&[0, 1, 0], &palette, &mut framebuffer);
+        assert_eq!(framebuffer, [0x000000, 0xFFFFFF, 0x000000]);
+        assert!(persistence.is_settled());
+    }
+
+    #[test]
+    fn off_passes_the_display_through() {
+        let mut persistence = Persistence::new(FlickerFilter::Off);
+        let mut framebuffer = Vec::new();
+        persistence.apply(&[1, 0], &Palette::default(), &mut framebuffer);
+        persistence.apply(&[0, 1], &Palette::default(), &mut framebuffer);
+        assert_eq!(framebuffer, [0x000000, 0xFFFFFF]);
+        assert!(persistence.is_settled());
+    }
+}
@@ -0,0 +1,94 @@
+// Behaviour differences between CHIP-8 interpreters. The names and the all-off
+// defaults follow Octo's quirk options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    // 8XY6/8XYE shift Reg VX in place instead of shifting Reg VY into Reg VX
+    pub shift: bool,
+    // FX55/FX65 leave I unchanged instead of incrementing it
+    pub load_store: bool,
+    // 8XY1/8XY2/8XY3 reset Reg VF to 0 like the COSMAC VIP
+    pub vf_reset: bool,
+    // the flag is written to Reg VF before the result, so an op on VF keeps the result
+    pub vf_order: bool,
+    // sprites are clipped at the screen edge instead of wrapping around, like the COSMAC VIP
+    pub clip: bool,
+    // BNNN is treated as BXNN and jumps to XNN + Reg VX
+    pub jump: bool,
+    // DXYN waits for the next vertical blank before drawing, like the COSMAC VIP
+    pub vblank: bool,
+    // FX55/FX65 increment I by X instead of X + 1, like CHIP-48
+    pub increment_by_x: bool,
+}
+
+// the quirks by name, in the order of the fields
+pub const QUIRK_NAMES: [&str; 8] =
+    ["shift", "load_store", "vf_reset", "vf_order", "clip", "jump", "vblank", "increment_by_x"];
+
+impl Quirks {
+    fn flags_mut(&mut self) -> [&mut bool; 8] {
+        [
+            &mut self.shift,
+            &mut self.load_store,
+            &mut self.vf_reset,
+            &mut self.vf_order,
+            &mut self.clip,
+            &mut self.jump,
+            &mut self.vblank,
+            &mut self.increment_by_x,
+        ]
+    }
+
+    // the names of the enabled quirks
+    pub fn enabled(&self) -> Vec<&'static str> {
+        let mut quirks = *self;
+        QUIRK_NAMES
+            .iter()
+            .zip(quirks.flags_mut())
+            .filter(|(_, enabled)| **enabled)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    // only the named quirks enabled, None if a name is unknown
+    pub fn from_names<'a, I: IntoIterator<Item = &'a str>>(names: I) -> Option<Quirks> {
+        let mut quirks = Quirks::default();
+        for name in names {
+            let index = QUIRK_NAMES.iter().position(|known| *known == name)?;
+            *quirks.flags_mut()[index] = true;
+        }
+        Some(quirks)
+    }
+
+    // quirks of the platforms named in the CHIP-8 community database
+    pub fn for_platform(platform: &str) -> Option<Quirks> {
+        let quirks = match platform {
+            "originalChip8" | "hybridVIP" => Quirks {
+                vf_reset: true,
+                clip: true,
+                vblank: true,
+                ..Quirks::default()
+            },
+            "modernChip8" => Quirks {
+                clip: true,
+                ..Quirks::default()
+            },
+            "chip48" => Quirks {
+                shift: true,
+                clip: true,
+                jump: true,
+                increment_by_x: true,
+                ..Quirks::default()
+            },
+            "superchip1" | "superchip" => Quirks {
+                shift: true,
+                load_store: true,
+                clip: true,
+                jump: true,
+                ..Quirks::default()
+            },
+            "xochip" => Quirks::default(),
+            _ => return None,
+        };
+        Some(quirks)
+    }
+}
@@ -45,11 +45,23 @@ impl Ram {
         self.mem[address as usize]
     }
 
-    pub fn print_ram(&self) {
-        for i in 0..self.mem.len() {
-            print!("{} ", self.mem[i]);
+    // hex dump, 16 bytes per line prefixed with the address
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        for (line, bytes) in self.mem.chunks(16).enumerate() {
+            dump.push_str(&format!("{:03X}:", line * 16));
+            for byte in bytes {
+                dump.push_str(&format!(" {:02X}", byte));
+            }
+            dump.push('\n');
         }
-        print!("\n");
+        dump
     }
 
 }
+
+impl Default for Ram {
+    fn default() -> Self {
+        Ram::new()
+    }
+}
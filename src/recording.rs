@@ -0,0 +1,354 @@
+use crate::renderer::Frame;
+use crate::scaler::FilterPipeline;
+use crate::screenshot;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// Records gameplay at the 60Hz frame rate, either as an animated GIF or as
+// numbered PNG frames for external muxing. The sound timer can be recorded as a
+// square wave tone to a WAV file next to the video.
+
+pub const DEFAULT_DIRECTORY: &str = "recordings";
+
+pub const FRAME_RATE: u32 = 60;
+pub const SAMPLE_RATE: u32 = 44_100;
+const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / FRAME_RATE;
+const TONE_FREQUENCY: u32 = 440;
+const TONE_AMPLITUDE: i16 = 8_000;
+// viewers show GIF frames with a delay of 1 or less for 10 hundredths of a
+// second, so frames are never written with less than this
+const MIN_DELAY: u64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    Gif,
+    // frame-000000.png, frame-000001.png, ... in a directory
+    Frames,
+}
+
+impl RecordingFormat {
+    // a .gif file records a GIF, anything else is a directory of frames
+    pub fn for_path(path: &Path) -> RecordingFormat {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("gif") => RecordingFormat::Gif,
+            _ => RecordingFormat::Frames,
+        }
+    }
+}
+
+struct GifVideo {
+    // created with the size of the first frame
+    encoder: Option<gif::Encoder<BufWriter<File>>>,
+    width: usize,
+    height: usize,
+    // the last frame and when it started in hundredths of a second. It is only
+    // written once the next one differs, so runs of identical frames become one
+    // frame with a longer delay
+    pending: Vec<u32>,
+    pending_start: u64,
+}
+
+pub struct Recorder {
+    path: PathBuf,
+    format: RecordingFormat,
+    gif: GifVideo,
+    audio: Option<WavWriter>,
+    scale: usize,
+    scaled: Vec<u32>,
+    frames: u64,
+}
+
+impl Recorder {
+    // frames are scaled `scale` times with nearest neighbour scaling. Audio goes
+    // to the same path with a .wav extension, or audio.wav in a frames directory
+    pub fn start<P: Into<PathBuf>>(path: P, audio: bool, scale: usize) -> io::Result<Recorder> {
+        let path = path.into();
+        let format = RecordingFormat::for_path(&path);
+        if format == RecordingFormat::Frames {
+            fs::create_dir_all(&path)?;
+        }
+        let audio = match (audio, format) {
+            (false, _) => None,
+            (true, RecordingFormat::Gif) => Some(WavWriter::create(path.with_extension("wav"))?),
+            (true, RecordingFormat::Frames) => Some(WavWriter::create(path.join("audio.wav"))?),
+        };
+
+        Ok(Recorder {
+            path,
+            format,
+            gif: GifVideo {
+                encoder: None,
+                width: 0,
+                height: 0,
+                pending: Vec::new(),
+                pending_start: 0,
+            },
+            audio,
+            scale: scale.max(1),
+            scaled: Vec::new(),
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // records one 60Hz frame, `sound` is true while the sound timer runs
+    pub fn push_frame(&mut self, frame: &Frame, sound: bool) -> io::Result<()> {
+        let (width, height) = (frame.width * self.scale, frame.height * self.scale);
+        if self.scale > 1 {
+            FilterPipeline::default().apply(frame, &mut self.scaled, width, height);
+        } else {
+            self.scaled.clear();
+            self.scaled.extend_from_slice(frame.pixels);
+        }
+
+        match self.format {
+            RecordingFormat::Gif => {
+                let start = frame_start(self.frames);
+                let gif = &mut self.gif;
+                if gif.encoder.is_none() {
+                    gif.encoder = Some(gif_encoder(&self.path, width, height)?);
+                    gif.width = width;
+                    gif.height = height;
+                    gif.pending_start = start;
+                } else if gif.pending == self.scaled {
+                    return self.finish_push(sound);
+                } else if start - gif.pending_start >= MIN_DELAY {
+                    gif.write_pending(start)?;
+                    gif.pending_start = start;
+                }
+                // a frame shown for less than MIN_DELAY is dropped, the next one
+                // takes its place from the time it started
+                gif.pending.clone_from(&self.scaled);
+            },
+            RecordingFormat::Frames => {
+                let path = self.path.join(format!("frame-{:06}.png", self.frames));
+                screenshot::save_png(path, &Frame { width, height, pixels: &self.scaled })?;
+            },
+        }
+        self.finish_push(sound)
+    }
+
+    fn finish_push(&mut self, sound: bool) -> io::Result<()> {
+        if let Some(audio) = self.audio.as_mut() {
+            audio.push_frame(sound)?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.format == RecordingFormat::Gif {
+            let end = frame_start(self.frames).max(self.gif.pending_start + MIN_DELAY);
+            self.gif.write_pending(end)?;
+            if let Some(encoder) = self.gif.encoder.take() {
+                encoder.into_inner()?.flush()?;
+            }
+        }
+        if let Some(audio) = self.audio.take() {
+            audio.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl GifVideo {
+    // writes the pending frame to show until `end`
+    fn write_pending(&mut self, end: u64) -> io::Result<()> {
+        let encoder = match self.encoder.as_mut() {
+            Some(encoder) => encoder,
+            None => return Ok(()),
+        };
+
+        // the display rarely has more than a handful of colors, so most frames
+        // fit an exact palette. Fading can produce more, those get quantized
+        let mut indices = HashMap::new();
+        let mut palette = Vec::new();
+        let mut pixels = Vec::with_capacity(self.pending.len());
+        for color in self.pending.iter() {
+            let next = indices.len();
+            let index = *indices.entry(*color).or_insert(next);
+            if index == next && next < 256 {
+                palette.extend_from_slice(&[(color >> 16) as u8, (color >> 8) as u8, *color as u8]);
+            }
+            pixels.push(index as u8);
+        }
+        let mut frame = if indices.len() <= 256 {
+            gif::Frame::from_palette_pixels(self.width as u16, self.height as u16, pixels, palette, None)
+        } else {
+            let rgb: Vec<u8> = self
+                .pending
+                .iter()
+                .flat_map(|color| [(color >> 16) as u8, (color >> 8) as u8, *color as u8])
+                .collect();
+            gif::Frame::from_rgb_speed(self.width as u16, self.height as u16, &rgb, 10)
+        };
+        frame.delay = (end - self.pending_start).min(u16::MAX as u64) as u16;
+        encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+}
+
+fn gif_encoder(path: &Path, width: usize, height: usize) -> io::Result<gif::Encoder<BufWriter<File>>> {
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "frames are too large for a GIF"));
+    }
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[]).map_err(io::Error::other)?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+    Ok(encoder)
+}
+
+// GIF delays are in hundredths of a second, frame n starts at n * 100 / 60 of
+// them. Delays are taken between these starts so the GIF stays in sync
+fn frame_start(frame: u64) -> u64 {
+    frame * 100 / FRAME_RATE as u64
+}
+
+// 16 bit mono PCM, the sizes in the header are filled in by finish
+struct WavWriter {
+    file: BufWriter<File>,
+    samples: u32,
+    // position in the tone's period, kept across frames so the wave is continuous
+    phase: u32,
+}
+
+impl WavWriter {
+    fn create<P: AsRef<Path>>(path: P) -> io::Result<WavWriter> {
+        let mut wav = WavWriter {
+            file: BufWriter::new(File::create(path)?),
+            samples: 0,
+            phase: 0,
+        };
+        wav.write_header()?;
+        Ok(wav)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let data_size = self.samples * 2;
+        let file = &mut self.file;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(36 + data_size).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM, 1 channel
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        file.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+        // block align and bits per sample
+        file.write_all(&2u16.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&data_size.to_le_bytes())
+    }
+
+    fn push_frame(&mut self, sound: bool) -> io::Result<()> {
+        for _ in 0..SAMPLES_PER_FRAME {
+            let sample = if !sound {
+                0
+            } else if self.phase < SAMPLE_RATE / 2 {
+                TONE_AMPLITUDE
+            } else {
+                -TONE_AMPLITUDE
+            };
+            self.phase = (self.phase + TONE_FREQUENCY) % SAMPLE_RATE;
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += SAMPLES_PER_FRAME;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chip8-recording-test-{}-{}", std::process::id(), name))
+    }
+
+    // records one frame per color and returns the delays of the GIF's frames
+    fn record_gif(name: &str, colors: &[u32]) -> Vec<u16> {
+        let path = temp_path(name).with_extension("gif");
+        let mut recorder = Recorder::start(&path, false, 1).unwrap();
+        for color in colors {
+            let pixels = [*color; 4];
+            recorder.push_frame(&Frame { width: 2, height: 2, pixels: &pixels }, false).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let mut decoder = gif::DecodeOptions::new().read_info(File::open(&path).unwrap()).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        fs::remove_file(&path).unwrap();
+        delays
+    }
+
+    #[test]
+    fn frames_start_on_the_60hz_clock() {
+        let starts: Vec<u64> = (0..7).map(frame_start).collect();
+        assert_eq!(starts, [0, 1, 3, 5, 6, 8, 10]);
+    }
+
+    #[test]
+    fn flicker_at_60hz_keeps_the_timing_with_delays_of_at_least_2() {
+        let delays = record_gif("flicker", &[0x000000, 0xFFFFFF, 0x000000, 0xFFFFFF, 0x000000, 0xFFFFFF]);
+        assert_eq!(delays, [3, 2, 3, 2]);
+        // six frames at 60Hz are a tenth of a second
+        assert_eq!(delays.iter().sum::<u16>(), 10);
+    }
+
+    #[test]
+    fn identical_frames_become_one_longer_frame() {
+        let delays = record_gif("runs", &[0x000000, 0x000000, 0x000000, 0xFFFFFF, 0xFFFFFF, 0xFFFFFF]);
+        assert_eq!(delays, [5, 5]);
+        // a lone frame is still shown for the minimum delay
+        assert_eq!(record_gif("single", &[0x000000]), [2]);
+    }
+
+    #[test]
+    fn the_wav_header_holds_the_sizes_of_the_samples() {
+        let path = temp_path("audio").with_extension("wav");
+        let mut wav = WavWriter::create(&path).unwrap();
+        wav.push_frame(true).unwrap();
+        wav.push_frame(false).unwrap();
+        wav.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let data_size = SAMPLES_PER_FRAME * 2 * 2;
+
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(u32_at(4), 36 + data_size);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(16), 16);
+        assert_eq!((u16_at(20), u16_at(22)), (1, 1));
+        assert_eq!((u32_at(24), u32_at(28)), (SAMPLE_RATE, SAMPLE_RATE * 2));
+        assert_eq!((u16_at(32), u16_at(34)), (2, 16));
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(40), data_size);
+        assert_eq!(bytes.len(), 44 + data_size as usize);
+
+        // the tone starts high, silence is zero
+        assert_eq!(u16_at(44) as i16, TONE_AMPLITUDE);
+        assert_eq!(u16_at(44 + SAMPLES_PER_FRAME as usize * 2) as i16, 0);
+    }
+}
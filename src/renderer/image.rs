@@ -0,0 +1,32 @@
+use super::{Frame, Renderer};
+use crate::screenshot;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+// Writes every frame to an image file, overwriting the previous one, so the file
+// always holds the latest frame. PNG for .png files, binary PPM otherwise
+pub struct ImageRenderer {
+    path: PathBuf,
+}
+
+impl ImageRenderer {
+    pub fn new<P: Into<PathBuf>>(path: P) -> ImageRenderer {
+        ImageRenderer { path: path.into() }
+    }
+}
+
+impl Renderer for ImageRenderer {
+    fn render(&mut self, frame: &Frame) -> io::Result<()> {
+        if self.path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png")) {
+            return screenshot::save_png(&self.path, frame);
+        }
+
+        let mut file = BufWriter::new(File::create(&self.path)?);
+        write!(file, "P6\n{} {}\n255\n", frame.width, frame.height)?;
+        for color in frame.pixels {
+            file.write_all(&[(color >> 16) as u8, (color >> 8) as u8, *color as u8])?;
+        }
+        file.flush()
+    }
+}
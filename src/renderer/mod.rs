@@ -0,0 +1,122 @@
+use crate::chip8::CpuState;
+use crate::display::DirtyRegion;
+use crate::overlay::OverlayView;
+use crate::scaler::FilterPipeline;
+use minifb::Key;
+use std::io;
+
+mod image;
+mod null;
+mod terminal;
+mod tui;
+mod window;
+
+pub use self::image::ImageRenderer;
+pub use self::null::NullRenderer;
+pub use self::terminal::TerminalRenderer;
+pub use self::tui::{Glyphs, TuiRenderer};
+pub use self::window::WindowRenderer;
+
+// A frame at the emulated display's resolution, one 0RGB color per pixel
+pub struct Frame<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a [u32],
+}
+
+impl<'a> Frame<'a> {
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+// Machine state shown next to the display by debugging frontends
+#[derive(Debug, Clone)]
+pub struct DebugView {
+    pub cpu: CpuState,
+    // (address, instruction, text) around the program counter
+    pub disassembly: Vec<(u16, u16, String)>,
+}
+
+pub trait Renderer {
+    fn render(&mut self, frame: &Frame) -> io::Result<()>;
+
+    // called instead of render when only the rows in `dirty` differ from the
+    // last rendered frame, so renderers can skip the rest
+    fn render_region(&mut self, frame: &Frame, _dirty: &DirtyRegion) -> io::Result<()> {
+        self.render(frame)
+    }
+
+    // called instead of render when the frame did not change
+    fn poll(&mut self) {}
+
+    // false once the user closed the output
+    fn is_open(&self) -> bool {
+        true
+    }
+
+    // host keys currently held down, for renderers that own an input device
+    fn keys_down(&self) -> Vec<Key> {
+        Vec::new()
+    }
+
+    // renderers returning true get a DebugView before every rendered frame
+    fn shows_debug_view(&self) -> bool {
+        false
+    }
+
+    fn set_debug_view(&mut self, _view: DebugView) {}
+
+    // text shown over the frame from the next render on, renderers that have
+    // nowhere to show it ignore it
+    fn set_overlay(&mut self, _overlay: OverlayView) {}
+
+    // true while the renderer draws on the terminal, anything written to
+    // stderr would end up on its screen
+    fn owns_terminal(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RendererKind {
+    Window,
+    Terminal,
+    Tui,
+    Image,
+    Null,
+}
+
+impl RendererKind {
+    pub fn parse(name: &str) -> Option<RendererKind> {
+        let kind = match name {
+            "window" => RendererKind::Window,
+            "terminal" => RendererKind::Terminal,
+            "tui" => RendererKind::Tui,
+            "image" => RendererKind::Image,
+            "null" => RendererKind::Null,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+pub struct RendererOptions {
+    pub title: String,
+    // file written by the image renderer
+    pub output: String,
+    pub glyphs: Glyphs,
+    // scaling and CRT effects of the window renderer
+    pub filters: FilterPipeline,
+}
+
+pub fn create(kind: RendererKind, options: &RendererOptions) -> io::Result<Box<dyn Renderer>> {
+    let renderer: Box<dyn Renderer> = match kind {
+        RendererKind::Window => Box::new(WindowRenderer::new(&options.title, options.filters)?),
+        RendererKind::Terminal => Box::new(TerminalRenderer::new()),
+        RendererKind::Tui => Box::new(TuiRenderer::new(options.glyphs)?),
+        RendererKind::Image => Box::new(ImageRenderer::new(&options.output)),
+        RendererKind::Null => Box::new(NullRenderer),
+    };
+    Ok(renderer)
+}
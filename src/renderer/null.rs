@@ -0,0 +1,11 @@
+use super::{Frame, Renderer};
+use std::io;
+
+// Discards every frame, for headless runs and benchmarks
+pub struct NullRenderer;
+
+impl Renderer for NullRenderer {
+    fn render(&mut self, _frame: &Frame) -> io::Result<()> {
+        Ok(())
+    }
+}
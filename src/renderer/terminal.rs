@@ -0,0 +1,65 @@
+use super::{Frame, Renderer};
+use crate::display::DirtyRegion;
+use std::io::{self, Write};
+
+// Draws two pixel rows per line of text with the upper half block character,
+// the top pixel as foreground and the bottom pixel as background color.
+// Needs a terminal with 24 bit color support
+pub struct TerminalRenderer {
+    output: String,
+    // size of the last frame, the screen is cleared when it changes
+    size: (usize, usize),
+}
+
+impl TerminalRenderer {
+    pub fn new() -> TerminalRenderer {
+        TerminalRenderer {
+            output: String::new(),
+            size: (0, 0),
+        }
+    }
+}
+
+impl Default for TerminalRenderer {
+    fn default() -> Self {
+        TerminalRenderer::new()
+    }
+}
+
+fn rgb(color: u32) -> (u8, u8, u8) {
+    ((color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
+
+impl Renderer for TerminalRenderer {
+    fn render(&mut self, frame: &Frame) -> io::Result<()> {
+        self.render_region(frame, &DirtyRegion::full())
+    }
+
+    fn render_region(&mut self, frame: &Frame, dirty: &DirtyRegion) -> io::Result<()> {
+        self.output.clear();
+        if self.size != (frame.width, frame.height) {
+            self.size = (frame.width, frame.height);
+            self.output.push_str("\x1b[2J");
+        }
+
+        for y in (0..frame.height).step_by(2) {
+            if !dirty.is_row_dirty(y) && !dirty.is_row_dirty(y + 1) {
+                continue;
+            }
+            // cursor to the start of the line
+            self.output.push_str(&format!("\x1b[{};1H", y / 2 + 1));
+            for x in 0..frame.width {
+                let (tr, tg, tb) = rgb(frame.pixel(x, y));
+                let (br, bg, bb) = if y + 1 < frame.height { rgb(frame.pixel(x, y + 1)) } else { (0, 0, 0) };
+                self.output.push_str(&format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}", tr, tg, tb, br, bg, bb));
+            }
+            self.output.push_str("\x1b[0m");
+        }
+        // leave the cursor below the image
+        self.output.push_str(&format!("\x1b[{};1H", frame.height.div_ceil(2) + 1));
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(self.output.as_bytes())?;
+        stdout.flush()
+    }
+}
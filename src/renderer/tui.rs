@@ -0,0 +1,429 @@
+use super::{DebugView, Frame, Renderer};
+use crate::display::DirtyRegion;
+use crate::overlay::OverlayView;
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+use minifb::Key;
+use std::collections::HashMap;
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+// Terminals only report key presses and auto repeats, so without the kitty
+// keyboard protocol a key counts as held until shortly after its last event.
+// The first hold covers the usual delay before auto repeat starts
+const FIRST_PRESS_HOLD: Duration = Duration::from_millis(500);
+const REPEAT_HOLD: Duration = Duration::from_millis(100);
+
+const PANE_WIDTH: usize = 32;
+// lines under the screen for the overlay, its top line and the messages
+const OVERLAY_LINES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glyphs {
+    // 1x2 pixels per cell, in color
+    HalfBlock,
+    // 2x4 pixels per cell in the foreground color, fits 128x64 in 64x16 cells
+    Braille,
+}
+
+// Full screen terminal frontend: the display beside register and disassembly
+// panes, with keyboard input read from stdin in raw mode
+pub struct TuiRenderer {
+    stdout: Stdout,
+    glyphs: Glyphs,
+    // true if the terminal reports key releases
+    reports_release: bool,
+    // host key to the time it counts as released
+    held: HashMap<Key, Instant>,
+    debug_view: Option<DebugView>,
+    overlay: OverlayView,
+    open: bool,
+    // size of the last frame, the screen is cleared when it changes
+    size: (usize, usize),
+}
+
+impl TuiRenderer {
+    pub fn new(glyphs: Glyphs) -> io::Result<TuiRenderer> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        queue!(stdout, terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(terminal::ClearType::All))?;
+
+        let reports_release = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_release {
+            queue!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+        stdout.flush()?;
+
+        Ok(TuiRenderer {
+            stdout,
+            glyphs,
+            reports_release,
+            held: HashMap::new(),
+            debug_view: None,
+            overlay: OverlayView::default(),
+            open: true,
+            size: (0, 0),
+        })
+    }
+
+    fn read_input(&mut self) -> io::Result<()> {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key_event) = event::read()? {
+                self.handle_key(key_event);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key_event: KeyEvent) {
+        if key_event.code == KeyCode::Char('c') && key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            self.open = false;
+            return;
+        }
+        let key = match host_key(key_event.code) {
+            Some(key) => key,
+            None => return,
+        };
+
+        let now = Instant::now();
+        match key_event.kind {
+            KeyEventKind::Release => {
+                self.held.remove(&key);
+            },
+            // with release reports a key stays held until it is released
+            _ if self.reports_release => {
+                self.held.insert(key, now + Duration::from_secs(3600));
+            },
+            KeyEventKind::Repeat => {
+                self.held.insert(key, now + REPEAT_HOLD);
+            },
+            KeyEventKind::Press => {
+                // some terminals report auto repeats as presses
+                let repeating = self.held.get(&key).is_some_and(|until| *until > now);
+                let hold = if repeating { REPEAT_HOLD } else { FIRST_PRESS_HOLD };
+                self.held.insert(key, now + hold);
+            },
+        }
+    }
+
+    // redraws the lines showing rows in `dirty`
+    fn draw_screen(&mut self, frame: &Frame, dirty: &DirtyRegion) -> io::Result<()> {
+        let line_dirty = |y: usize, rows: usize| (y..y + rows).any(|row| dirty.is_row_dirty(row));
+        match self.glyphs {
+            Glyphs::HalfBlock => {
+                for y in (0..frame.height).step_by(2) {
+                    if !line_dirty(y, 2) {
+                        continue;
+                    }
+                    queue!(self.stdout, cursor::MoveTo(0, (y / 2) as u16))?;
+                    for x in 0..frame.width {
+                        let top = frame.pixel(x, y);
+                        let bottom = if y + 1 < frame.height { frame.pixel(x, y + 1) } else { 0 };
+                        queue!(
+                            self.stdout,
+                            SetForegroundColor(color(top)),
+                            SetBackgroundColor(color(bottom)),
+                            Print('\u{2580}'),
+                        )?;
+                    }
+                    queue!(self.stdout, ResetColor)?;
+                }
+            },
+            Glyphs::Braille => {
+                let (background, foreground) = braille_colors(frame);
+                queue!(self.stdout, SetForegroundColor(color(foreground)), SetBackgroundColor(color(background)))?;
+
+                for y in (0..frame.height).step_by(4) {
+                    if !line_dirty(y, 4) {
+                        continue;
+                    }
+                    let mut line = String::with_capacity(frame.width / 2);
+                    for x in (0..frame.width).step_by(2) {
+                        line.push(braille_cell(frame, x, y, background));
+                    }
+                    queue!(self.stdout, cursor::MoveTo(0, (y / 4) as u16), Print(line))?;
+                }
+                queue!(self.stdout, ResetColor)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn draw_panes(&mut self, screen_columns: u16) -> io::Result<()> {
+        let view = match self.debug_view.as_ref() {
+            Some(view) => view,
+            None => return Ok(()),
+        };
+        let left = screen_columns + 2;
+        let state = &view.cpu;
+
+        let mut lines = Vec::<String>::new();
+        lines.push(format!("PC {:04X}  I {:04X}", state.pc, state.i));
+        lines.push(format!("DT {:02X}    ST {:02X}", state.delay_timer, state.sound_timer));
+        lines.push(format!("SP {:X}", state.stack.len()));
+        for row in 0..4 {
+            let registers: Vec<String> = (0..4)
+                .map(|column| row * 4 + column)
+                .map(|index| format!("V{:X} {:02X}", index, state.v[index]))
+                .collect();
+            lines.push(registers.join(" "));
+        }
+        lines.push(String::new());
+        for (address, instruction, text) in view.disassembly.iter() {
+            let marker = if *address == state.pc { '>' } else { ' ' };
+            lines.push(format!("{}{:03X} {:04X} {}", marker, address, instruction, text));
+        }
+
+        for (row, line) in lines.iter().enumerate() {
+            let mut line = line.clone();
+            line.truncate(PANE_WIDTH);
+            queue!(
+                self.stdout,
+                cursor::MoveTo(left, row as u16),
+                terminal::Clear(terminal::ClearType::UntilNewLine),
+                Print(line),
+            )?;
+        }
+        Ok(())
+    }
+
+    // the overlay as text under the screen, each line padded to the screen's
+    // width so shorter text overwrites the last
+    fn draw_overlay(&mut self, screen_columns: usize, screen_lines: usize) -> io::Result<()> {
+        let top = [self.overlay.stats.as_deref(), self.overlay.status.as_deref()];
+        let mut lines = vec![top.iter().flatten().copied().collect::<Vec<&str>>().join("  ")];
+        lines.extend(self.overlay.messages.iter().cloned());
+        lines.resize(OVERLAY_LINES, String::new());
+
+        for (row, line) in lines.iter().enumerate() {
+            let line: String = line.chars().take(screen_columns).collect();
+            queue!(
+                self.stdout,
+                cursor::MoveTo(0, (screen_lines + row) as u16),
+                Print(format!("{:<width$}", line, width = screen_columns)),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Renderer for TuiRenderer {
+    fn render(&mut self, frame: &Frame) -> io::Result<()> {
+        self.render_region(frame, &DirtyRegion::full())
+    }
+
+    fn render_region(&mut self, frame: &Frame, dirty: &DirtyRegion) -> io::Result<()> {
+        self.read_input()?;
+        if self.size != (frame.width, frame.height) {
+            self.size = (frame.width, frame.height);
+            queue!(self.stdout, terminal::Clear(terminal::ClearType::All))?;
+        }
+        self.draw_screen(frame, dirty)?;
+        let (screen_columns, screen_lines) = match self.glyphs {
+            Glyphs::HalfBlock => (frame.width, frame.height.div_ceil(2)),
+            Glyphs::Braille => (frame.width.div_ceil(2), frame.height.div_ceil(4)),
+        };
+        self.draw_panes(screen_columns as u16)?;
+        self.draw_overlay(screen_columns, screen_lines)?;
+        self.stdout.flush()
+    }
+
+    fn poll(&mut self) {
+        if self.read_input().is_err() {
+            self.open = false;
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn keys_down(&self) -> Vec<Key> {
+        let now = Instant::now();
+        self.held
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    fn shows_debug_view(&self) -> bool {
+        true
+    }
+
+    fn set_debug_view(&mut self, view: DebugView) {
+        self.debug_view = Some(view);
+    }
+
+    fn set_overlay(&mut self, overlay: OverlayView) {
+        self.overlay = overlay;
+    }
+
+    fn owns_terminal(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for TuiRenderer {
+    fn drop(&mut self) {
+        if self.reports_release {
+            let _ = queue!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(self.stdout, ResetColor, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = self.stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn color(pixel: u32) -> Color {
+    Color::Rgb {
+        r: (pixel >> 16) as u8,
+        g: (pixel >> 8) as u8,
+        b: pixel as u8,
+    }
+}
+
+// the most common color is the background, anything else is set and drawn in
+// the next most common one. A frame that starts with an inverted line has a
+// set top left pixel, so that pixel is not the background
+fn braille_colors(frame: &Frame) -> (u32, u32) {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for pixel in frame.pixels {
+        *counts.entry(*pixel).or_default() += 1;
+    }
+    let mut colors: Vec<(u32, usize)> = counts.into_iter().collect();
+    // ties go to the darker color so the order does not depend on the hash map
+    colors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let background = colors.first().map(|(color, _)| *color).unwrap_or(0);
+    let foreground = colors.get(1).map(|(color, _)| *color).unwrap_or(background);
+    (background, foreground)
+}
+
+// braille dots are numbered down the left column then the right one, with the
+// bottom row added later as dots 7 and 8
+fn braille_cell(frame: &Frame, x: usize, y: usize, background: u32) -> char {
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let mut bits = 0;
+    for (dy, row) in DOTS.iter().enumerate() {
+        for (dx, dot) in row.iter().enumerate() {
+            let (px, py) = (x + dx, y + dy);
+            if px < frame.width && py < frame.height && frame.pixel(px, py) != background {
+                bits |= dot;
+            }
+        }
+    }
+    char::from_u32(0x2800 + bits).unwrap_or(' ')
+}
+
+// terminal keys expressed as the window's keys so both frontends share a keymap
+fn host_key(code: KeyCode) -> Option<Key> {
+    let key = match code {
+        KeyCode::Esc => Key::Escape,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Char(' ') => Key::Space,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::F(number) => match number {
+            1 => Key::F1,
+            2 => Key::F2,
+            3 => Key::F3,
+            4 => Key::F4,
+            5 => Key::F5,
+            6 => Key::F6,
+            7 => Key::F7,
+            8 => Key::F8,
+            9 => Key::F9,
+            10 => Key::F10,
+            11 => Key::F11,
+            12 => Key::F12,
+            _ => return None,
+        },
+        KeyCode::Char(c) => match c.to_ascii_lowercase() {
+            '0' => Key::Key0,
+            '1' => Key::Key1,
+            '2' => Key::Key2,
+            '3' => Key::Key3,
+            '4' => Key::Key4,
+            '5' => Key::Key5,
+            '6' => Key::Key6,
+            '7' => Key::Key7,
+            '8' => Key::Key8,
+            '9' => Key::Key9,
+            'a' => Key::A,
+            'b' => Key::B,
+            'c' => Key::C,
+            'd' => Key::D,
+            'e' => Key::E,
+            'f' => Key::F,
+            'g' => Key::G,
+            'h' => Key::H,
+            'i' => Key::I,
+            'j' => Key::J,
+            'k' => Key::K,
+            'l' => Key::L,
+            'm' => Key::M,
+            'n' => Key::N,
+            'o' => Key::O,
+            'p' => Key::P,
+            'q' => Key::Q,
+            'r' => Key::R,
+            's' => Key::S,
+            't' => Key::T,
+            'u' => Key::U,
+            'v' => Key::V,
+            'w' => Key::W,
+            'x' => Key::X,
+            'y' => Key::Y,
+            'z' => Key::Z,
+            '\'' => Key::Apostrophe,
+            ',' => Key::Comma,
+            '.' => Key::Period,
+            ';' => Key::Semicolon,
+            '/' => Key::Slash,
+            '-' => Key::Minus,
+            '=' => Key::Equal,
+            '[' => Key::LeftBracket,
+            ']' => Key::RightBracket,
+            '\\' => Key::Backslash,
+            '`' => Key::Backquote,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn braille_uses_the_most_common_color_as_background() {
+        // an inverted first line over a mostly black 128x64 frame
+        let (width, height) = (128, 64);
+        let mut pixels = vec![0x000000; width * height];
+        pixels[..width * 6].fill(0xFFFFFF);
+        pixels[width * 10 + 4] = 0xFFFFFF;
+        let frame = Frame { width, height, pixels: &pixels };
+
+        assert_eq!(braille_colors(&frame), (0x000000, 0xFFFFFF));
+        assert_eq!(braille_cell(&frame, 0, 0, 0x000000), '\u{28FF}');
+        assert_eq!(braille_cell(&frame, 4, 8, 0x000000), '\u{2804}');
+        assert_eq!(braille_cell(&frame, 126, 60, 0x000000), '\u{2800}');
+    }
+
+    #[test]
+    fn a_blank_frame_has_one_color() {
+        let pixels = vec![0x123456; 64 * 32];
+        let frame = Frame { width: 64, height: 32, pixels: &pixels };
+        assert_eq!(braille_colors(&frame), (0x123456, 0x123456));
+    }
+}
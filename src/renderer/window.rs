@@ -0,0 +1,62 @@
+use super::{Frame, Renderer};
+use crate::overlay::OverlayView;
+use crate::scaler::FilterPipeline;
+use minifb::{Key, Window, WindowOptions};
+use std::io;
+
+pub const WINDOW_WIDTH: usize = 640;
+pub const WINDOW_HEIGHT: usize = 320;
+
+// A resizable minifb window, frames are scaled to its current size by the filter pipeline
+pub struct WindowRenderer {
+    window: Window,
+    filters: FilterPipeline,
+    buffer: Vec<u32>,
+    overlay: OverlayView,
+}
+
+impl WindowRenderer {
+    pub fn new(title: &str, filters: FilterPipeline) -> io::Result<WindowRenderer> {
+        let options = WindowOptions {
+            resize: true,
+            ..WindowOptions::default()
+        };
+        let window = Window::new(title, WINDOW_WIDTH, WINDOW_HEIGHT, options)
+            .map_err(|err| io::Error::other(err.to_string()))?;
+
+        Ok(WindowRenderer {
+            window,
+            filters,
+            buffer: Vec::new(),
+            overlay: OverlayView::default(),
+        })
+    }
+}
+
+impl Renderer for WindowRenderer {
+    fn render(&mut self, frame: &Frame) -> io::Result<()> {
+        let (width, height) = self.window.get_size();
+        self.filters.apply(frame, &mut self.buffer, width, height);
+        self.overlay.draw(&mut self.buffer, width, height);
+
+        self.window
+            .update_with_buffer(&self.buffer, width, height)
+            .map_err(|err| io::Error::other(err.to_string()))
+    }
+
+    fn poll(&mut self) {
+        self.window.update();
+    }
+
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn keys_down(&self) -> Vec<Key> {
+        self.window.get_keys()
+    }
+
+    fn set_overlay(&mut self, overlay: OverlayView) {
+        self.overlay = overlay;
+    }
+}
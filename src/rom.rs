@@ -0,0 +1,108 @@
+use crate::octo::AssembleError;
+use std::fmt;
+use std::io;
+
+pub const RAM_SIZE: usize = 4096;
+pub const ETI_660_PROGRAM_START: u16 = 0x600;
+
+// the interpreter and font live below this address on the original machines
+const MIN_LOAD_ADDRESS: u16 = 0x200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomFormat {
+    // plain program bytes (.ch8)
+    Raw,
+    // Octo "cartridge" GIF with the program embedded in the pixel data
+    OctoCartridge,
+}
+
+impl RomFormat {
+    pub fn detect(data: &[u8]) -> RomFormat {
+        if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            RomFormat::OctoCartridge
+        } else {
+            RomFormat::Raw
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RomLoadError {
+    Io(io::Error),
+    Empty,
+    TooLarge { size: usize, max: usize },
+    InvalidLoadAddress(u16),
+    InvalidCartridge(String),
+    // the Octo source of a cartridge does not assemble
+    Assemble(AssembleError),
+}
+
+impl fmt::Display for RomLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomLoadError::Io(err) => write!(f, "failed to read rom: {}", err),
+            RomLoadError::Empty => write!(f, "rom is empty"),
+            RomLoadError::TooLarge { size, max } => {
+                write!(f, "rom is {} bytes, at most {} bytes fit at the load address", size, max)
+            },
+            RomLoadError::InvalidLoadAddress(address) => {
+                write!(f, "invalid load address {:#X}", address)
+            },
+            RomLoadError::InvalidCartridge(message) => {
+                write!(f, "invalid Octo cartridge: {}", message)
+            },
+            RomLoadError::Assemble(err) => write!(f, "failed to assemble the cartridge, {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RomLoadError {}
+
+impl From<io::Error> for RomLoadError {
+    fn from(err: io::Error) -> Self {
+        RomLoadError::Io(err)
+    }
+}
+
+// returns how many bytes of program fit in ram when loaded at `address`
+pub fn max_rom_size(address: u16) -> Result<usize, RomLoadError> {
+    if address < MIN_LOAD_ADDRESS || address as usize >= RAM_SIZE {
+        return Err(RomLoadError::InvalidLoadAddress(address));
+    }
+    Ok(RAM_SIZE - address as usize)
+}
+
+pub fn validate(data: &[u8], address: u16) -> Result<(), RomLoadError> {
+    let max = max_rom_size(address)?;
+    if data.is_empty() {
+        return Err(RomLoadError::Empty);
+    }
+    if data.len() > max {
+        return Err(RomLoadError::TooLarge { size: data.len(), max });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_addresses_outside_program_memory_are_rejected() {
+        assert!(matches!(max_rom_size(0x1FF), Err(RomLoadError::InvalidLoadAddress(0x1FF))));
+        assert!(matches!(max_rom_size(0x1000), Err(RomLoadError::InvalidLoadAddress(0x1000))));
+        assert_eq!(max_rom_size(0x200).unwrap(), 0xE00);
+        assert_eq!(max_rom_size(ETI_660_PROGRAM_START).unwrap(), 0xA00);
+    }
+
+    #[test]
+    fn roms_must_fit_between_the_load_address_and_the_end_of_ram() {
+        assert!(matches!(validate(&[], 0x200), Err(RomLoadError::Empty)));
+        assert!(validate(&[0; 0xE00], 0x200).is_ok());
+        assert!(matches!(
+            validate(&[0; 0xE01], 0x200),
+            Err(RomLoadError::TooLarge { size: 0xE01, max: 0xE00 })
+        ));
+        assert!(matches!(validate(&[0; 2], 0x100), Err(RomLoadError::InvalidLoadAddress(0x100))));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::renderer::Frame;
+
+// Software upscaling and CRT effects, from the display's resolution to the size
+// of the output. The image keeps the frame's aspect ratio and is centered with
+// black bars filling the rest.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scaler {
+    #[default]
+    Nearest,
+    Bilinear,
+    // EPX/Scale2x smooths diagonal edges without blurring, then nearest neighbour
+    // scales the result to the output size
+    Scale2x,
+}
+
+impl Scaler {
+    pub fn parse(name: &str) -> Option<Scaler> {
+        let scaler = match name {
+            "nearest" => Scaler::Nearest,
+            "bilinear" => Scaler::Bilinear,
+            "scale2x" | "epx" => Scaler::Scale2x,
+            _ => return None,
+        };
+        Some(scaler)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FilterPipeline {
+    pub scaler: Scaler,
+    // darkens the lower half of every display row
+    pub scanlines: bool,
+    // darkens the edges of every display pixel
+    pub grid: bool,
+}
+
+// a rectangle inside the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// the largest rectangle with the frame's aspect ratio that fits the output
+pub fn fit(frame_width: usize, frame_height: usize, width: usize, height: usize) -> Viewport {
+    let (fit_width, fit_height) = if width * frame_height <= height * frame_width {
+        (width, (width * frame_height / frame_width).max(1))
+    } else {
+        ((height * frame_width / frame_height).max(1), height)
+    };
+    Viewport {
+        x: (width - fit_width) / 2,
+        y: (height - fit_height) / 2,
+        width: fit_width,
+        height: fit_height,
+    }
+}
+
+impl FilterPipeline {
+    // renders `frame` into `output`, a `width` x `height` image
+    pub fn apply(&self, frame: &Frame, output: &mut Vec<u32>, width: usize, height: usize) {
+        output.clear();
+        output.resize(width * height, 0);
+        if width == 0 || height == 0 || frame.width == 0 || frame.height == 0 {
+            return;
+        }
+        let viewport = fit(frame.width, frame.height, width, height);
+
+        let doubled: Vec<u32>;
+        let source = match self.scaler {
+            Scaler::Scale2x => {
+                let mut pixels = frame.pixels.to_vec();
+                let (mut source_width, mut source_height) = (frame.width, frame.height);
+                // double while the output still has room for it
+                while source_width * 2 <= viewport.width && source_height * 2 <= viewport.height {
+                    pixels = scale2x(&pixels, source_width, source_height);
+                    source_width *= 2;
+                    source_height *= 2;
+                }
+                doubled = pixels;
+                Frame { width: source_width, height: source_height, pixels: &doubled }
+            },
+            _ => Frame { width: frame.width, height: frame.height, pixels: frame.pixels },
+        };
+
+        for y in 0..viewport.height {
+            let row = (viewport.y + y) * width + viewport.x;
+            for x in 0..viewport.width {
+                let color = match self.scaler {
+                    Scaler::Bilinear => bilinear(&source, x, y, viewport),
+                    _ => source.pixel(x * source.width / viewport.width, y * source.height / viewport.height),
+                };
+                output[row + x] = self.effects(color, frame, x, y, viewport);
+            }
+        }
+    }
+
+    fn effects(&self, color: u32, frame: &Frame, x: usize, y: usize, viewport: Viewport) -> u32 {
+        // position inside the display pixel, in 1/256ths
+        let sub_x = (x * frame.width * 256 / viewport.width) % 256;
+        let sub_y = (y * frame.height * 256 / viewport.height) % 256;
+        // effects need a few output pixels per display pixel to be visible
+        let pixel_size = viewport.height / frame.height;
+
+        let mut color = color;
+        if self.scanlines && pixel_size >= 2 && sub_y >= 128 {
+            color = darken(color, 2);
+        }
+        if self.grid && pixel_size >= 3 {
+            let edge = 256 / pixel_size;
+            if sub_x < edge || sub_y < edge {
+                color = darken(color, 3);
+            }
+        }
+        color
+    }
+}
+
+// keeps `quarters`/4 of the brightness of every channel
+fn darken(color: u32, quarters: u32) -> u32 {
+    let channel = |shift: u32| (((color >> shift) & 0xFF) * quarters / 4) << shift;
+    channel(16) | channel(8) | channel(0)
+}
+
+fn bilinear(source: &Frame, x: usize, y: usize, viewport: Viewport) -> u32 {
+    // sample at the center of the output pixel, in source pixel coordinates
+    let fx = ((x as f32 + 0.5) * source.width as f32 / viewport.width as f32 - 0.5).max(0.0);
+    let fy = ((y as f32 + 0.5) * source.height as f32 / viewport.height as f32 - 0.5).max(0.0);
+    let x0 = (fx as usize).min(source.width - 1);
+    let y0 = (fy as usize).min(source.height - 1);
+    let x1 = (x0 + 1).min(source.width - 1);
+    let y1 = (y0 + 1).min(source.height - 1);
+    let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+
+    let channel = |shift: u32| {
+        let sample = |px: usize, py: usize| ((source.pixel(px, py) >> shift) & 0xFF) as f32;
+        let top = sample(x0, y0) * (1.0 - tx) + sample(x1, y0) * tx;
+        let bottom = sample(x0, y1) * (1.0 - tx) + sample(x1, y1) * tx;
+        ((top * (1.0 - ty) + bottom * ty).round() as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+// EPX: every pixel becomes 2x2, a corner takes the color of its two neighbours
+// when they agree and the other two neighbours differ
+fn scale2x(pixels: &[u32], width: usize, height: usize) -> Vec<u32> {
+    let mut output = vec![0; width * height * 4];
+    let at = |x: isize, y: isize| {
+        let x = x.clamp(0, width as isize - 1) as usize;
+        let y = y.clamp(0, height as isize - 1) as usize;
+        pixels[y * width + x]
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let (ix, iy) = (x as isize, y as isize);
+            let p = at(ix, iy);
+            let a = at(ix, iy - 1);
+            let b = at(ix + 1, iy);
+            let c = at(ix - 1, iy);
+            let d = at(ix, iy + 1);
+
+            let mut corners = [p; 4];
+            if c == a && c != d && a != b {
+                corners[0] = a;
+            }
+            if a == b && a != c && b != d {
+                corners[1] = b;
+            }
+            if d == c && d != b && c != a {
+                corners[2] = c;
+            }
+            if b == d && b != a && d != c {
+                corners[3] = d;
+            }
+
+            let row = y * 2 * width * 2;
+            output[row + x * 2] = corners[0];
+            output[row + x * 2 + 1] = corners[1];
+            output[row + width * 2 + x * 2] = corners[2];
+            output[row + width * 2 + x * 2 + 1] = corners[3];
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: u32 = 0xFFFFFF;
+    const B: u32 = 0x000000;
+
+    #[test]
+    fn the_viewport_keeps_the_aspect_ratio_and_is_centered() {
+        assert_eq!(fit(64, 32, 640, 480), Viewport { x: 0, y: 80, width: 640, height: 320 });
+        assert_eq!(fit(64, 32, 300, 100), Viewport { x: 50, y: 0, width: 200, height: 100 });
+        assert_eq!(fit(64, 32, 128, 64), Viewport { x: 0, y: 0, width: 128, height: 64 });
+        // never smaller than a pixel
+        assert_eq!(fit(64, 32, 1, 1), Viewport { x: 0, y: 0, width: 1, height: 1 });
+    }
+
+    #[test]
+    fn scale2x_smooths_diagonals() {
+        let doubled = scale2x(&[W, B, B, W], 2, 2);
+        assert_eq!(&doubled[..4], [W, W, B, B]);
+        assert_eq!(&doubled[4..8], [W, B, W, B]);
+        assert_eq!(scale2x(&[W; 4], 2, 2), vec![W; 16]);
+    }
+
+    #[test]
+    fn bilinear_blends_neighbouring_pixels() {
+        let source = Frame { width: 2, height: 1, pixels: &[B, W] };
+        let viewport = Viewport { x: 0, y: 0, width: 4, height: 1 };
+        let row: Vec<u32> = (0..4).map(|x| bilinear(&source, x, 0, viewport)).collect();
+        assert_eq!(row, [0x000000, 0x404040, 0xBFBFBF, 0xFFFFFF]);
+    }
+
+    #[test]
+    fn nearest_scaling_leaves_black_bars() {
+        let frame = Frame { width: 2, height: 1, pixels: &[0x112233, 0x445566] };
+        let mut output = Vec::new();
+        FilterPipeline::default().apply(&frame, &mut output, 4, 4);
+        assert_eq!(&output[..4], [B; 4]);
+        assert_eq!(&output[4..8], [0x112233, 0x112233, 0x445566, 0x445566]);
+        assert_eq!(&output[8..12], [0x112233, 0x112233, 0x445566, 0x445566]);
+        assert_eq!(&output[12..], [B; 4]);
+    }
+
+    #[test]
+    fn scanlines_and_grid_darken_parts_of_each_display_pixel() {
+        let frame = Frame { width: 1, height: 2, pixels: &[W, W] };
+        let mut output = Vec::new();
+        let scanlines = FilterPipeline { scanlines: true, ..FilterPipeline::default() };
+        scanlines.apply(&frame, &mut output, 2, 4);
+        assert_eq!(output, [W, W, 0x7F7F7F, 0x7F7F7F, W, W, 0x7F7F7F, 0x7F7F7F]);
+
+        let frame = Frame { width: 1, height: 1, pixels: &[W] };
+        let grid = FilterPipeline { grid: true, ..FilterPipeline::default() };
+        grid.apply(&frame, &mut output, 4, 4);
+        assert_eq!(&output[..4], [0xBFBFBF; 4]);
+        assert_eq!(&output[4..8], [0xBFBFBF, W, W, W]);
+
+        // too small an output to show the effects
+        grid.apply(&frame, &mut output, 2, 2);
+        assert_eq!(output, [W; 4]);
+    }
+
+    #[test]
+    fn scaler_names() {
+        assert_eq!(Scaler::parse("epx"), Some(Scaler::Scale2x));
+        assert_eq!(Scaler::parse("bilinear"), Some(Scaler::Bilinear));
+        assert_eq!(Scaler::parse("hq2x"), None);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::renderer::Frame;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_DIRECTORY: &str = "screenshots";
+
+// writes 0RGB pixels as an 8 bit RGB PNG
+pub fn write_png<W: Write>(writer: W, frame: &Frame) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, frame.width as u32, frame.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+
+    let mut data = Vec::with_capacity(frame.pixels.len() * 3);
+    for color in frame.pixels {
+        data.extend_from_slice(&[(color >> 16) as u8, (color >> 8) as u8, *color as u8]);
+    }
+    writer.write_image_data(&data).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+pub fn save_png<P: AsRef<Path>>(path: P, frame: &Frame) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    write_png(file, frame)
+}
+
+// <directory>/<rom name>-<UTC timestamp>.png, with a counter appended if that
+// file already exists. The directory is created if needed
+pub fn next_path<P: AsRef<Path>>(directory: P, rom_path: &str, extension: &str) -> io::Result<PathBuf> {
+    let directory = directory.as_ref();
+    fs::create_dir_all(directory)?;
+
+    let rom_name = Path::new(rom_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("chip8"));
+    let stem = format!("{}-{}", rom_name, timestamp());
+
+    let mut path = directory.join(format!("{}.{}", stem, extension));
+    let mut counter = 1;
+    while path.exists() {
+        counter += 1;
+        path = directory.join(format!("{}-{}.{}", stem, counter, extension));
+    }
+    Ok(path)
+}
+
+// current UTC time as YYYYMMDD-HHMMSS
+pub fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+// days since 1970-01-01 to a proleptic Gregorian date, Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+    use crate::palette::Palette;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chip8-screenshot-test-{}-{}", std::process::id(), name))
+    }
+
+    // the size and 0RGB pixels of a PNG file
+    fn read_png(path: &Path) -> (u32, u32, Vec<u32>) {
+        let mut reader = png::Decoder::new(File::open(path).unwrap()).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.color_type, info.bit_depth), (png::ColorType::Rgb, png::BitDepth::Eight));
+        let pixels = data[..info.buffer_size()]
+            .chunks(3)
+            .map(|rgb| (rgb[0] as u32) << 16 | (rgb[1] as u32) << 8 | rgb[2] as u32)
+            .collect();
+        (info.width, info.height, pixels)
+    }
+
+    #[test]
+    fn pngs_keep_the_pixels() {
+        let path = temp_path("pixels.png");
+        let pixels = [0x123456, 0xFFFFFF, 0x000000, 0xFF0080, 0x00FF00, 0x0000FF];
+        save_png(&path, &Frame { width: 3, height: 2, pixels: &pixels }).unwrap();
+        let png = read_png(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(png, (3, 2, pixels.to_vec()));
+    }
+
+    #[test]
+    fn screenshots_scale_the_display() {
+        let mut chip8 = Chip8::new();
+        // draws the 0 of the font at 0,0, its top row is 4 pixels wide
+        chip8.load_rom(&[0xF0, 0x29, 0xD0, 0x05, 0x12, 0x04]).unwrap();
+        chip8.run_frame();
+        let colors = Palette::default().colors;
+
+        for scale in [1, 3] {
+            let path = temp_path(&format!("scale-{}.png", scale));
+            chip8.save_screenshot(&path, scale).unwrap();
+            let (width, height, pixels) = read_png(&path);
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!((width, height), (64 * scale as u32, 32 * scale as u32));
+            let pixel = |x: usize, y: usize| pixels[y * width as usize + x];
+            assert_eq!(pixel(0, 0), colors[1]);
+            assert_eq!(pixel(4 * scale - 1, scale - 1), colors[1]);
+            assert_eq!(pixel(4 * scale, 0), colors[0]);
+            assert_eq!(pixel(scale, scale), colors[0]);
+        }
+    }
+
+    #[test]
+    fn days_since_the_epoch_are_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(10_957), (2000, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(19_783), (2024, 3, 1));
+    }
+
+    #[test]
+    fn taken_paths_get_a_counter() {
+        let directory = temp_path("shots");
+        // the timestamp can tick between the two calls, then there is no collision to count
+        let (first, second) = loop {
+            let first = next_path(&directory, "roms/pong.ch8", "png").unwrap();
+            File::create(&first).unwrap();
+            let second = next_path(&directory, "roms/pong.ch8", "png").unwrap();
+            if second.to_string_lossy().starts_with(&*first.with_extension("").to_string_lossy()) {
+                break (first, second);
+            }
+        };
+        fs::remove_dir_all(&directory).unwrap();
+
+        let name = first.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("pong-") && name.ends_with(".png"));
+        assert_eq!(second, first.with_file_name(name.replace(".png", "-2.png")));
+    }
+}
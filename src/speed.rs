@@ -0,0 +1,236 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Pacing of emulated frames for the frontend: the 60Hz of the original
+// hardware, slow motion, unlimited fast-forward, pausing and frame advance.
+
+pub const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Speed {
+    Quarter,
+    Half,
+    #[default]
+    Normal,
+    // as fast as the host runs
+    Unlimited,
+}
+
+impl Speed {
+    // "25", "50", "100" or "max"
+    pub fn parse(name: &str) -> Option<Speed> {
+        let speed = match name.trim_end_matches('%') {
+            "25" => Speed::Quarter,
+            "50" => Speed::Half,
+            "100" => Speed::Normal,
+            "max" => Speed::Unlimited,
+            _ => return None,
+        };
+        Some(speed)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Speed::Quarter => "25%",
+            Speed::Half => "50%",
+            Speed::Normal => "100%",
+            Speed::Unlimited => "unlimited",
+        }
+    }
+
+    // None for unlimited speed
+    pub fn frame_duration(&self) -> Option<Duration> {
+        match self {
+            Speed::Quarter => Some(FRAME_DURATION * 4),
+            Speed::Half => Some(FRAME_DURATION * 2),
+            Speed::Normal => Some(FRAME_DURATION),
+            Speed::Unlimited => None,
+        }
+    }
+
+    // cycles 100% -> 50% -> 25% -> 100%
+    pub fn slower(&self) -> Speed {
+        match self {
+            Speed::Normal | Speed::Unlimited => Speed::Half,
+            Speed::Half => Speed::Quarter,
+            Speed::Quarter => Speed::Normal,
+        }
+    }
+}
+
+// Decides when the next emulated frame runs. The frontend loop polls for input
+// at 60Hz even while paused or in slow motion
+pub struct FrameClock {
+    speed: Speed,
+    paused: bool,
+    // one frame was requested while paused
+    advance: bool,
+    next_poll: Instant,
+    next_frame: Instant,
+    last_present: Instant,
+}
+
+impl FrameClock {
+    pub fn new(speed: Speed) -> FrameClock {
+        let now = Instant::now();
+        FrameClock {
+            speed,
+            paused: false,
+            advance: false,
+            next_poll: now,
+            next_frame: now,
+            last_present: now,
+        }
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.next_frame = Instant::now();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.advance = false;
+        self.next_frame = Instant::now();
+    }
+
+    // pauses if running, then runs exactly one frame
+    pub fn advance(&mut self) {
+        self.paused = true;
+        self.advance = true;
+    }
+
+    // true if an emulated frame is due
+    pub fn should_run(&mut self) -> bool {
+        if self.paused {
+            return std::mem::take(&mut self.advance);
+        }
+        match self.speed.frame_duration() {
+            None => true,
+            Some(duration) => {
+                let now = Instant::now();
+                if now < self.next_frame {
+                    return false;
+                }
+                self.next_frame += duration;
+                // a late frame does not cause a burst of frames to catch up
+                if self.next_frame < now {
+                    self.next_frame = now;
+                }
+                true
+            },
+        }
+    }
+
+    // frames are presented at most at 60Hz, so fast-forward is not limited by
+    // the renderer
+    pub fn should_present(&mut self) -> bool {
+        let now = Instant::now();
+        if self.speed == Speed::Unlimited && !self.paused && now < self.last_present + FRAME_DURATION {
+            return false;
+        }
+        self.last_present = now;
+        true
+    }
+
+    // sleeps until the next poll, unless frames run at unlimited speed
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if self.speed == Speed::Unlimited && !self.paused {
+            self.next_poll = now;
+            return;
+        }
+        self.next_poll += FRAME_DURATION;
+        if self.next_poll > now {
+            thread::sleep(self.next_poll - now);
+        } else {
+            self.next_poll = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speeds_parse_with_or_without_percent() {
+        assert_eq!(Speed::parse("50%"), Some(Speed::Half));
+        assert_eq!(Speed::parse("max"), Some(Speed::Unlimited));
+        assert_eq!(Speed::parse("200"), None);
+        assert_eq!(Speed::Unlimited.slower(), Speed::Half);
+        assert_eq!(Speed::Quarter.slower(), Speed::Normal);
+        assert_eq!(Speed::Quarter.frame_duration(), Some(FRAME_DURATION * 4));
+    }
+
+    #[test]
+    fn frames_run_once_per_frame_duration() {
+        let mut clock = FrameClock::new(Speed::Normal);
+        assert!(clock.should_run());
+        assert!(!clock.should_run());
+
+        clock.next_frame = Instant::now() - Duration::from_millis(1);
+        assert!(clock.should_run());
+        assert!(!clock.should_run());
+    }
+
+    #[test]
+    fn a_late_clock_catches_up_one_frame_at_most() {
+        let mut clock = FrameClock::new(Speed::Normal);
+        clock.next_frame = Instant::now() - Duration::from_secs(1);
+        assert!(clock.should_run());
+        assert!(clock.should_run());
+        assert!(!clock.should_run());
+    }
+
+    #[test]
+    fn paused_clocks_only_run_advanced_frames() {
+        let mut clock = FrameClock::new(Speed::Unlimited);
+        clock.set_paused(true);
+        assert!(!clock.should_run());
+
+        clock.advance();
+        assert!(clock.should_run());
+        assert!(!clock.should_run());
+        assert!(clock.is_paused());
+
+        // advancing a running clock pauses it after the frame
+        clock.set_paused(false);
+        assert!(clock.should_run());
+        clock.advance();
+        assert!(clock.is_paused());
+        assert!(clock.should_run());
+        assert!(!clock.should_run());
+
+        // an advance not run yet is dropped on resume
+        clock.advance();
+        clock.set_paused(false);
+        clock.set_paused(true);
+        assert!(!clock.should_run());
+    }
+
+    #[test]
+    fn fast_forward_presents_at_60hz_at_most() {
+        let mut clock = FrameClock::new(Speed::Unlimited);
+        assert!(clock.should_run());
+        assert!(clock.should_run());
+        assert!(!clock.should_present());
+
+        clock.last_present = Instant::now() - FRAME_DURATION;
+        assert!(clock.should_present());
+        assert!(!clock.should_present());
+
+        // paused or at normal speed every frame is presented
+        clock.set_paused(true);
+        assert!(clock.should_present());
+        assert!(clock.should_present());
+    }
+}
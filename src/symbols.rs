@@ -0,0 +1,270 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Symbol files map the addresses of an assembled rom back to its source, so
+// debuggers can set breakpoints on source lines and show where the program is,
+// and disassembly can name addresses (CALL draw_paddle instead of CALL 0x2A4):
+//
+//     chip8-symbols 1
+//     label 0x200 main
+//     line 0x200 12 pong.8o
+//     line 0x202 13 pong.8o
+//     label 0x2A4 draw_paddle
+//
+// A line entry is the address of the first instruction assembled from a source
+// line, the line number and the source file, relative to the symbol file. A
+// label entry names an address. The symbol file of a rom sits next to it with
+// the .sym extension. Assemblers write it, the Octo assembler of this crate
+// builds the symbols of cartridges with add_label and add_line.
+
+const MAGIC: &str = "chip8-symbols 1";
+pub const EXTENSION: &str = "sym";
+
+#[derive(Debug)]
+pub enum SymbolError {
+    Io(io::Error),
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolError::Io(err) => write!(f, "failed to read symbols: {}", err),
+            SymbolError::Invalid { line, message } => write!(f, "invalid symbols at line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl From<io::Error> for SymbolError {
+    fn from(err: io::Error) -> Self {
+        SymbolError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub path: PathBuf,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    lines: BTreeMap<u16, SourceLine>,
+    labels: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Symbols, SymbolError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        Symbols::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    // the symbol file next to `rom_path`, if there is one
+    pub fn load_for_rom<P: AsRef<Path>>(rom_path: P) -> Option<Result<Symbols, SymbolError>> {
+        let path = rom_path.as_ref().with_extension(EXTENSION);
+        path.is_file().then(|| Symbols::load(path))
+    }
+
+    // source paths are relative to `directory`
+    pub fn parse(text: &str, directory: &Path) -> Result<Symbols, SymbolError> {
+        let invalid = |line: usize, message: &str| SymbolError::Invalid { line, message: String::from(message) };
+        let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+        match lines.next() {
+            Some((_, MAGIC)) => {},
+            _ => return Err(invalid(1, "not a chip8 symbol file")),
+        }
+
+        let mut symbols = Symbols::default();
+        for (number, line) in lines {
+            let mut fields = line.splitn(4, ' ');
+            let kind = fields.next();
+            if kind != Some("line") && kind != Some("label") {
+                // blank lines, comments and entries newer versions may add
+                continue;
+            }
+            let address = fields.next().and_then(parse_address).ok_or_else(|| invalid(number, "bad address"))?;
+            if kind == Some("label") {
+                let name = fields.next().filter(|name| !name.is_empty() && fields.next().is_none());
+                let name = name.ok_or_else(|| invalid(number, "labels are one word"))?;
+                symbols.labels.insert(address, String::from(name));
+                continue;
+            }
+            let line = fields.next().and_then(|line| line.parse().ok());
+            let line = line.ok_or_else(|| invalid(number, "line must be a number"))?;
+            let path = fields.next().filter(|path| !path.is_empty());
+            let path = path.ok_or_else(|| invalid(number, "missing source"))?;
+            symbols.lines.insert(address, SourceLine { path: directory.join(path), line });
+        }
+        Ok(symbols)
+    }
+
+    pub fn add_label(&mut self, address: u16, name: &str) {
+        self.labels.insert(address, String::from(name));
+    }
+
+    pub fn add_line(&mut self, address: u16, path: PathBuf, line: u32) {
+        self.lines.insert(address, SourceLine { path, line });
+    }
+
+    // writes the symbols for a file in `directory`, so parse with the same
+    // directory reads them back. Sources outside of it keep their full path
+    pub fn write<W: Write>(&self, writer: &mut W, directory: &Path) -> io::Result<()> {
+        writeln!(writer, "{}", MAGIC)?;
+        let addresses: BTreeSet<u16> = self.labels.keys().chain(self.lines.keys()).copied().collect();
+        for address in addresses {
+            if let Some(name) = self.labels.get(&address) {
+                writeln!(writer, "label {:#05X} {}", address, name)?;
+            }
+            if let Some(source) = self.lines.get(&address) {
+                let path = source.path.strip_prefix(directory).unwrap_or(&source.path);
+                writeln!(writer, "line {:#05X} {} {}", address, source.line, path.display())?;
+            }
+        }
+        Ok(())
+    }
+
+    // the source line `address` was assembled from
+    pub fn source_line(&self, address: u16) -> Option<&SourceLine> {
+        self.lines.get(&address)
+    }
+
+    // the name of exactly `address`
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    pub fn address_of_label(&self, name: &str) -> Option<u16> {
+        self.labels.iter().find(|(_, label)| *label == name).map(|(address, _)| *address)
+    }
+
+    // `address` relative to the closest label before it, like draw_paddle+0x4
+    pub fn describe(&self, address: u16) -> Option<String> {
+        let (start, label) = self.labels.range(..=address).next_back()?;
+        Some(match address - start {
+            0 => label.clone(),
+            offset => format!("{}+{:#X}", label, offset),
+        })
+    }
+
+    // the first address assembled from `line` of `path`, or from the next line
+    // after it with code, for breakpoints on blank lines and comments. Returns
+    // the address and the line it belongs to
+    pub fn address_of_line(&self, path: &Path, line: u32) -> Option<(u16, u32)> {
+        self.lines
+            .iter()
+            .filter(|(_, source)| source.line >= line && same_file(&source.path, path))
+            .min_by_key(|(address, source)| (source.line, **address))
+            .map(|(address, source)| (*address, source.line))
+    }
+}
+
+// addresses are hex with a 0x prefix, or decimal
+pub fn parse_address(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// debuggers send absolute paths, symbol files may not resolve to the same text
+fn same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_name_addresses() {
+        let text = "chip8-symbols 1\nlabel 0x200 main\nline 0x200 3 pong.8o\nlabel 0x2A4 draw_paddle\n";
+        let symbols = Symbols::parse(text, Path::new("roms")).unwrap();
+        assert_eq!(symbols.label(0x2A4), Some("draw_paddle"));
+        assert_eq!(symbols.address_of_label("main"), Some(0x200));
+        assert_eq!(symbols.describe(0x2A8).as_deref(), Some("draw_paddle+0x4"));
+        assert_eq!(symbols.describe(0x100), None);
+        assert_eq!(symbols.source_line(0x200).map(|source| source.line), Some(3));
+        assert!(Symbols::parse("chip8-symbols 1\nlabel 0x200 two words\n", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn lines_resolve_to_the_next_line_with_code() {
+        let text = "chip8-symbols 1\n\nline 0x204 9 pong.8o\nline 0x200 4 pong.8o\nline 0x202 4 pong.8o\n\
+                    line 0x300 5 lib.8o\n";
+        let symbols = Symbols::parse(text, Path::new("roms")).unwrap();
+        let pong = Path::new("roms/pong.8o");
+        assert_eq!(symbols.address_of_line(pong, 4), Some((0x200, 4)));
+        assert_eq!(symbols.address_of_line(pong, 5), Some((0x204, 9)));
+        assert_eq!(symbols.address_of_line(pong, 10), None);
+        assert_eq!(symbols.address_of_line(Path::new("roms/lib.8o"), 1), Some((0x300, 5)));
+        assert_eq!(symbols.address_of_line(Path::new("pong.8o"), 4), None);
+    }
+
+    #[test]
+    fn bad_entries_name_their_line() {
+        let parse = |text: &str| match Symbols::parse(text, Path::new("")) {
+            Err(SymbolError::Invalid { line, .. }) => Some(line),
+            _ => None,
+        };
+        assert_eq!(parse("chip8-symbols 2\n"), Some(1));
+        assert_eq!(parse("chip8-symbols 1\nlabel 0x10000 main\n"), Some(2));
+        assert_eq!(parse("chip8-symbols 1\n# comment\nline 0x200 x pong.8o\n"), Some(3));
+        assert_eq!(parse("chip8-symbols 1\nline 0x200 3\n"), Some(2));
+        // kinds this version does not know are skipped
+        assert!(Symbols::parse("chip8-symbols 1\nfile pong.8o\n", Path::new("")).is_ok());
+    }
+
+    #[test]
+    fn addresses_are_hex_or_decimal() {
+        assert_eq!(parse_address("0x2A4"), Some(0x2A4));
+        assert_eq!(parse_address("0X2a4"), Some(0x2A4));
+        assert_eq!(parse_address("512"), Some(0x200));
+        assert_eq!(parse_address("2A4"), None);
+        assert_eq!(parse_address("0x10000"), None);
+    }
+
+    #[test]
+    fn roms_find_the_symbol_file_next_to_them() {
+        let directory = std::env::temp_dir();
+        let rom = directory.join(format!("chip8-symbols-test-{}.ch8", std::process::id()));
+        assert!(Symbols::load_for_rom(&rom).is_none());
+
+        let path = rom.with_extension(EXTENSION);
+        fs::write(&path, "chip8-symbols 1\nline 0x200 1 game.8o\n").unwrap();
+        let loaded = Symbols::load_for_rom(&rom);
+        fs::remove_file(&path).unwrap();
+
+        let symbols = loaded.unwrap().unwrap();
+        assert_eq!(symbols.source_line(0x200).unwrap().path, directory.join("game.8o"));
+    }
+
+    #[test]
+    fn written_symbols_parse_back() {
+        let directory = Path::new("roms");
+        let mut symbols = Symbols::default();
+        symbols.add_label(0x200, "main");
+        symbols.add_line(0x200, directory.join("pong.8o"), 12);
+        symbols.add_line(0x202, directory.join("lib/draw.8o"), 3);
+        symbols.add_label(0x2A4, "draw_paddle");
+        symbols.add_line(0x2A4, PathBuf::from("/src/other file.8o"), 1);
+
+        let mut text = Vec::new();
+        symbols.write(&mut text, directory).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("chip8-symbols 1\nlabel 0x200 main\nline 0x200 12 pong.8o\n"));
+        assert!(text.contains("\nline 0x202 3 lib/draw.8o\nlabel 0x2A4 draw_paddle\n"));
+        assert_eq!(Symbols::parse(&text, directory).unwrap(), symbols);
+    }
+}
@@ -0,0 +1,130 @@
+// Approximate instruction timing of the CHIP-8 interpreter on the COSMAC VIP,
+// after Laurence Scotford's disassembly of the original RCA 1802 interpreter.
+// Costs are in 1802 machine cycles (8 clocks at 1.7609MHz, about 4.54us).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimingMode {
+    // a fixed number of instructions (the tickrate) per frame
+    #[default]
+    Fixed,
+    // as many instructions as fit in the machine cycles of a VIP frame
+    CosmacVip,
+}
+
+impl TimingMode {
+    // "fixed" or "vip"
+    pub fn parse(name: &str) -> Option<TimingMode> {
+        match name {
+            "fixed" => Some(TimingMode::Fixed),
+            "vip" => Some(TimingMode::CosmacVip),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimingMode::Fixed => "fixed",
+            TimingMode::CosmacVip => "vip",
+        }
+    }
+}
+
+// machine cycles between two 60Hz display interrupts
+pub const VIP_FRAME_CYCLES: u32 = 3668;
+// cycles taken from the interpreter by the display interrupt routine and the
+// 1861's DMA of 128 lines of 8 bytes
+pub const VIP_DISPLAY_CYCLES: u32 = 1070;
+// the fetch and decode loop the interpreter runs for every instruction
+const FETCH_CYCLES: u32 = 40;
+
+pub fn vip_cycles_per_frame() -> u32 {
+    VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES
+}
+
+// `vx` is the value of Reg VX, needed for the opcodes whose cost depends on it
+pub fn vip_instruction_cycles(instruction: u16, vx: u8) -> u32 {
+    let x = (instruction & 0x0F00) >> 8;
+    let n = (instruction & 0x000F) as u32;
+    let nn = instruction & 0x00FF;
+
+    let execute = match (instruction & 0xF000) >> 12 {
+        0x0 => match nn {
+            // clearing writes all 256 bytes of display memory
+            0xE0 => 24 + 256 * 12,
+            0xEE => 10,
+            _ => 12,
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 | 0x4 => 10,
+        0x5 | 0x9 => 14,
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => 44,
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        // each row is shifted into place bit by bit, and a sprite that is not
+        // byte aligned touches two bytes of display memory per row
+        0xD => {
+            let shift = (vx % 8) as u32;
+            let bytes_per_row = if shift == 0 { 1 } else { 2 };
+            68 + n * (46 + 20 * shift + 12 * bytes_per_row)
+        },
+        0xE => 14,
+        0xF => match nn {
+            0x07 | 0x15 | 0x18 => 10,
+            0x0A => 8,
+            0x1E | 0x29 => 16,
+            // one subtraction loop per decimal digit value
+            0x33 => 84 + 16 * ((vx / 100) + (vx / 10 % 10) + (vx % 10)) as u32,
+            0x55 | 0x65 => 14 + 14 * (x as u32 + 1),
+            _ => 10,
+        },
+        _ => 10,
+    };
+
+    FETCH_CYCLES + execute
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_instruction_pays_for_the_fetch() {
+        assert_eq!(vip_instruction_cycles(0x1200, 0), FETCH_CYCLES + 12);
+        assert_eq!(vip_instruction_cycles(0x6A05, 0), FETCH_CYCLES + 6);
+        assert_eq!(vip_instruction_cycles(0x00E0, 0), FETCH_CYCLES + 24 + 256 * 12);
+        assert_eq!(vip_instruction_cycles(0x00EE, 0), FETCH_CYCLES + 10);
+    }
+
+    #[test]
+    fn sprites_cost_more_when_not_byte_aligned() {
+        assert_eq!(vip_instruction_cycles(0xD015, 8), FETCH_CYCLES + 68 + 5 * (46 + 12));
+        assert_eq!(vip_instruction_cycles(0xD015, 3), FETCH_CYCLES + 68 + 5 * (46 + 60 + 24));
+        assert_eq!(vip_instruction_cycles(0xD010, 3), FETCH_CYCLES + 68);
+    }
+
+    #[test]
+    fn costs_that_depend_on_the_registers() {
+        // 1 + 2 + 3 subtraction loops for 123
+        assert_eq!(vip_instruction_cycles(0xF033, 123), FETCH_CYCLES + 84 + 16 * 6);
+        assert_eq!(vip_instruction_cycles(0xF033, 0), FETCH_CYCLES + 84);
+        assert_eq!(vip_instruction_cycles(0xF055, 0), FETCH_CYCLES + 14 + 14);
+        assert_eq!(vip_instruction_cycles(0xFF65, 0), FETCH_CYCLES + 14 + 14 * 16);
+    }
+
+    #[test]
+    fn the_display_takes_its_share_of_the_frame() {
+        assert_eq!(vip_cycles_per_frame(), 2598);
+    }
+
+    #[test]
+    fn timing_modes_parse_their_names() {
+        for mode in [TimingMode::Fixed, TimingMode::CosmacVip] {
+            assert_eq!(TimingMode::parse(mode.name()), Some(mode));
+        }
+        assert_eq!(TimingMode::parse("cosmac"), None);
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// Notices when a file changes on disk by polling its modification time, so a
+// rom can be reloaded after every assemble
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    next_check: Instant,
+}
+
+impl FileWatcher {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileWatcher {
+        let path = path.into();
+        let modified = modified(&path);
+        FileWatcher {
+            path,
+            modified,
+            next_check: Instant::now() + CHECK_INTERVAL,
+        }
+    }
+
+    // true once for every change, checked at most every CHECK_INTERVAL. A file
+    // that disappears while it is rewritten counts as changed when it is back
+    pub fn has_changed(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next_check {
+            return false;
+        }
+        self.next_check = now + CHECK_INTERVAL;
+
+        let modified = modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    // writes `data` with a modification time `seconds` after the epoch
+    fn write(path: &Path, data: &[u8], seconds: u64) {
+        fs::write(path, data).unwrap();
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+    }
+
+    // skips the wait for the next check
+    fn check(watcher: &mut FileWatcher) -> bool {
+        watcher.next_check = Instant::now();
+        watcher.has_changed()
+    }
+
+    #[test]
+    fn rewrites_are_changes_once() {
+        let path = std::env::temp_dir().join(format!("chip8-watch-test-{}.ch8", std::process::id()));
+        write(&path, &[0x12, 0x00], 1_000_000);
+        let mut watcher = FileWatcher::new(&path);
+        assert!(!check(&mut watcher));
+
+        write(&path, &[0x12, 0x02], 1_000_010);
+        // not checked again before the interval
+        assert!(!watcher.has_changed());
+        assert!(check(&mut watcher));
+        assert!(!check(&mut watcher));
+
+        // a file that is gone is not a change until it is back
+        fs::remove_file(&path).unwrap();
+        assert!(!check(&mut watcher));
+        write(&path, &[0x12, 0x04], 1_000_020);
+        let changed = check(&mut watcher);
+        fs::remove_file(&path).unwrap();
+        assert!(changed);
+    }
+}